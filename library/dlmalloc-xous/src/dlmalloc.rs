@@ -36,7 +36,7 @@ unsafe impl<A: Send> Send for Dlmalloc<A> {}
 
 // TODO: runtime configurable? documentation?
 const DEFAULT_GRANULARITY: usize = 64 * 1024;
-const DEFAULT_TRIM_THRESHOLD: usize = 2 * 1024 * 1024;
+pub const DEFAULT_TRIM_THRESHOLD: usize = 2 * 1024 * 1024;
 const MAX_RELEASE_CHECK_RATE: usize = 4095;
 
 #[repr(C)]
@@ -570,7 +570,9 @@ pub unsafe fn memalign(&mut self, mut alignment: usize, bytes: usize) -> *mut u8
         if alignment < self.min_chunk_size() {
             alignment = self.min_chunk_size();
         }
-        if bytes >= self.max_request() - alignment {
+        // Very large alignments (anything past a page is possible) would
+        // otherwise overflow the padded request below.
+        if alignment >= self.max_request() || bytes >= self.max_request() - alignment {
             return ptr::null_mut();
         }
         let nb = self.request2size(bytes);
@@ -700,7 +702,7 @@ unsafe fn init_top(&mut self, ptr: *mut Chunk, size: usize) {
         self.topsize = size;
         (*p).head = size | PINUSE;
         (*Chunk::plus_offset(p, size)).head = self.top_foot_size();
-        self.trim_check = DEFAULT_TRIM_THRESHOLD;
+        self.trim_check = self.system_allocator.trim_threshold();
     }
 
     unsafe fn init_bins(&mut self) {
@@ -1203,7 +1205,8 @@ pub unsafe fn free(&mut self, mem: *mut u8) {
                     self.dvsize = 0;
                 }
                 if self.should_trim(tsize) {
-                    self.sys_trim(0);
+                    let pad = self.system_allocator.trim_pad();
+                    self.sys_trim(pad);
                 }
                 return;
             } else if next == self.dv {
@@ -1239,6 +1242,22 @@ pub unsafe fn free(&mut self, mem: *mut u8) {
         }
     }
 
+    /// Returns the number of bytes currently obtained from the system.
+    pub fn footprint(&self) -> usize {
+        self.footprint
+    }
+
+    /// Returns the largest number of bytes ever obtained from the system.
+    pub fn max_footprint(&self) -> usize {
+        self.max_footprint
+    }
+
+    /// Returns unused memory at the top of the heap to the system, keeping
+    /// `pad` bytes of it. Returns whether anything was released.
+    pub unsafe fn trim(&mut self, pad: usize) -> bool {
+        self.sys_trim(pad)
+    }
+
     fn should_trim(&self, size: usize) -> bool {
         size > self.trim_check
     }
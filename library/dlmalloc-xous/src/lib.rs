@@ -63,6 +63,19 @@ pub unsafe trait Allocator: Send {
 
     /// Returns the page size. Must be a power of two
     fn page_size(&self) -> usize;
+
+    /// Returns how much unused memory may collect at the top of the heap
+    /// before the allocator tries to release it with `free_part`.
+    fn trim_threshold(&self) -> usize {
+        dlmalloc::DEFAULT_TRIM_THRESHOLD
+    }
+
+    /// Returns how much of the unused memory at the top of the heap to keep
+    /// when trimming, so that a program whose memory use goes back up right
+    /// away doesn't immediately have to ask for it again.
+    fn trim_pad(&self) -> usize {
+        0
+    }
 }
 
 /// An allocator instance
@@ -145,6 +158,31 @@ pub unsafe fn free(&mut self, ptr: *mut u8, size: usize, align: usize) {
         self.0.free(ptr)
     }
 
+    /// Returns the number of bytes this allocator currently holds from the
+    /// system, whether or not they are in use.
+    #[inline]
+    pub fn footprint(&self) -> usize {
+        self.0.footprint()
+    }
+
+    /// Returns the most bytes this allocator has held from the system at
+    /// any one time.
+    #[inline]
+    pub fn max_footprint(&self) -> usize {
+        self.0.max_footprint()
+    }
+
+    /// Releases as much unused memory as possible back to the system, keeping
+    /// `pad` bytes for future allocations. Returns `true` if any memory was
+    /// released.
+    ///
+    /// This normally happens on its own as memory is freed, but can be
+    /// forced after a known spike in memory use.
+    #[inline]
+    pub unsafe fn trim(&mut self, pad: usize) -> bool {
+        self.0.trim(pad)
+    }
+
     /// Reallocates `ptr`, a previous allocation with `old_size` and
     /// `old_align`, to have `new_size` and the same alignment as before.
     ///
@@ -1,14 +1,70 @@
+use core::cell::Cell;
 use core::ptr;
 use Allocator;
 
+const PAGE_SIZE: usize = 4096;
+
+/// The heap is grown by at least this much at a time, so that a program that
+/// allocates in small pieces doesn't need a syscall for every few of them.
+const MIN_HEAP_GROWTH: usize = 64 * 1024;
+
+/// Larger heaps grow in proportionally larger steps, up to this limit.
+const MAX_HEAP_GROWTH: usize = 1024 * 1024;
+
+/// Once this much memory at the end of the heap is unused, it is returned to
+/// the kernel.
+const TRIM_THRESHOLD: usize = 256 * 1024;
+
+/// How much unused memory to keep at the end of the heap when trimming. This
+/// keeps a program whose memory use rises and falls around the same level
+/// from growing and shrinking the heap over and over.
+const TRIM_PAD: usize = 2 * MIN_HEAP_GROWTH;
+
 pub struct System {
-    _priv: (),
+    /// Size of the heap as reported by the kernel the last time it changed.
+    heap_size: Cell<usize>,
+    /// Address just past the end of the heap. Only memory at the very end
+    /// can be given back.
+    heap_end: Cell<usize>,
 }
 
 impl System {
     pub const fn new() -> System {
-        System { _priv: () }
+        System { heap_size: Cell::new(0), heap_end: Cell::new(0) }
+    }
+
+    /// Extends the heap by `size` bytes, returning the start of the new
+    /// region. The kernel replies with the range covering the entire heap,
+    /// and the new pages are the ones at the end of it.
+    fn increase_heap(&self, size: usize) -> Option<*mut u8> {
+        let syscall =
+            xous::SysCall::IncreaseHeap(size, xous::MemoryFlags::R | xous::MemoryFlags::W);
+        if let Ok(xous::Result::MemoryRange(mem)) = xous::rsyscall(syscall) {
+            self.heap_size.set(mem.len());
+            self.heap_end.set(mem.as_ptr() as usize + mem.len());
+            Some((mem.as_ptr() as usize - size + mem.len()) as *mut u8)
+        } else {
+            None
+        }
     }
+
+    /// Gives the `size` bytes starting at `start` back to the kernel. This
+    /// only works if they are at the end of the heap.
+    fn decrease_heap(&self, start: usize, size: usize) -> bool {
+        if size == 0 || start + size != self.heap_end.get() {
+            return false;
+        }
+        if xous::rsyscall(xous::SysCall::DecreaseHeap(size)).is_err() {
+            return false;
+        }
+        self.heap_size.set(self.heap_size.get() - size);
+        self.heap_end.set(start);
+        true
+    }
+}
+
+fn page_align(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
 }
 
 unsafe impl Allocator for System {
@@ -16,22 +72,24 @@ unsafe impl Allocator for System {
     /// chunk of memory, as well as the size of the allocation and some
     /// flags. Since flags are unused on this platform, they will always
     /// be `0`.
+    ///
+    /// The heap grows by a quarter of its current size at a time, within
+    /// `MIN_HEAP_GROWTH` and `MAX_HEAP_GROWTH`. If the kernel can't supply
+    /// that much, only `size` bytes are requested, and if even that fails a
+    /// null pointer is returned so that the allocation fails normally.
     fn alloc(&self, size: usize) -> (*mut u8, usize, u32) {
-        let size = if size == 0 {
-            4096
-        } else if size & 4095 == 0 {
-            size
-        } else {
-            size + (4096 - (size & 4095))
-        };
+        let size = page_align(size.max(1));
+        let step = page_align(self.heap_size.get() / 4).max(MIN_HEAP_GROWTH).min(MAX_HEAP_GROWTH);
+        let preferred = size.max(step);
 
-        let syscall =
-            xous::SysCall::IncreaseHeap(size, xous::MemoryFlags::R | xous::MemoryFlags::W);
-        if let Ok(xous::Result::MemoryRange(mem)) = xous::rsyscall(syscall) {
-            let start = mem.as_ptr() as usize - size + mem.len();
-            (start as *mut u8, size, 0)
-        } else {
-            (ptr::null_mut(), 0, 0)
+        let grown = match self.increase_heap(preferred) {
+            Some(start) => Some((start, preferred)),
+            None if preferred > size => self.increase_heap(size).map(|start| (start, size)),
+            None => None,
+        };
+        match grown {
+            Some((start, size)) => (start, size, 0),
+            None => (ptr::null_mut(), 0, 0),
         }
     }
 
@@ -40,16 +98,16 @@ fn remap(&self, _ptr: *mut u8, _oldsize: usize, _newsize: usize, _can_move: bool
         ptr::null_mut()
     }
 
-    fn free_part(&self, _ptr: *mut u8, _oldsize: usize, _newsize: usize) -> bool {
-        false
+    fn free_part(&self, ptr: *mut u8, oldsize: usize, newsize: usize) -> bool {
+        self.decrease_heap(ptr as usize + newsize, oldsize - newsize)
     }
 
-    fn free(&self, _ptr: *mut u8, _size: usize) -> bool {
-        false
+    fn free(&self, ptr: *mut u8, size: usize) -> bool {
+        self.decrease_heap(ptr as usize, size)
     }
 
     fn can_release_part(&self, _flags: u32) -> bool {
-        false
+        true
     }
 
     fn allocates_zeros(&self) -> bool {
@@ -57,7 +115,15 @@ fn allocates_zeros(&self) -> bool {
     }
 
     fn page_size(&self) -> usize {
-        4 * 1024
+        PAGE_SIZE
+    }
+
+    fn trim_threshold(&self) -> usize {
+        TRIM_THRESHOLD
+    }
+
+    fn trim_pad(&self) -> usize {
+        TRIM_PAD
     }
 }
 
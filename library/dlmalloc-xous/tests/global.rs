@@ -1,5 +1,8 @@
 extern crate dlmalloc;
+extern crate rand;
 
+use rand::Rng;
+use std::alloc::{alloc, dealloc, realloc, Layout};
 use std::collections::HashMap;
 use std::thread;
 
@@ -29,3 +32,60 @@ fn strings() {
 fn threads() {
     assert!(thread::spawn(|| panic!()).join().is_err());
 }
+
+// Each thread fills its blocks with its own byte and checks them before
+// freeing, so a block handed to two threads at once shows up as corruption.
+#[test]
+fn threads_stress() {
+    let threads = (0..8u8)
+        .map(|tag| {
+            thread::spawn(move || unsafe {
+                let mut rng = rand::thread_rng();
+                let mut ptrs: Vec<(*mut u8, Layout)> = Vec::new();
+                for _ in 0..2_000 {
+                    if ptrs.len() > 0 && rng.gen() {
+                        let idx = rng.gen_range(0, ptrs.len());
+                        let (ptr, layout) = ptrs.swap_remove(idx);
+                        for i in 0..layout.size() {
+                            assert_eq!(*ptr.offset(i as isize), tag);
+                        }
+                        if rng.gen_weighted_bool(10) {
+                            let new_size = rng.gen_range(1, layout.size() * 2 + 1);
+                            let ptr = realloc(ptr, layout, new_size);
+                            assert!(!ptr.is_null());
+                            for i in 0..new_size {
+                                *ptr.offset(i as isize) = tag;
+                            }
+                            ptrs.push((ptr, Layout::from_size_align(new_size, 8).unwrap()));
+                        } else {
+                            dealloc(ptr, layout);
+                        }
+                        continue;
+                    }
+
+                    let size = if rng.gen() {
+                        rng.gen_range(1, 128)
+                    } else {
+                        rng.gen_range(1, 64 * 1024)
+                    };
+                    let layout = Layout::from_size_align(size, 8).unwrap();
+                    let ptr = alloc(layout);
+                    assert!(!ptr.is_null());
+                    for i in 0..size {
+                        *ptr.offset(i as isize) = tag;
+                    }
+                    ptrs.push((ptr, layout));
+                }
+                for (ptr, layout) in ptrs {
+                    for i in 0..layout.size() {
+                        assert_eq!(*ptr.offset(i as isize), tag);
+                    }
+                    dealloc(ptr, layout);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -23,6 +23,42 @@ fn smoke() {
     }
 }
 
+#[test]
+fn over_aligned() {
+    let mut a = Dlmalloc::new();
+    unsafe {
+        for &align in &[4096, 8192, 16 * 1024, 32 * 1024, 64 * 1024] {
+            for &size in &[1, 100, 4095, 4096, 4097, 10_000, 64 * 1024, 100 * 1024] {
+                let ptr = a.malloc(size, align);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0, "size {} align {}", size, align);
+                for i in 0..size {
+                    *ptr.offset(i as isize) = i as u8;
+                }
+
+                let zeroed = a.calloc(size, align);
+                assert!(!zeroed.is_null());
+                assert_eq!(zeroed as usize % align, 0, "size {} align {}", size, align);
+                for i in 0..size {
+                    assert_eq!(*zeroed.offset(i as isize), 0);
+                }
+                a.free(zeroed, size, align);
+
+                let new_size = size * 3;
+                let ptr = a.realloc(ptr, size, align, new_size);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0, "size {} align {}", new_size, align);
+                for i in 0..size {
+                    assert_eq!(*ptr.offset(i as isize), i as u8);
+                }
+                a.free(ptr, new_size, align);
+            }
+        }
+
+        assert!(a.malloc(16, 1 << (usize::max_value().count_ones() - 1)).is_null());
+    }
+}
+
 #[test]
 fn stress() {
     let mut a = Dlmalloc::new();
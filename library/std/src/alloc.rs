@@ -329,6 +329,8 @@ fn default_alloc_error_hook(layout: Layout) {
         panic!("memory allocation of {} bytes failed\n", layout.size());
     } else {
         rtprintpanic!("memory allocation of {} bytes failed\n", layout.size());
+        #[cfg(target_os = "xous")]
+        crate::sys::alloc::print_alloc_error_details(layout);
     }
 }
 
@@ -341,6 +343,9 @@ pub fn rust_oom(layout: Layout) -> ! {
     let hook: fn(Layout) =
         if hook.is_null() { default_alloc_error_hook } else { unsafe { mem::transmute(hook) } };
     hook(layout);
+    #[cfg(target_os = "xous")]
+    crate::sys::alloc::exit_on_alloc_error();
+    #[cfg(not(target_os = "xous"))]
     crate::process::abort()
 }
 
@@ -3,7 +3,8 @@
 use crate::path::Path;
 
 #[test]
-#[cfg_attr(any(target_os = "emscripten", target_env = "sgx"), ignore)]
+// Xous gives the bare name the program was started as. See below.
+#[cfg_attr(any(target_os = "emscripten", target_env = "sgx", target_os = "xous"), ignore)]
 fn test_self_exe_path() {
     let path = current_exe();
     assert!(path.is_ok());
@@ -21,6 +22,15 @@ fn test() {
     current_dir().unwrap();
 }
 
+#[test]
+#[cfg(target_os = "xous")]
+fn test_self_exe_name() {
+    let name = current_exe().unwrap();
+    assert!(!name.as_os_str().is_empty());
+    assert_eq!(name.components().count(), 1, "{:?} is not a bare name", name);
+    assert_eq!(current_exe().unwrap(), name);
+}
+
 #[test]
 #[cfg(windows)]
 fn split_paths_windows() {
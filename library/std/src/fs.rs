@@ -1786,6 +1786,8 @@ pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<u64> {
 /// On Android, VxWorks, and Redox, it instead corresponds to the `link` function.
 /// On MacOS, it uses the `linkat` function if it is available, but on very old
 /// systems where `linkat` is not available, `link` is selected at runtime instead.
+/// On Xous, where a key can't have two names, `link` is a copy: changes made
+/// through one path afterwards are not seen through the other.
 /// Note that, this [may change in the future][changes].
 ///
 /// [changes]: io#platform-specific-behavior
@@ -1534,3 +1534,125 @@ fn read_large_dir() {
         entry.unwrap();
     }
 }
+
+// On Xous, files are keys in the PDDB and directories are its dicts.
+
+#[test]
+#[cfg(target_os = "xous")]
+fn xous_key_lifecycle() {
+    let tmpdir = tmpdir();
+    let old = tmpdir.join("old");
+    let new = tmpdir.join("new");
+
+    check!(fs::write(&old, b"hello"));
+    assert_eq!(check!(fs::read(&old)), b"hello");
+
+    check!(fs::rename(&old, &new));
+    assert_eq!(fs::read(&old).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(check!(fs::read(&new)), b"hello");
+
+    check!(fs::remove_file(&new));
+    assert_eq!(fs::metadata(&new).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(fs::remove_file(&new).unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[test]
+#[cfg(target_os = "xous")]
+fn xous_dict_or_key() {
+    let tmpdir = tmpdir();
+    let dict = tmpdir.join("dict");
+    let key = tmpdir.join("key");
+    check!(fs::create_dir(&dict));
+    check!(fs::write(&key, b"data"));
+
+    let meta = check!(fs::metadata(&dict));
+    assert!(meta.is_dir() && !meta.is_file());
+    assert_eq!(meta.len(), 0);
+    assert!(dict.is_dir() && !key.is_dir());
+    assert!(key.is_file() && !dict.is_file());
+    assert!(check!(fs::metadata(tmpdir.join("dict/"))).is_dir());
+
+    // Only dicts can be named with a trailing slash.
+    assert_eq!(fs::metadata(tmpdir.join("key/")).unwrap_err().kind(), ErrorKind::NotADirectory);
+    assert_eq!(File::open(&dict).unwrap_err().kind(), ErrorKind::IsADirectory);
+    assert_eq!(
+        fs::rename(&dict, tmpdir.join("renamed")).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(fs::create_dir(&key).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    // A dict that isn't empty stays until everything in it is gone.
+    check!(fs::write(dict.join("inner"), b""));
+    assert_eq!(fs::remove_dir(&dict).unwrap_err().kind(), ErrorKind::DirectoryNotEmpty);
+    check!(fs::remove_dir_all(&dict));
+    assert!(!dict.exists());
+}
+
+#[test]
+#[cfg(target_os = "xous")]
+fn xous_metadata() {
+    let tmpdir = tmpdir();
+    let key = tmpdir.join("key");
+    let before = crate::time::SystemTime::now();
+    check!(fs::write(&key, &[7; 5000]));
+
+    let meta = check!(fs::metadata(&key));
+    assert!(meta.is_file());
+    assert_eq!(meta.len(), 5000);
+    assert_eq!(check!(check!(File::open(&key)).metadata()).len(), 5000);
+    // Keys written by older services have no modification time.
+    if let Ok(modified) = meta.modified() {
+        let slack = Duration::from_secs(2);
+        assert!(modified + slack >= before, "{modified:?} is before {before:?}");
+    }
+    assert!(meta.accessed().is_err());
+    assert!(!meta.permissions().readonly());
+}
+
+#[test]
+#[cfg(target_os = "xous")]
+fn xous_read_dir() {
+    let tmpdir = tmpdir();
+    check!(fs::write(tmpdir.join("a"), b"1"));
+    check!(fs::write(tmpdir.join("b"), b"22"));
+    check!(fs::create_dir(tmpdir.join("c")));
+    check!(fs::write(tmpdir.join("c/nested"), b""));
+
+    let mut entries: Vec<_> = check!(fs::read_dir(tmpdir.path())).map(|e| check!(e)).collect();
+    entries.sort_by_key(|e| e.file_name());
+    let names: Vec<_> = entries.iter().map(|e| e.file_name()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+    for (entry, name) in entries.iter().zip(["a", "b", "c"]) {
+        assert_eq!(entry.path(), tmpdir.join(name));
+        assert_eq!(check!(entry.file_type()).is_dir(), name == "c");
+    }
+    assert_eq!(check!(entries[1].metadata()).len(), 2);
+
+    assert_eq!(fs::read_dir(tmpdir.join("missing")).unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[test]
+#[cfg(target_os = "xous")]
+fn xous_hard_link_copies() {
+    let tmpdir = tmpdir();
+    let src = tmpdir.join("src");
+    let dst = tmpdir.join("dst");
+    check!(fs::write(&src, b"original"));
+
+    check!(fs::hard_link(&src, &dst));
+    assert_eq!(check!(fs::read(&dst)), b"original");
+
+    // The PDDB can't give a key two names, so the link is a copy.
+    check!(fs::write(&dst, b"changed"));
+    assert_eq!(check!(fs::read(&src)), b"original");
+    check!(fs::remove_file(&src));
+    assert_eq!(check!(fs::read(&dst)), b"changed");
+
+    check!(fs::write(&src, b"again"));
+    assert_eq!(fs::hard_link(&src, &dst).unwrap_err().kind(), ErrorKind::AlreadyExists);
+    check!(fs::create_dir(tmpdir.join("dict")));
+    assert_eq!(
+        fs::hard_link(tmpdir.join("dict"), tmpdir.join("link")).unwrap_err().kind(),
+        ErrorKind::IsADirectory
+    );
+}
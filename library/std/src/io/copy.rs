@@ -46,6 +46,9 @@
 /// `sendfile(2)` or `splice(2)` syscalls to move data directly between file
 /// descriptors if possible.
 ///
+/// On Xous, copies between a `TcpStream` and a `File` share one buffer
+/// between the network service and the PDDB instead of using a separate one.
+///
 /// Note that platform-specific behavior [may change in the future][changes].
 ///
 /// [changes]: crate::io#platform-specific-behavior
@@ -58,6 +61,8 @@ pub fn copy<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W) -> Result<u64>
     cfg_if::cfg_if! {
         if #[cfg(any(target_os = "linux", target_os = "android"))] {
             crate::sys::kernel_copy::copy_spec(reader, writer)
+        } else if #[cfg(target_os = "xous")] {
+            crate::sys::kernel_copy::copy_spec(reader, writer)
         } else {
             generic_copy(reader, writer)
         }
@@ -78,7 +78,7 @@ const fn ntohs(i: u16) -> u16 {
     u16::from_be(i)
 }
 
-fn each_addr<A: ToSocketAddrs, F, T>(addr: A, mut f: F) -> io::Result<T>
+pub(crate) fn each_addr<A: ToSocketAddrs, F, T>(addr: A, mut f: F) -> io::Result<T>
 where
     F: FnMut(io::Result<&SocketAddr>) -> io::Result<T>,
 {
@@ -0,0 +1,47 @@
+//! Safe building blocks for talking to servers over protocols of a program's
+//! own.
+//!
+//! Messages are sent over a [`Connection`] from
+//! [`services::connect`](super::services::connect). Kernel errors come back
+//! as [`io::Error`]s, with the kernel's error code as
+//! [`raw_os_error`](io::Error::raw_os_error).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! #![feature(xous_std)]
+//! use std::os::xous::ffi::{send_blocking_scalar, LendBuffer};
+//! use std::os::xous::services;
+//!
+//! let server = services::connect("my-server")?;
+//! let [version, ..] = send_blocking_scalar(&server, 0 /* GetVersion */, [0; 4])?;
+//!
+//! let mut buffer = LendBuffer::new(64)?;
+//! buffer[..5].copy_from_slice(b"hello");
+//! let reply = buffer.lend_mut(&server, 1 /* Echo */, 0, 5)?;
+//! println!("version {version}: {:?}", &buffer[..reply.valid]);
+//! # std::io::Result::Ok(())
+//! ```
+
+pub use crate::sys::ffi::{LendBuffer, Lent};
+pub use crate::sys::services::Connection;
+
+use crate::io;
+
+/// Sends a scalar message with `opcode` and `args` to the server on
+/// `connection`, without waiting for the server to handle it.
+pub fn send_scalar(connection: &Connection, opcode: usize, args: [usize; 4]) -> io::Result<()> {
+    connection.send(opcode, args)
+}
+
+/// Sends a scalar message with `opcode` and `args` to the server on
+/// `connection`, and waits for its scalar reply. Reply values the server
+/// didn't send are zero, and a reply that isn't a scalar fails with
+/// [`io::ErrorKind::InvalidData`].
+pub fn send_blocking_scalar(
+    connection: &Connection,
+    opcode: usize,
+    args: [usize; 4],
+) -> io::Result<[usize; 5]> {
+    connection.send_blocking(opcode, args)
+}
@@ -0,0 +1,82 @@
+//! Xous-specific extensions to filesystem primitives.
+
+use crate::fs::File;
+use crate::io;
+use crate::sealed::Sealed;
+use crate::sys_common::AsInner;
+use crate::time::Duration;
+
+impl Sealed for File {}
+
+/// Xous-specific extensions to [`File`], for advisory locking.
+///
+/// Any number of handles can hold a shared lock on a key at once, but an
+/// exclusive lock excludes every other lock. The locks are only advisory:
+/// they keep other handles from taking conflicting locks, not from reading
+/// or writing the key.
+///
+/// A lock belongs to the handle it was taken through, so a [`File`] and the
+/// clones made from it with [`File::try_clone`] share one lock. The lock is
+/// released when the last of them is dropped, or when the process exits.
+/// Taking a lock while already holding one changes the lock's mode.
+///
+/// This trait is sealed: it cannot be implemented outside the standard library.
+/// This is so that future additional methods are not breaking changes.
+pub trait FileExt: Sealed {
+    /// Takes a shared lock, waiting as long as an exclusive lock is held.
+    fn lock_shared(&self) -> io::Result<()>;
+
+    /// Takes an exclusive lock, waiting as long as any other lock is held.
+    fn lock_exclusive(&self) -> io::Result<()>;
+
+    /// Takes a shared lock like [`lock_shared`](FileExt::lock_shared), but
+    /// fails with [`io::ErrorKind::TimedOut`] if it could not be taken
+    /// within `timeout`.
+    fn lock_shared_timeout(&self, timeout: Duration) -> io::Result<()>;
+
+    /// Takes an exclusive lock like [`lock_exclusive`](FileExt::lock_exclusive),
+    /// but fails with [`io::ErrorKind::TimedOut`] if it could not be taken
+    /// within `timeout`.
+    fn lock_exclusive_timeout(&self, timeout: Duration) -> io::Result<()>;
+
+    /// Takes a shared lock if that can be done without waiting, and
+    /// otherwise fails with [`io::ErrorKind::WouldBlock`].
+    fn try_lock_shared(&self) -> io::Result<()>;
+
+    /// Takes an exclusive lock if that can be done without waiting, and
+    /// otherwise fails with [`io::ErrorKind::WouldBlock`].
+    fn try_lock_exclusive(&self) -> io::Result<()>;
+
+    /// Releases the lock held through this handle, if any.
+    fn unlock(&self) -> io::Result<()>;
+}
+
+impl FileExt for File {
+    fn lock_shared(&self) -> io::Result<()> {
+        self.as_inner().lock(false, true, None)
+    }
+
+    fn lock_exclusive(&self) -> io::Result<()> {
+        self.as_inner().lock(true, true, None)
+    }
+
+    fn lock_shared_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.as_inner().lock(false, true, Some(timeout))
+    }
+
+    fn lock_exclusive_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.as_inner().lock(true, true, Some(timeout))
+    }
+
+    fn try_lock_shared(&self) -> io::Result<()> {
+        self.as_inner().lock(false, false, None)
+    }
+
+    fn try_lock_exclusive(&self) -> io::Result<()> {
+        self.as_inner().lock(true, false, None)
+    }
+
+    fn unlock(&self) -> io::Result<()> {
+        self.as_inner().unlock()
+    }
+}
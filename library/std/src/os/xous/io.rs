@@ -0,0 +1,97 @@
+//! Xous-specific extensions to the standard I/O handles.
+
+use crate::io::{self, Stderr, Stdin, Stdout};
+use crate::sealed::Sealed;
+use crate::sys::stdio;
+use crate::time::Duration;
+
+pub use crate::sys::stdio::KeyEvent;
+
+impl Sealed for Stdin {}
+impl Sealed for Stdout {}
+impl Sealed for Stderr {}
+
+/// Xous-specific extensions to [`Stdin`].
+///
+/// This trait is sealed: it cannot be implemented outside the standard library.
+/// This is so that future additional methods are not breaking changes.
+pub trait StdinExt: Sealed {
+    /// Sets a timeout for reads from the console.
+    ///
+    /// If the value specified is [`None`], reads block until input arrives.
+    /// Otherwise, a read that has not received any input when the timeout
+    /// elapses fails with [`io::ErrorKind::TimedOut`]. The timeout applies to
+    /// every handle to standard input.
+    ///
+    /// An [`Err`] is returned if the zero [`Duration`] is passed.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Returns the read timeout set by [`set_read_timeout`](StdinExt::set_read_timeout).
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+
+    /// Waits for the next key to be pressed or released.
+    ///
+    /// Key events come straight from the keyboard, bypassing line editing,
+    /// so this reports keys such as the arrows and Backspace that never show
+    /// up in text read from [`Stdin`]. The read timeout set by
+    /// [`set_read_timeout`](StdinExt::set_read_timeout) applies here too.
+    fn read_key_event(&self) -> io::Result<KeyEvent>;
+
+    /// Switches the console between raw and line-cooked delivery.
+    ///
+    /// In raw mode the console hands each key to the program as soon as it
+    /// is typed, without echoing it or waiting for Enter. Switching back
+    /// restores normal line-at-a-time reads such as [`Stdin::read_line`].
+    /// The console is put back into cooked mode automatically when the
+    /// process exits or panics.
+    fn set_raw_mode(&self, raw: bool) -> io::Result<()>;
+}
+
+impl StdinExt for Stdin {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        stdio::set_stdin_read_timeout(timeout)
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(stdio::stdin_read_timeout())
+    }
+
+    fn read_key_event(&self) -> io::Result<KeyEvent> {
+        stdio::read_key_event()
+    }
+
+    fn set_raw_mode(&self, raw: bool) -> io::Result<()> {
+        stdio::set_raw_mode(raw)
+    }
+}
+
+/// Xous-specific extensions shared by [`Stdin`], [`Stdout`] and [`Stderr`].
+///
+/// This trait is sealed: it cannot be implemented outside the standard library.
+/// This is so that future additional methods are not breaking changes.
+pub trait StdioExt: Sealed {
+    /// Returns `true` if this stream is attached to an interactive console,
+    /// and `false` if it is being captured, for example by a test harness.
+    ///
+    /// The answer is looked up once and then cached for the life of the
+    /// process.
+    fn is_terminal(&self) -> bool;
+}
+
+impl StdioExt for Stdin {
+    fn is_terminal(&self) -> bool {
+        stdio::stdin_is_terminal()
+    }
+}
+
+impl StdioExt for Stdout {
+    fn is_terminal(&self) -> bool {
+        stdio::stdout_is_terminal()
+    }
+}
+
+impl StdioExt for Stderr {
+    fn is_terminal(&self) -> bool {
+        stdio::stderr_is_terminal()
+    }
+}
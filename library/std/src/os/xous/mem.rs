@@ -0,0 +1,51 @@
+//! Xous-specific control over the process's memory.
+
+pub use crate::sys::alloc::HeapStats;
+pub use crate::sys::page_pool::PagePoolStats;
+
+/// The exit code of a process that was terminated because a memory
+/// allocation failed.
+///
+/// Before exiting, the process prints the size and alignment of the failed
+/// request and the size of its heap to the log, the same way a panic message
+/// is printed. A custom hook set with `std::alloc::set_alloc_error_hook`
+/// replaces that message, but the exit code stays the same.
+pub const ALLOC_ERROR_EXIT_CODE: u32 = crate::sys::alloc::ALLOC_ERROR_EXIT_CODE;
+
+/// Returns as much unused heap memory as possible to the kernel, and
+/// returns `true` if any was released.
+///
+/// Freed memory is normally handed back once enough of it has collected at
+/// the end of the heap, keeping a little in reserve so that a program whose
+/// memory use goes up and down doesn't keep growing and shrinking its heap.
+/// Call this after a known spike in memory use to release that reserve as
+/// well.
+///
+/// Only memory at the end of the heap can be released, so a long-lived
+/// allocation made during the spike keeps everything below it in place.
+pub fn trim() -> bool {
+    crate::sys::alloc::trim()
+}
+
+/// Returns a snapshot of the heap's usage.
+///
+/// The allocator keeps these totals as it goes, so this is cheap enough to
+/// call periodically, for example to watch for fragmentation on a device.
+/// Only allocations made through [`System`], which is the default global
+/// allocator, are counted.
+///
+/// [`System`]: crate::alloc::System
+pub fn heap_stats() -> HeapStats {
+    crate::sys::alloc::heap_stats()
+}
+
+/// Returns how much the pool of pages that the standard library lends to
+/// services has been used.
+///
+/// Requests to the network, DNS and other services take their buffers from
+/// the pool and give them back afterwards. A count of `allocated` that keeps
+/// growing while `reused` doesn't means more requests are in flight at once
+/// than the pool keeps pages for, which costs an allocation per request.
+pub fn page_pool_stats() -> PagePoolStats {
+    crate::sys::page_pool::stats()
+}
@@ -0,0 +1,16 @@
+//! Functionality specific to the Xous operating system.
+
+#![unstable(feature = "xous_std", issue = "none")]
+#![doc(cfg(target_os = "xous"))]
+
+pub mod ffi;
+pub mod fs;
+pub mod io;
+pub mod mem;
+pub mod net;
+pub mod pipe;
+pub mod process;
+pub mod services;
+pub mod sync;
+pub mod time;
+pub mod trng;
@@ -0,0 +1,96 @@
+//! Xous-specific information about the device's network, and extensions to
+//! its sockets.
+
+pub use crate::sys::net::InterfaceInfo;
+
+use crate::io;
+use crate::net::{self, TcpListener, ToSocketAddrs};
+use crate::sealed::Sealed;
+use crate::sys;
+use crate::sys_common::{AsInner, FromInner};
+
+/// Returns the device's hostname, as the network service announces it.
+///
+/// This works whether or not Wi-Fi is connected. Older network services
+/// have no hostname to give while it is down, and then this returns
+/// `"localhost"`.
+pub fn hostname() -> io::Result<String> {
+    crate::sys::net::hostname()
+}
+
+/// Returns the device's network interfaces and their addresses.
+///
+/// This works whether or not Wi-Fi is connected. While it is down, the
+/// Wi-Fi interface is reported as down with no addresses, or left out
+/// entirely by older network services, but the loopback interface is always
+/// there.
+pub fn interfaces() -> io::Result<Vec<InterfaceInfo>> {
+    crate::sys::net::interfaces()
+}
+
+/// Options for binding a [`TcpListener`], for those that have to be set
+/// before the listener is bound.
+///
+/// # Examples
+///
+/// ```no_run
+/// #![feature(xous_std)]
+/// use std::os::xous::net::{TcpListenerExt, TcpListenerOptions};
+///
+/// // Take the port back straight away after a restart, even while the
+/// // previous listener's connections are still closing.
+/// let listener = TcpListenerOptions::new().reuse_addr(true).bind("0.0.0.0:80")?;
+/// if !listener.reuse_addr()? {
+///     println!("the network service can't reuse addresses");
+/// }
+/// # std::io::Result::Ok(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TcpListenerOptions {
+    reuse_addr: bool,
+}
+
+impl TcpListenerOptions {
+    /// Returns the default options, which bind the same way as
+    /// [`TcpListener::bind`].
+    pub fn new() -> TcpListenerOptions {
+        TcpListenerOptions::default()
+    }
+
+    /// Asks the network service to bind to the address even if it is still
+    /// held by connections that are closing, like `SO_REUSEADDR` on other
+    /// platforms. Off by default.
+    ///
+    /// An address that a listener or an open connection holds is never
+    /// taken. Network services that can't reuse addresses ignore this, which
+    /// [`TcpListenerExt::reuse_addr`] tells.
+    pub fn reuse_addr(&mut self, reuse_addr: bool) -> &mut TcpListenerOptions {
+        self.reuse_addr = reuse_addr;
+        self
+    }
+
+    /// Binds a [`TcpListener`] to `addr` with these options, trying each
+    /// address it resolves to in turn, like [`TcpListener::bind`].
+    pub fn bind<A: ToSocketAddrs>(&self, addr: A) -> io::Result<TcpListener> {
+        net::each_addr(addr, |addr| sys::net::TcpListener::bind_with(addr, self.reuse_addr))
+            .map(TcpListener::from_inner)
+    }
+}
+
+impl Sealed for TcpListener {}
+
+/// Xous-specific extensions to [`TcpListener`].
+///
+/// This trait is sealed: it cannot be implemented outside the standard library.
+/// This is so that future additional methods are not breaking changes.
+pub trait TcpListenerExt: Sealed {
+    /// Returns `true` if the listener was bound with
+    /// [`TcpListenerOptions::reuse_addr`] and the network service honored it.
+    fn reuse_addr(&self) -> io::Result<bool>;
+}
+
+impl TcpListenerExt for TcpListener {
+    fn reuse_addr(&self) -> io::Result<bool> {
+        Ok(self.as_inner().reuse_addr())
+    }
+}
@@ -0,0 +1,145 @@
+//! Anonymous pipes, for passing bytes between threads, or to a child process.
+//!
+//! A pipe is a bounded channel of bytes: writes wait while it is full, and
+//! reads wait while it is empty. Reads return zero, the end of the stream,
+//! once every handle to the write end has been dropped, including any held
+//! by child processes, and writes fail with [`io::ErrorKind::BrokenPipe`]
+//! once every handle to the read end has.
+//!
+//! Pipes are kept by the loader rather than in the process, which is what
+//! lets either end be given to a child through [`process::Stdio`]. A pipe
+//! holds at most a page of data, so a writer that gets ahead of its reader
+//! is held back until the reader catches up.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! #![feature(xous_std)]
+//! use std::io::{Read, Write};
+//! use std::os::xous::pipe;
+//! use std::thread;
+//!
+//! let (mut reader, mut writer) = pipe::pipe()?;
+//! let producer = thread::spawn(move || writer.write_all(b"hello"));
+//!
+//! let mut received = String::new();
+//! reader.read_to_string(&mut received)?;
+//! assert_eq!(received, "hello");
+//! producer.join().unwrap()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+use crate::fmt;
+use crate::io::{self, IoSlice, IoSliceMut, Read, Write};
+use crate::process;
+use crate::sys::pipe::{anon_pipe, AnonPipe};
+use crate::sys_common::FromInner;
+
+/// Makes a new pipe, returning its read end and its write end.
+pub fn pipe() -> io::Result<(PipeReader, PipeWriter)> {
+    let (read, write) = anon_pipe()?;
+    Ok((PipeReader(read), PipeWriter(write)))
+}
+
+/// The read end of a pipe made by [`pipe`].
+pub struct PipeReader(AnonPipe);
+
+/// The write end of a pipe made by [`pipe`].
+pub struct PipeWriter(AnonPipe);
+
+impl PipeReader {
+    /// Makes reads fail with [`io::ErrorKind::WouldBlock`] when the pipe is
+    /// empty, instead of waiting for data.
+    ///
+    /// This only applies to reads through this `PipeReader`, and not to
+    /// other processes holding the same end.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.0.set_nonblocking(nonblocking)
+    }
+}
+
+impl PipeWriter {
+    /// Makes writes fail with [`io::ErrorKind::WouldBlock`] when the pipe is
+    /// full, instead of waiting for room.
+    ///
+    /// This only applies to writes through this `PipeWriter`, and not to
+    /// other processes holding the same end.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.0.set_nonblocking(nonblocking)
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.0.read_vectored(bufs)
+    }
+}
+
+impl Read for &PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.0.read_vectored(bufs)
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl From<PipeReader> for process::Stdio {
+    /// Gives the read end to a child, typically as its standard input.
+    fn from(pipe: PipeReader) -> process::Stdio {
+        process::Stdio::from_inner(pipe.0.into())
+    }
+}
+
+impl From<PipeWriter> for process::Stdio {
+    /// Gives the write end to a child, typically as its standard output or
+    /// standard error.
+    fn from(pipe: PipeWriter) -> process::Stdio {
+        process::Stdio::from_inner(pipe.0.into())
+    }
+}
+
+impl fmt::Debug for PipeReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PipeReader").field(&self.0.handle()).finish()
+    }
+}
+
+impl fmt::Debug for PipeWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PipeWriter").field(&self.0.handle()).finish()
+    }
+}
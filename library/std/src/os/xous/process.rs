@@ -0,0 +1,38 @@
+//! Xous-specific extensions to process handling.
+
+use crate::process;
+use crate::sealed::Sealed;
+use crate::sys_common::AsInner;
+
+pub use crate::sys::process::TerminationReason;
+
+/// The exit code of a process that aborted, for example through
+/// [`std::process::abort`] or a panic while panicking.
+///
+/// Before exiting, the process writes its name, the ID of the thread that
+/// aborted, and "abort called" to the log, the same way a panic message is
+/// printed.
+///
+/// [`std::process::abort`]: crate::process::abort
+pub const ABORT_EXIT_CODE: u32 = crate::sys::ABORT_EXIT_CODE;
+
+impl Sealed for process::ExitStatus {}
+
+/// Xous-specific extensions to [`process::ExitStatus`].
+///
+/// A child that exits, or returns from `main`, has an exit code, which
+/// [`ExitStatus::code`](process::ExitStatus::code) returns. A child that is
+/// ended some other way has none, and the reason is available from here.
+///
+/// This trait is sealed: it cannot be implemented outside the standard library.
+/// This is so that future additional methods are not breaking changes.
+pub trait ExitStatusExt: Sealed {
+    /// Returns why the process was ended, if it didn't exit by itself.
+    fn termination_reason(&self) -> Option<TerminationReason>;
+}
+
+impl ExitStatusExt for process::ExitStatus {
+    fn termination_reason(&self) -> Option<TerminationReason> {
+        self.as_inner().termination_reason()
+    }
+}
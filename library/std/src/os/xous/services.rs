@@ -0,0 +1,17 @@
+//! Xous-specific access to the system's services.
+
+pub use crate::sys::services::Connection;
+
+use crate::io;
+
+/// Connects to the service registered with the name server as `name`.
+///
+/// Connections are cached, so connecting to the same service again, or to
+/// one the standard library already talks to itself, doesn't go back to the
+/// name server. A connection is closed once every [`Connection`] to it has
+/// been dropped, unless the standard library is still using it.
+///
+/// An error naming the service is returned if it can't be found.
+pub fn connect(name: &str) -> io::Result<Connection> {
+    crate::sys::services::connect(name)
+}
@@ -0,0 +1,166 @@
+//! Xous-specific synchronization primitives.
+
+use crate::cell::UnsafeCell;
+use crate::fmt;
+use crate::ops::{Deref, DerefMut};
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+use crate::sys::locks as sys;
+use crate::thread;
+
+pub use crate::sys::locks::{set_priority_hooks, PriorityHooks};
+
+/// A mutual exclusion primitive that avoids priority inversion.
+///
+/// This behaves like [`Mutex`], except that when a thread blocks on the lock,
+/// the thread currently holding it temporarily inherits the priority of the
+/// waiter. The boost is removed as soon as the lock is released, including
+/// when the holder panics while the lock is held.
+///
+/// Use this for data that is shared between a high-priority thread, such as
+/// the UI thread, and low-priority background workers.
+///
+/// The lock is poisoned in the same way as [`Mutex`] if a thread panics while
+/// holding it. Locking recursively from the same thread panics.
+///
+/// [`Mutex`]: crate::sync::Mutex
+///
+/// # Examples
+///
+/// ```no_run
+/// #![feature(xous_std)]
+/// use std::os::xous::sync::PriorityInheritanceMutex;
+///
+/// let cache = PriorityInheritanceMutex::new(Vec::<u8>::new());
+/// cache.lock().unwrap().push(1);
+/// ```
+pub struct PriorityInheritanceMutex<T: ?Sized> {
+    inner: sys::PiMutex,
+    poisoned: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for PriorityInheritanceMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for PriorityInheritanceMutex<T> {}
+
+/// An RAII guard for a [`PriorityInheritanceMutex`]. The lock is released,
+/// and any priority boost is undone, when this is dropped.
+#[must_use = "if unused the PriorityInheritanceMutex will immediately unlock"]
+pub struct PriorityInheritanceMutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a PriorityInheritanceMutex<T>,
+    panicking: bool,
+}
+
+impl<T: ?Sized> !Send for PriorityInheritanceMutexGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for PriorityInheritanceMutexGuard<'_, T> {}
+
+impl<T> PriorityInheritanceMutex<T> {
+    /// Creates a new, unlocked mutex.
+    pub const fn new(t: T) -> PriorityInheritanceMutex<T> {
+        PriorityInheritanceMutex {
+            inner: sys::PiMutex::new(),
+            poisoned: AtomicBool::new(false),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.is_poisoned();
+        let data = self.data.into_inner();
+        if poisoned { Err(PoisonError::new(data)) } else { Ok(data) }
+    }
+}
+
+impl<T: ?Sized> PriorityInheritanceMutex<T> {
+    /// Acquires the mutex, blocking the current thread until it is able to do
+    /// so. While blocked, the current holder runs with at least the priority
+    /// of the calling thread.
+    pub fn lock(&self) -> LockResult<PriorityInheritanceMutexGuard<'_, T>> {
+        unsafe {
+            self.inner.lock();
+            self.guard()
+        }
+    }
+
+    /// Attempts to acquire the mutex without blocking. No priority boost is
+    /// requested if the lock is already held.
+    pub fn try_lock(&self) -> TryLockResult<PriorityInheritanceMutexGuard<'_, T>> {
+        unsafe {
+            if self.inner.try_lock() {
+                Ok(self.guard()?)
+            } else {
+                Err(TryLockError::WouldBlock)
+            }
+        }
+    }
+
+    /// Determines whether the mutex is poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Returns a mutable reference to the underlying data. No locking is
+    /// needed since this call borrows the mutex mutably.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.is_poisoned();
+        let data = self.data.get_mut();
+        if poisoned { Err(PoisonError::new(data)) } else { Ok(data) }
+    }
+
+    unsafe fn guard(&self) -> LockResult<PriorityInheritanceMutexGuard<'_, T>> {
+        let guard = PriorityInheritanceMutexGuard { lock: self, panicking: thread::panicking() };
+        if self.is_poisoned() { Err(PoisonError::new(guard)) } else { Ok(guard) }
+    }
+}
+
+impl<T: Default> Default for PriorityInheritanceMutex<T> {
+    fn default() -> PriorityInheritanceMutex<T> {
+        PriorityInheritanceMutex::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PriorityInheritanceMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("PriorityInheritanceMutex");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized> Deref for PriorityInheritanceMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for PriorityInheritanceMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for PriorityInheritanceMutexGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // A panic that started while the lock was held poisons it. The unlock
+        // below still runs during unwinding, which also undoes any boost.
+        if !self.panicking && thread::panicking() {
+            self.lock.poisoned.store(true, Ordering::Relaxed);
+        }
+        unsafe { self.lock.inner.unlock() };
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PriorityInheritanceMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,38 @@
+//! Xous-specific extensions to time handling.
+
+use crate::time::Duration;
+
+/// Tells the standard library that the device has just resumed after being
+/// suspended for `suspended`.
+///
+/// [`Instant`] does not count time spent suspended, so that timeouts that were
+/// pending across a suspend still wait out their remaining duration after
+/// resuming. It is read from the ticktimer's count of the time the device has
+/// been awake, which stops while it is suspended, and nothing needs to call
+/// this. Ticktimers that predate that count only keep one that runs through
+/// suspends, and with those, programs that receive a resume notification
+/// should call this from their resume handler.
+///
+/// Calling it with a made-up duration simulates a suspend with any
+/// ticktimer, which is useful for testing.
+///
+/// [`Instant`] never goes backwards as a result of this call.
+///
+/// [`Instant`]: crate::time::Instant
+pub fn note_resume(suspended: Duration) {
+    crate::sys::time::note_resume(suspended)
+}
+
+/// Returns the offset between the UNIX epoch and the start of the ticktimer,
+/// or `None` if the time server can't be asked for it, or the real-time
+/// clock has never been set.
+///
+/// [`SystemTime::now`] is the ticktimer's count (which includes time spent
+/// suspended) plus this offset. Setting or adjusting the clock only changes
+/// the offset, so code that needs deadlines immune to clock changes can track
+/// it and treat any change as a clock adjustment.
+///
+/// [`SystemTime::now`]: crate::time::SystemTime::now
+pub fn utc_offset() -> Option<Duration> {
+    crate::sys::time::utc_offset_ms().ok().map(Duration::from_millis)
+}
@@ -0,0 +1,44 @@
+//! Random numbers from the Xous true random number generator.
+//!
+//! These ask the TRNG server directly, over the connection the standard
+//! library keeps to it, and may be called from any number of threads. If the
+//! TRNG's health tests have failed, they return an error rather than data
+//! that may not be random.
+//!
+//! # Using this with `getrandom`
+//!
+//! The `getrandom` crate, which `rand`'s `OsRng` and many other crates get
+//! their entropy from, has no Xous backend of its own. A program can plug
+//! this module in as its custom backend with the `custom` feature of
+//! `getrandom`:
+//!
+//! ```ignore (requires the getrandom crate)
+//! use core::num::NonZeroU32;
+//!
+//! fn xous_getrandom(buf: &mut [u8]) -> Result<(), getrandom::Error> {
+//!     std::os::xous::trng::fill_bytes(buf)
+//!         .map_err(|_| NonZeroU32::new(getrandom::Error::CUSTOM_START).unwrap().into())
+//! }
+//!
+//! getrandom::register_custom_getrandom!(xous_getrandom);
+//! ```
+
+use crate::io;
+
+/// Fills `buf` with random bytes from the TRNG.
+///
+/// Buffers of any length can be filled. Large buffers are filled a page per
+/// request, by lending the TRNG the memory to fill, and short ones, and
+/// whatever is left at the end, eight bytes per request. TRNG servers that
+/// can't fill lent memory are asked for eight bytes at a time throughout. If
+/// an error is returned, `buf` may have been partly filled, and none of it
+/// should be used.
+pub fn fill_bytes(buf: &mut [u8]) -> io::Result<()> {
+    crate::sys::rand::fill_bytes(buf)
+}
+
+/// Returns a random `u64` from the TRNG, with a single request and without
+/// allocating.
+pub fn next_u64() -> io::Result<u64> {
+    crate::sys::rand::next_u64()
+}
@@ -169,7 +169,8 @@ pub enum Prefix<'a> {
     /// Device namespace prefix, e.g., `\\.\COM42`.
     ///
     /// Device namespace prefixes consist of `\\.\` immediately followed by the
-    /// device name.
+    /// device name. On Xous, this is instead the name of a PDDB basis followed
+    /// by `:`, as in `secrets:/`.
     #[stable(feature = "rust1", since = "1.0.0")]
     DeviceNS(#[stable(feature = "rust1", since = "1.0.0")] &'a OsStr),
 
@@ -202,6 +203,7 @@ fn os_str_len(s: &OsStr) -> usize {
             }
             VerbatimDisk(_) => 6,
             UNC(x, y) => 2 + os_str_len(x) + if os_str_len(y) > 0 { 1 + os_str_len(y) } else { 0 },
+            DeviceNS(x) if cfg!(target_os = "xous") => os_str_len(x) + 1,
             DeviceNS(x) => 4 + os_str_len(x),
             Disk(_) => 2,
         }
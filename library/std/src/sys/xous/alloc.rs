@@ -2,6 +2,25 @@
 
 static mut DLMALLOC: dlmalloc_xous::Dlmalloc = dlmalloc_xous::Dlmalloc::new();
 
+/// Usage totals for `heap_stats`, protected by the same lock as `DLMALLOC`.
+static mut ALLOCATED: usize = 0;
+static mut ALLOCATIONS: usize = 0;
+
+/// A snapshot of how the heap is being used.
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct HeapStats {
+    /// Bytes handed out to the program and not yet freed, as requested.
+    pub allocated: usize,
+    /// Bytes obtained from the kernel for the heap, including free space and
+    /// the allocator's own bookkeeping. The difference from `allocated` shows
+    /// how much is lost to fragmentation.
+    pub reserved: usize,
+    /// Number of allocations that have not been freed yet.
+    pub allocations: usize,
+}
+
 #[stable(feature = "alloc_system_type", since = "1.28.0")]
 unsafe impl GlobalAlloc for System {
     #[inline]
@@ -9,7 +28,9 @@ unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         // SAFETY: DLMALLOC access is guranteed to be safe because the lock gives us unique and non-reentrant access.
         // Calling malloc() is safe because preconditions on this function match the trait method preconditions.
         let _lock = lock::lock();
-        unsafe { DLMALLOC.malloc(layout.size(), layout.align()) }
+        let ptr = unsafe { DLMALLOC.malloc(layout.size(), layout.align()) };
+        unsafe { note_alloc(ptr, layout.size()) };
+        ptr
     }
 
     #[inline]
@@ -17,7 +38,9 @@ unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
         // SAFETY: DLMALLOC access is guranteed to be safe because the lock gives us unique and non-reentrant access.
         // Calling calloc() is safe because preconditions on this function match the trait method preconditions.
         let _lock = lock::lock();
-        unsafe { DLMALLOC.calloc(layout.size(), layout.align()) }
+        let ptr = unsafe { DLMALLOC.calloc(layout.size(), layout.align()) };
+        unsafe { note_alloc(ptr, layout.size()) };
+        ptr
     }
 
     #[inline]
@@ -25,7 +48,11 @@ unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         // SAFETY: DLMALLOC access is guranteed to be safe because the lock gives us unique and non-reentrant access.
         // Calling free() is safe because preconditions on this function match the trait method preconditions.
         let _lock = lock::lock();
-        unsafe { DLMALLOC.free(ptr, layout.size(), layout.align()) }
+        unsafe {
+            DLMALLOC.free(ptr, layout.size(), layout.align());
+            ALLOCATED -= layout.size();
+            ALLOCATIONS -= 1;
+        }
     }
 
     #[inline]
@@ -33,36 +60,90 @@ unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut
         // SAFETY: DLMALLOC access is guranteed to be safe because the lock gives us unique and non-reentrant access.
         // Calling realloc() is safe because preconditions on this function match the trait method preconditions.
         let _lock = lock::lock();
-        unsafe { DLMALLOC.realloc(ptr, layout.size(), layout.align(), new_size) }
+        let new_ptr = unsafe { DLMALLOC.realloc(ptr, layout.size(), layout.align(), new_size) };
+        if !new_ptr.is_null() {
+            unsafe { ALLOCATED = ALLOCATED - layout.size() + new_size };
+        }
+        new_ptr
+    }
+}
+
+/// Counts a new allocation in the heap statistics. Must be called with the
+/// heap lock held.
+unsafe fn note_alloc(ptr: *mut u8, size: usize) {
+    if !ptr.is_null() {
+        unsafe {
+            ALLOCATED += size;
+            ALLOCATIONS += 1;
+        }
+    }
+}
+
+pub fn heap_stats() -> HeapStats {
+    let _lock = lock::lock();
+    unsafe {
+        HeapStats { allocated: ALLOCATED, reserved: DLMALLOC.footprint(), allocations: ALLOCATIONS }
+    }
+}
+
+/// Exit code of a process that was terminated because an allocation failed,
+/// so that the loader can tell running out of memory apart from a panic.
+pub const ALLOC_ERROR_EXIT_CODE: u32 = 102;
+
+/// Prints what is known about the heap after an allocation has failed. This
+/// must not allocate, and only tries to take the heap lock since the failure
+/// may have happened while another thread holds it.
+pub fn print_alloc_error_details(layout: Layout) {
+    rtprintpanic!("requested alignment: {} bytes\n", layout.align());
+    if let Some(_lock) = lock::try_lock() {
+        let (allocated, footprint, max_footprint) =
+            unsafe { (ALLOCATED, DLMALLOC.footprint(), DLMALLOC.max_footprint()) };
+        rtprintpanic!(
+            "heap: {} bytes allocated, {} bytes reserved (peak {} bytes)\n",
+            allocated,
+            footprint,
+            max_footprint
+        );
     }
 }
 
+/// Terminates the process after the allocation error hook has run, in place
+/// of the abort other platforms use.
+pub fn exit_on_alloc_error() -> ! {
+    super::os::exit(ALLOC_ERROR_EXIT_CODE as i32)
+}
+
+/// Returns unused memory at the end of the heap to the kernel. Returns
+/// whether anything was released.
+pub fn trim() -> bool {
+    // SAFETY: DLMALLOC access is guranteed to be safe because the lock gives us unique and non-reentrant access.
+    let _lock = lock::lock();
+    unsafe { DLMALLOC.trim(0) }
+}
 
 mod lock {
-    use crate::sync::atomic::{AtomicI32, Ordering::SeqCst};
+    use crate::sys::locks::Mutex;
 
-    static LOCKED: AtomicI32 = AtomicI32::new(0);
+    // Threads waiting for the heap sleep in the ticktimer rather than
+    // spinning. The mutex only needs the ticktimer connection, which is made
+    // without allocating, so it works before threads or the runtime are set
+    // up and can't recurse into the allocator.
+    static LOCK: Mutex = Mutex::new();
 
     pub struct DropLock;
 
     pub fn lock() -> DropLock {
-        loop {
-            if LOCKED.swap(1, SeqCst) == 0 {
-                return DropLock;
-            }
-            xous::syscall::yield_slice();
-        }
+        unsafe { LOCK.lock() };
+        DropLock
+    }
+
+    pub fn try_lock() -> Option<DropLock> {
+        if unsafe { LOCK.try_lock() } { Some(DropLock) } else { None }
     }
 
     impl Drop for DropLock {
         fn drop(&mut self) {
-            let r = LOCKED.swap(0, SeqCst);
-            debug_assert_eq!(r, 1);
-            // this yield_slice() will allow any waiting thread to wake up and
-            // do its thing -- without it, a waiting thread could be starved if
-            // the code continues executing and within the same quanta it grabs
-            // the lock again.
-            xous::syscall::yield_slice();
+            unsafe { LOCK.unlock() };
         }
     }
 }
@@ -1,36 +1,46 @@
+use super::params;
 use crate::ffi::OsString;
 use crate::fmt;
+use crate::vec;
 
-pub struct Args {}
+pub struct Args {
+    iter: vec::IntoIter<OsString>,
+}
 
+/// Returns the arguments the loader passed in its parameter block. Without
+/// one, the only argument is the program's name, if the loader gave that.
 pub fn args() -> Args {
-    Args {}
+    let args = params::block(params::ARGS_TAG)
+        .and_then(params::decode_args)
+        .or_else(|| Some(vec![super::os::current_exe().ok()?.into_os_string()]))
+        .unwrap_or_default();
+    Args { iter: args.into_iter() }
 }
 
 impl fmt::Debug for Args {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_list().finish()
+        f.debug_list().entries(self.iter.as_slice()).finish()
     }
 }
 
 impl Iterator for Args {
     type Item = OsString;
     fn next(&mut self) -> Option<OsString> {
-        None
+        self.iter.next()
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(0))
+        self.iter.size_hint()
     }
 }
 
 impl ExactSizeIterator for Args {
     fn len(&self) -> usize {
-        0
+        self.iter.len()
     }
 }
 
 impl DoubleEndedIterator for Args {
     fn next_back(&mut self) -> Option<OsString> {
-        None
+        self.iter.next_back()
     }
 }
@@ -0,0 +1,142 @@
+//! 64-bit atomic operations for rv32.
+//!
+//! The riscv32 `A` extension only provides 32-bit atomics, so LLVM lowers
+//! every 64-bit atomic operation to one of the `__atomic_*_8` library calls
+//! below. These are implemented by taking one of a small number of locks,
+//! selected by the address being operated on, which makes them correct with
+//! respect to each other across threads. Mixing 64-bit and narrower atomic
+//! accesses to the same location is not supported.
+
+use super::services::ticktimer_scalar;
+use core::sync::atomic::{AtomicBool, Ordering};
+use xous::syscall::yield_slice;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of locks to spread addresses across. Must be a power of two.
+const LOCK_COUNT: usize = 16;
+
+/// How many times a thread yields while waiting for a lock before it starts
+/// sleeping instead.
+const YIELDS_BEFORE_SLEEP: usize = 16;
+
+static LOCKS: [AtomicBool; LOCK_COUNT] = {
+    const UNLOCKED: AtomicBool = AtomicBool::new(false);
+    [UNLOCKED; LOCK_COUNT]
+};
+
+struct AddressGuard(&'static AtomicBool);
+
+impl AddressGuard {
+    fn lock(ptr: *const u64) -> AddressGuard {
+        // 64-bit values are 8-byte aligned, so the bottom three bits carry no
+        // information.
+        let lock = &LOCKS[(ptr as usize >> 3) & (LOCK_COUNT - 1)];
+        // This is a plain test-and-set lock, which is neither fair nor
+        // wait-free: whichever thread tries when it is free gets it. The
+        // holder only keeps it for a few instructions, but it can be
+        // preempted in the middle of them, so a waiter gives up its quantum
+        // rather than spinning through it. Yielding only lets threads of the
+        // same priority run, so a holder of lower priority could wait
+        // forever behind a spinning waiter. After a few tries the waiter
+        // sleeps instead, which lets every other thread run.
+        let mut tries = 0;
+        while lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err()
+        {
+            if tries < YIELDS_BEFORE_SLEEP {
+                tries += 1;
+                yield_slice();
+            } else {
+                ticktimer_scalar(1 /* SleepMs */, 1, 0, 0, 0).ok();
+            }
+        }
+        AddressGuard(lock)
+    }
+}
+
+impl Drop for AddressGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Performs `op` on the value at `ptr` while holding its lock, returning the
+/// value that was there previously.
+#[inline(always)]
+unsafe fn rmw(ptr: *mut u64, op: impl FnOnce(u64) -> u64) -> u64 {
+    let _guard = AddressGuard::lock(ptr);
+    unsafe {
+        let old = ptr.read_volatile();
+        ptr.write_volatile(op(old));
+        old
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_load_8(ptr: *const u64, _order: i32) -> u64 {
+    let _guard = AddressGuard::lock(ptr);
+    unsafe { ptr.read_volatile() }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_store_8(ptr: *mut u64, val: u64, _order: i32) {
+    let _guard = AddressGuard::lock(ptr);
+    unsafe { ptr.write_volatile(val) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_exchange_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |_| val) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_compare_exchange_8(
+    ptr: *mut u64,
+    expected: *mut u64,
+    desired: u64,
+    _success: i32,
+    _failure: i32,
+) -> bool {
+    let _guard = AddressGuard::lock(ptr);
+    unsafe {
+        let current = ptr.read_volatile();
+        if current == *expected {
+            ptr.write_volatile(desired);
+            true
+        } else {
+            *expected = current;
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_add_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| old.wrapping_add(val)) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_sub_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| old.wrapping_sub(val)) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_and_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| old & val) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_or_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| old | val) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_xor_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| old ^ val) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __atomic_fetch_nand_8(ptr: *mut u64, val: u64, _order: i32) -> u64 {
+    unsafe { rmw(ptr, |old| !(old & val)) }
+}
@@ -0,0 +1,77 @@
+use super::LOCK_COUNT;
+use crate::sync::atomic::{AtomicU64, Ordering};
+use crate::sync::{Arc, Barrier};
+use crate::thread;
+
+const THREADS: usize = 8;
+const ROUNDS: u64 = 10_000;
+
+/// An increment with bits in both halves, so that a torn update would show.
+const STEP: u64 = 1 << 32 | 1;
+
+/// Bumps the counters at index 0 and `stride` from several threads at once,
+/// each thread starting on a different one, and checks that no update was
+/// lost.
+fn bump_concurrently(stride: usize) {
+    let counters: Arc<[AtomicU64; LOCK_COUNT + 1]> =
+        Arc::new([(); LOCK_COUNT + 1].map(|_| AtomicU64::new(0)));
+    let start = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let counters = counters.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                start.wait();
+                for round in 0..ROUNDS as usize {
+                    let counter = &counters[(i + round) % 2 * stride];
+                    counter.fetch_add(STEP, Ordering::Relaxed);
+                    assert_eq!(counter.load(Ordering::Relaxed) % STEP, 0);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let total = counters[0].load(Ordering::Relaxed) + counters[stride].load(Ordering::Relaxed);
+    assert_eq!(total, STEP * THREADS as u64 * ROUNDS);
+}
+
+#[test]
+fn counters() {
+    bump_concurrently(1);
+}
+
+#[test]
+fn counters_sharing_a_lock() {
+    // Counters `LOCK_COUNT` apart are guarded by the same lock, so every
+    // update contends with the other counter's as well.
+    bump_concurrently(LOCK_COUNT);
+}
+
+#[test]
+fn compare_exchange_under_contention() {
+    let value = Arc::new(AtomicU64::new(0));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let value = value.clone();
+            thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    let mut current = value.load(Ordering::Relaxed);
+                    while let Err(actual) = value.compare_exchange_weak(
+                        current,
+                        current + STEP,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    ) {
+                        current = actual;
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(value.load(Ordering::Relaxed), STEP * THREADS as u64 * ROUNDS);
+}
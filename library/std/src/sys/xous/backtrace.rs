@@ -0,0 +1,72 @@
+//! Backtraces for panics, by walking frame pointers.
+//!
+//! Only return addresses are printed (see below), and frame pointers are
+//! enough to find those without going through the unwinder. The target spec
+//! keeps them in every function (`"frame-pointer": "always"`), so the
+//! standard library and programs built for it have them without being
+//! asked. On RISC-V, a function's frame pointer is the stack pointer on
+//! entry, and the return address and the caller's frame pointer are the two
+//! words below it.
+//!
+//! Nothing is symbolized on the device. The return addresses are printed
+//! as-is, and can be turned into function names and lines with the program's
+//! ELF, for example with `addr2line -e program.elf -f -C -i`.
+
+use crate::arch::asm;
+use crate::backtrace_rs::PrintFmt;
+use crate::io::{self, Write};
+use crate::mem::size_of;
+use crate::sys_common::thread_info;
+
+/// The most frames to print, in case the frames are corrupt in a way that
+/// still looks valid.
+const MAX_FRAMES: usize = 100;
+
+/// Prints the return address of each frame above this one, up to the top of
+/// the current thread's stack. Doesn't allocate, so it is safe to use while
+/// panicking.
+///
+/// Each frame pointer is checked to be within the stack, and above the one
+/// before it, before anything is read through it, so a corrupt frame ends
+/// the backtrace instead of faulting.
+///
+/// Short backtraces are the same as full ones, since telling the standard
+/// library's own frames apart would take symbols.
+#[inline(never)]
+pub fn print(w: &mut dyn Write, _format: PrintFmt) -> io::Result<()> {
+    writeln!(w, "stack backtrace:")?;
+    let top = match thread_info::stack_guard() {
+        Some(stack) => stack.end,
+        None => {
+            return writeln!(w, "note: the bounds of this thread's stack are unknown");
+        }
+    };
+    let bottom = super::thread::stack_pointer();
+
+    let mut fp: usize;
+    unsafe { asm!("mv {}, s0", out(reg) fp, options(nomem, nostack)) };
+    let mut index = 0;
+    while index < MAX_FRAMES
+        && fp % size_of::<usize>() == 0
+        && fp >= bottom + 2 * size_of::<usize>()
+        && fp <= top
+    {
+        let (return_address, caller_fp) = unsafe {
+            let words = fp as *const usize;
+            (*words.sub(1), *words.sub(2))
+        };
+        if return_address == 0 {
+            break;
+        }
+        writeln!(w, "{index:4}: {return_address:#010x}")?;
+        index += 1;
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+    writeln!(
+        w,
+        "note: symbolize these addresses with the program's ELF, for example with `addr2line`"
+    )
+}
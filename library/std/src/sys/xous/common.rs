@@ -9,21 +9,81 @@ pub mod memchr {
 use crate::os::raw::c_char;
 
 extern "C" {
-    fn main() -> u32;
+    fn main() -> i32;
 }
 
+/// The entry point the loader starts a program at.
+///
+/// A program that returns from `main` winds down in this order, which
+/// programs can rely on:
+///
+/// 1. `main` itself runs the runtime cleanup once the program's own `main`
+///    has returned: stdout is flushed and left unbuffered, the console is
+///    put back in cooked mode, and the connections the standard library
+///    made for itself are closed (`services::disconnect_all`).
+/// 2. The main thread's thread-locals are dropped. Their destructors can
+///    still print or use any other service, which connects again as needed,
+///    and their output is not buffered.
+/// 3. The process exits with the code `main` returned, through `os::exit`.
+///
+/// `process::exit` runs step 1 and then exits, without step 2, as it does
+/// on other platforms. `abort` does neither.
 #[no_mangle]
-pub fn _start() {
-    xous::syscall::terminate_process(unsafe { main() });
+pub extern "C" fn _start(eh_frame: usize, params_address: usize) {
+    // Nothing can touch a thread-local before this.
+    super::thread_local_key::init_tls();
+    register_eh_frame(eh_frame);
+    super::params::set_address(params_address);
+    super::thread::set_main_stack_top(super::thread::stack_pointer());
+    let code = unsafe { main() };
+    // `main` has already run the runtime cleanup, but the main thread's
+    // thread-locals are still alive, and nothing else will drop them.
+    unsafe { super::thread_local_key::run_dtors() };
+    super::os::exit(code)
 }
 
+/// Tells the unwinder where the program's `.eh_frame` is, as passed to
+/// `_start` by the loader. The unwinder has no way of finding it by itself, so
+/// nothing can unwind until this has been done.
+#[cfg_attr(not(panic = "unwind"), allow(unused_variables))]
+fn register_eh_frame(eh_frame: usize) {
+    #[cfg(panic = "unwind")]
+    if eh_frame != 0 {
+        extern "C" {
+            fn __register_frame(begin: *const u8);
+        }
+        unsafe { __register_frame(eh_frame as *const u8) };
+    }
+}
+
+// The unwinder first looks for an `.eh_frame` between these symbols, which
+// the program's link script may define. These empty, weak definitions stand
+// in for them otherwise, which leaves what `register_eh_frame` registered.
+#[cfg(panic = "unwind")]
+core::arch::global_asm!(
+    ".pushsection .rodata.__eh_frame_placeholder,\"a\",@progbits",
+    ".weak __eh_frame_start",
+    ".weak __eh_frame_end",
+    ".weak __eh_frame_hdr_start",
+    ".weak __eh_frame_hdr_end",
+    "__eh_frame_start:",
+    "__eh_frame_end:",
+    "__eh_frame_hdr_start:",
+    "__eh_frame_hdr_end:",
+    ".popsection",
+);
+
 #[cfg(not(test))]
 pub unsafe fn init(_argc: isize, _argv: *const *const u8) {
 }
 
 // SAFETY: must be called only once during runtime cleanup.
 // NOTE: this is not guaranteed to run, for example when the program aborts.
-pub unsafe fn cleanup() {}
+pub unsafe fn cleanup() {
+    super::stdio::restore_cooked_mode();
+    // Output has been flushed by now, and the console is done with.
+    super::services::disconnect_all();
+}
 
 pub fn unsupported<T>() -> std_io::Result<T> {
     Err(unsupported_err())
@@ -33,16 +93,24 @@ pub fn unsupported_err() -> std_io::Error {
     std_io::Error::new(std_io::ErrorKind::Other, "operation not supported on this platform")
 }
 
-pub fn decode_error_kind(_code: i32) -> crate::io::ErrorKind {
-    crate::io::ErrorKind::Other
-}
+pub use super::error::decode_error_kind;
+
+/// The exit code of a process that aborted.
+pub const ABORT_EXIT_CODE: u32 = 134;
 
+/// Reports the abort through the panic output, which works at any point in
+/// the life of the process and never panics itself, and then terminates the
+/// process with `ABORT_EXIT_CODE`.
 pub fn abort_internal() -> ! {
-    core::intrinsics::abort();
-}
+    use crate::io::Write;
 
-pub fn hashmap_random_keys() -> (u64, u64) {
-    (1, 2)
+    if let Some(mut out) = super::stdio::panic_output() {
+        let name = super::params::block(super::params::NAME_TAG).unwrap_or(b"process");
+        out.write_all(name).ok();
+        let tid = xous::current_tid().map(|tid| tid as u32).unwrap_or_default();
+        out.write_fmt(format_args!(" (thread {tid}): abort called\n")).ok();
+    }
+    xous::syscall::terminate_process(ABORT_EXIT_CODE)
 }
 
 // This enum is used as the storage for a bunch of types which can't actually
@@ -0,0 +1,275 @@
+//! Raw OS error codes.
+//!
+//! Xous has no errno, so the standard library gives each failure a service
+//! can report its own code. Errors built from these codes carry them as
+//! `raw_os_error()`, and get their `ErrorKind` and message from the tables
+//! below. The numbering is stable:
+//!
+//! * `1..NET_ERROR_BASE` are kernel errors, numbered as in `xous::Error`.
+//! * `NET_ERROR_BASE + n` is error `n` from the network service, numbered
+//!   as in its `NetError` (see `net/src/api.rs` in the network server).
+//! * `PDDB_ERROR_BASE + n` is result code `n` from the PDDB's file interface.
+//!   Those codes belong to the standard library's protocol rather than to
+//!   the PDDB's own API, so they are defined here:
+//!
+//!   | Code | Meaning                                   | `ErrorKind`         |
+//!   |------|-------------------------------------------|---------------------|
+//!   | 1    | no such key or dict                       | `NotFound`          |
+//!   | 2    | path names a dict                         | `IsADirectory`      |
+//!   | 3    | key or dict already exists                | `AlreadyExists`     |
+//!   | 4    | path names a key                          | `NotADirectory`     |
+//!   | 5    | key is open                               | `ResourceBusy`      |
+//!   | 6    | dicts can't be renamed                    | `Unsupported`       |
+//!   | 7    | dict is not empty                         | `DirectoryNotEmpty` |
+//!   | 8    | not supported by the PDDB                 | `Unsupported`       |
+//!   | 9    | the PDDB is full                          | `StorageFull`       |
+//!   | 10   | basis is locked, or access is denied      | `PermissionDenied`  |
+//!   | 11   | no such basis                             | `NotFound`          |
+//!   | 12   | key is locked                             | `WouldBlock`        |
+//!   | 13   | timed out waiting for a lock              | `TimedOut`          |
+//!   | 14   | key is open for writing elsewhere         | `ResourceBusy`      |
+//!   | 15   | key is too large                          | `FileTooLarge`      |
+//!   | 16   | invalid key, dict or basis name           | `InvalidFilename`   |
+//!   | 17   | flash could not be read or written        | `Other`             |
+//!
+//!   Anything else is an internal error of the PDDB.
+//! * `LOADER_ERROR_BASE + n` is result code `n` from the process loader,
+//!   another protocol of the standard library's own:
+//!
+//!   | Code | Meaning                                   | `ErrorKind`         |
+//!   |------|-------------------------------------------|---------------------|
+//!   | 1    | no such program                           | `NotFound`          |
+//!   | 2    | no free process slots                     | `ResourceBusy`      |
+//!   | 3    | not enough memory to load the program     | `OutOfMemory`       |
+//!   | 4    | malformed spawn request                   | `InvalidInput`      |
+//!   | 5    | process is still running                  | `WouldBlock`        |
+//!   | 6    | pipe is closed at the other end           | `BrokenPipe`        |
+//!   | 7    | process has already exited                | `InvalidInput`      |
+//!   | 8    | pipe is empty or full                     | `WouldBlock`        |
+//!
+//!   Anything else is an internal error of the loader.
+//! * `DNS_ERROR_BASE + n` is error `n` from the DNS resolver, numbered as in
+//!   its `DnsResponseCode` (see `dns/src/api.rs` in the resolver). Codes 1 to
+//!   5 are the response codes of RFC 1035, and 9 is the resolver running out
+//!   of the time the lookup gave it.
+
+use crate::cell::Cell;
+use crate::io;
+
+#[cfg(test)]
+mod tests;
+
+pub const NET_ERROR_BASE: i32 = 0x100;
+pub const PDDB_ERROR_BASE: i32 = 0x200;
+pub const LOADER_ERROR_BASE: i32 = 0x300;
+pub const DNS_ERROR_BASE: i32 = 0x400;
+
+thread_local! { static ERRNO: Cell<i32> = Cell::new(0) }
+
+/// Returns the code of the last error this thread got from a service.
+pub fn errno() -> i32 {
+    ERRNO.try_with(|errno| errno.get()).unwrap_or(0)
+}
+
+pub fn set_errno(code: i32) {
+    ERRNO.try_with(|errno| errno.set(code)).ok();
+}
+
+/// Records `code` as this thread's last error and turns it into an
+/// `io::Error`.
+fn raw_error(code: i32) -> io::Error {
+    set_errno(code);
+    io::Error::from_raw_os_error(code)
+}
+
+/// Converts an error returned by the kernel.
+pub fn xous_error(e: xous::Error) -> io::Error {
+    raw_error(e.to_usize() as i32)
+}
+
+/// Converts an error code reported by the network service.
+pub fn net_error(code: u8) -> io::Error {
+    raw_error(NET_ERROR_BASE + code as i32)
+}
+
+/// Converts a result code reported by the process loader. Codes too large
+/// to belong to it are all treated as the same internal error.
+pub fn loader_error(code: usize) -> io::Error {
+    raw_error(LOADER_ERROR_BASE + code.min(0xff) as i32)
+}
+
+/// Converts a result code reported by the PDDB's file interface. Codes too
+/// large to belong to it are all treated as the same internal error.
+pub fn pddb_error(code: usize) -> io::Error {
+    raw_error(PDDB_ERROR_BASE + code.min(0xff) as i32)
+}
+
+/// Converts an error code reported by the DNS resolver.
+pub fn dns_error(code: u8) -> io::Error {
+    raw_error(DNS_ERROR_BASE + code as i32)
+}
+
+pub fn decode_error_kind(code: i32) -> io::ErrorKind {
+    use io::ErrorKind::*;
+    match code {
+        // BadAlignment, BadAddress, InvalidString
+        1 | 2 | 7 => InvalidInput,
+        // OutOfMemory
+        3 => OutOfMemory,
+        // MemoryInUse, InterruptInUse
+        4 | 6 => ResourceBusy,
+        // InterruptNotFound, ServerNotFound, ProcessNotFound
+        5 | 9 | 10 => NotFound,
+        // ServerExists
+        8 => AlreadyExists,
+        // Timeout
+        13 => TimedOut,
+        // ServerQueueFull, ThreadNotAvailable
+        15 | 16 => WouldBlock,
+        // UnhandledSyscall
+        17 => Unsupported,
+        // InvalidSyscall, InvalidThread, InvalidPID, InvalidLimit
+        18 | 20 | 21 | 27 => InvalidInput,
+        // ShareViolation, AccessDenied
+        19 | 23 => PermissionDenied,
+
+        c if c > DNS_ERROR_BASE => match c - DNS_ERROR_BASE {
+            1 => InvalidInput,
+            3 => NotFound,
+            4 => Unsupported,
+            5 => ConnectionRefused,
+            7 => NetworkUnreachable,
+            8 => NotConnected,
+            9 => TimedOut,
+            _ => Other,
+        },
+        c if c > LOADER_ERROR_BASE => match c - LOADER_ERROR_BASE {
+            1 => NotFound,
+            2 => ResourceBusy,
+            3 => OutOfMemory,
+            4 => InvalidInput,
+            5 => WouldBlock,
+            6 => BrokenPipe,
+            7 => InvalidInput,
+            8 => WouldBlock,
+            _ => Other,
+        },
+        c if c > PDDB_ERROR_BASE => match c - PDDB_ERROR_BASE {
+            1 | 11 => NotFound,
+            2 => IsADirectory,
+            3 => AlreadyExists,
+            4 => NotADirectory,
+            5 | 14 => ResourceBusy,
+            6 | 8 => Unsupported,
+            7 => DirectoryNotEmpty,
+            9 => StorageFull,
+            10 => PermissionDenied,
+            12 => WouldBlock,
+            13 => TimedOut,
+            15 => FileTooLarge,
+            16 => InvalidFilename,
+            _ => Other,
+        },
+        c if c > NET_ERROR_BASE => match c - NET_ERROR_BASE {
+            1 => AddrNotAvailable,
+            2 => ResourceBusy,
+            3 => PermissionDenied,
+            4 => InvalidInput,
+            5 => NotConnected,
+            7 => AddrInUse,
+            8 => TimedOut,
+            9 => WouldBlock,
+            _ => Other,
+        },
+        _ => Other,
+    }
+}
+
+pub fn error_string(code: i32) -> &'static str {
+    match code {
+        0 => "operation successful",
+        1 => "address is not correctly aligned",
+        2 => "bad address",
+        3 => "out of memory",
+        4 => "memory is already in use",
+        5 => "interrupt not found",
+        6 => "interrupt is already in use",
+        7 => "invalid string",
+        8 => "server already exists",
+        9 => "server not found",
+        10 => "process not found",
+        11 => "process is not a child of the caller",
+        12 => "process has terminated",
+        13 => "operation timed out",
+        14 => "kernel internal error",
+        15 => "server queue is full",
+        16 => "no thread available",
+        17 => "unhandled syscall",
+        18 => "invalid syscall",
+        19 => "memory sharing violation",
+        20 => "invalid thread",
+        21 => "invalid process ID",
+        22 => "unknown kernel error",
+        23 => "access denied",
+        24 => "used before initialization",
+        25 => "memory freed twice",
+        26 => "debugging in progress",
+        27 => "invalid limit",
+
+        c if c > DNS_ERROR_BASE => match c - DNS_ERROR_BASE {
+            1 => "DNS server could not understand the query",
+            2 => "DNS server failed",
+            3 => "no such host",
+            4 => "DNS server does not support the query",
+            5 => "DNS server refused the query",
+            7 => "network error during DNS lookup",
+            8 => "no DNS server is configured",
+            9 => "DNS lookup timed out",
+            _ => "unknown DNS error",
+        },
+        c if c > LOADER_ERROR_BASE => match c - LOADER_ERROR_BASE {
+            1 => "no such program",
+            2 => "no free process slots",
+            3 => "not enough memory to load the program",
+            4 => "malformed spawn request",
+            5 => "process is still running",
+            6 => "pipe is closed at the other end",
+            7 => "process has already exited",
+            8 => "pipe is empty or full",
+            _ => "loader internal error",
+        },
+        c if c > PDDB_ERROR_BASE => match c - PDDB_ERROR_BASE {
+            1 => "no such key or dict",
+            2 => "path names a dict",
+            3 => "key or dict already exists",
+            4 => "path names a key",
+            5 => "key is open",
+            6 => "dicts can't be renamed",
+            7 => "dict is not empty",
+            8 => "the PDDB does not support this",
+            9 => "the PDDB is full",
+            10 => "basis is locked",
+            11 => "no such basis",
+            12 => "key is locked",
+            13 => "timed out waiting for a lock",
+            14 => "key is open for writing through another handle",
+            15 => "key is too large",
+            16 => "invalid key, dict or basis name",
+            17 => "PDDB could not read or write flash",
+            _ => "PDDB internal error",
+        },
+        c if c > NET_ERROR_BASE => match c - NET_ERROR_BASE {
+            1 => "address is unreachable",
+            2 => "socket in use",
+            3 => "network access denied",
+            4 => "invalid socket or argument",
+            5 => "connection has finished",
+            6 => "network library error",
+            7 => "address already in use",
+            8 => "network operation timed out",
+            9 => "network operation would block",
+            _ => "unknown network error",
+        },
+        _ => "unknown error",
+    }
+}
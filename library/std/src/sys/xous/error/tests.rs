@@ -0,0 +1,93 @@
+use super::*;
+use io::ErrorKind::*;
+
+#[test]
+fn kinds() {
+    let table = [
+        // Kernel errors, numbered as in `xous::Error`.
+        (1, InvalidInput),
+        (3, OutOfMemory),
+        (4, ResourceBusy),
+        (9, NotFound),
+        (8, AlreadyExists),
+        (13, TimedOut),
+        (15, WouldBlock),
+        (17, Unsupported),
+        (23, PermissionDenied),
+        (14, Other),
+        (NET_ERROR_BASE - 1, Other),
+        // The network service.
+        (NET_ERROR_BASE + 1, AddrNotAvailable),
+        (NET_ERROR_BASE + 5, NotConnected),
+        (NET_ERROR_BASE + 7, AddrInUse),
+        (NET_ERROR_BASE + 8, TimedOut),
+        (NET_ERROR_BASE + 9, WouldBlock),
+        (NET_ERROR_BASE + 6, Other),
+        (NET_ERROR_BASE + 0xff, Other),
+        // The PDDB.
+        (PDDB_ERROR_BASE + 1, NotFound),
+        (PDDB_ERROR_BASE + 2, IsADirectory),
+        (PDDB_ERROR_BASE + 3, AlreadyExists),
+        (PDDB_ERROR_BASE + 4, NotADirectory),
+        (PDDB_ERROR_BASE + 5, ResourceBusy),
+        (PDDB_ERROR_BASE + 6, Unsupported),
+        (PDDB_ERROR_BASE + 7, DirectoryNotEmpty),
+        (PDDB_ERROR_BASE + 9, StorageFull),
+        (PDDB_ERROR_BASE + 10, PermissionDenied),
+        (PDDB_ERROR_BASE + 11, NotFound),
+        (PDDB_ERROR_BASE + 12, WouldBlock),
+        (PDDB_ERROR_BASE + 13, TimedOut),
+        (PDDB_ERROR_BASE + 14, ResourceBusy),
+        (PDDB_ERROR_BASE + 15, FileTooLarge),
+        (PDDB_ERROR_BASE + 16, InvalidFilename),
+        (PDDB_ERROR_BASE + 17, Other),
+        (PDDB_ERROR_BASE + 0xff, Other),
+        // The loader.
+        (LOADER_ERROR_BASE + 1, NotFound),
+        (LOADER_ERROR_BASE + 2, ResourceBusy),
+        (LOADER_ERROR_BASE + 3, OutOfMemory),
+        (LOADER_ERROR_BASE + 4, InvalidInput),
+        (LOADER_ERROR_BASE + 5, WouldBlock),
+        (LOADER_ERROR_BASE + 6, BrokenPipe),
+        (LOADER_ERROR_BASE + 7, InvalidInput),
+        (LOADER_ERROR_BASE + 8, WouldBlock),
+        (LOADER_ERROR_BASE + 0xff, Other),
+        // The DNS resolver.
+        (DNS_ERROR_BASE + 1, InvalidInput),
+        (DNS_ERROR_BASE + 2, Other),
+        (DNS_ERROR_BASE + 3, NotFound),
+        (DNS_ERROR_BASE + 4, Unsupported),
+        (DNS_ERROR_BASE + 5, ConnectionRefused),
+        (DNS_ERROR_BASE + 7, NetworkUnreachable),
+        (DNS_ERROR_BASE + 8, NotConnected),
+        (DNS_ERROR_BASE + 9, TimedOut),
+        (DNS_ERROR_BASE + 0xff, Other),
+        // Not from anything.
+        (0, Other),
+        (-1, Other),
+    ];
+    for (code, kind) in table {
+        assert_eq!(decode_error_kind(code), kind, "code {:#x}", code);
+    }
+}
+
+#[test]
+fn helpers_keep_their_ranges() {
+    assert_eq!(net_error(8).kind(), TimedOut);
+    assert_eq!(dns_error(3).kind(), NotFound);
+    assert_eq!(pddb_error(2).kind(), IsADirectory);
+    assert_eq!(loader_error(6).kind(), BrokenPipe);
+    assert_eq!(xous_error(xous::Error::ServerNotFound).kind(), NotFound);
+
+    // Codes too large for the PDDB or the loader mustn't spill into the next
+    // range.
+    assert_eq!(pddb_error(0x1234).raw_os_error(), Some(PDDB_ERROR_BASE + 0xff));
+    assert_eq!(loader_error(usize::MAX).raw_os_error(), Some(LOADER_ERROR_BASE + 0xff));
+}
+
+#[test]
+fn errno_follows_the_last_error() {
+    let e = pddb_error(1);
+    assert_eq!(errno(), e.raw_os_error().unwrap());
+    assert_eq!(error_string(errno()), "no such key or dict");
+}
@@ -0,0 +1,147 @@
+//! Buffers that programs can lend to servers of their own.
+
+use super::page_pool::{self, PagesBuf, PAGE_SIZE};
+use super::services::{self, Connection};
+use crate::fmt;
+use crate::io;
+use crate::ops::{Deref, DerefMut};
+
+/// Memory that can be lent to a server, zeroed when made.
+///
+/// Only whole pages can be lent, so the buffer starts on a page and its
+/// length is a whole number of pages, rounded up from the length it was made
+/// with.
+///
+/// Lending takes `&mut self` even when the server may only read the buffer,
+/// because the memory is not the program's to touch while it is lent.
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct LendBuffer {
+    pages: PagesBuf,
+}
+
+/// What a server answered a lend with: the offset and the valid length it
+/// set, each zero if it set none.
+#[unstable(feature = "xous_std", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lent {
+    pub offset: usize,
+    pub valid: usize,
+}
+
+#[unstable(feature = "xous_std", issue = "none")]
+impl LendBuffer {
+    /// Makes a buffer with room for at least `len` bytes. A `len` of zero
+    /// fails with `InvalidInput`, since there would be nothing to lend, and
+    /// so does one too large to ever be allocated.
+    pub fn new(len: usize) -> io::Result<LendBuffer> {
+        if len == 0 {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"a buffer to lend can't be empty",
+            ));
+        }
+        if len > isize::MAX as usize - PAGE_SIZE {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"a buffer to lend can't be that large",
+            ));
+        }
+        let count = len / PAGE_SIZE + (len % PAGE_SIZE != 0) as usize;
+        Ok(LendBuffer { pages: page_pool::get_pages(count) })
+    }
+
+    /// Lends the buffer to the server on `connection` with `opcode`, for it to
+    /// read but not change. `offset` and `valid` are passed along for the
+    /// server to interpret, and `valid` can't be more than the buffer's
+    /// length.
+    pub fn lend(
+        &mut self,
+        connection: &Connection,
+        opcode: usize,
+        offset: usize,
+        valid: usize,
+    ) -> io::Result<Lent> {
+        let range = self.range(valid)?;
+        let message = xous::Message::new_lend(
+            opcode,
+            range,
+            xous::MemoryAddress::new(offset),
+            xous::MemorySize::new(valid),
+        );
+        self.send(connection, message)
+    }
+
+    /// Lends the buffer to the server on `connection` with `opcode`, for it to
+    /// read and change, like `lend`. The server's changes are in the buffer
+    /// afterwards.
+    pub fn lend_mut(
+        &mut self,
+        connection: &Connection,
+        opcode: usize,
+        offset: usize,
+        valid: usize,
+    ) -> io::Result<Lent> {
+        let range = self.range(valid)?;
+        let message = xous::Message::new_lend_mut(
+            opcode,
+            range,
+            xous::MemoryAddress::new(offset),
+            xous::MemorySize::new(valid),
+        );
+        self.send(connection, message)
+    }
+
+    fn range(&mut self, valid: usize) -> io::Result<xous::MemoryRange> {
+        if valid > self.pages.len() {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"valid length is past the end of the buffer",
+            ));
+        }
+        Ok(self.pages.range())
+    }
+
+    /// Sends a lend of the buffer, and checks that the reply is the buffer
+    /// coming back, with a valid length that fits in it.
+    fn send(&self, connection: &Connection, message: xous::Message) -> io::Result<Lent> {
+        match services::send(connection.cid(), message)? {
+            xous::Result::MemoryReturned(offset, valid) => {
+                let lent = Lent {
+                    offset: offset.map_or(0, |offset| offset.get()),
+                    valid: valid.map_or(0, |valid| valid.get()),
+                };
+                if lent.valid > self.pages.len() {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::InvalidData,
+                        &"the server returned a valid length past the end of the buffer",
+                    ));
+                }
+                Ok(lent)
+            }
+            _ => Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                &"the server did not return the lent memory",
+            )),
+        }
+    }
+}
+
+impl Deref for LendBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.pages
+    }
+}
+
+impl DerefMut for LendBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.pages
+    }
+}
+
+impl fmt::Debug for LendBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LendBuffer").field("len", &self.pages.len()).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,658 @@
+use crate::ffi::OsString;
+use crate::fmt;
+use crate::io::{self, IoSlice, IoSliceMut, ReadBuf, SeekFrom};
+use crate::path::{Path, PathBuf};
+use crate::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use crate::sys::error::pddb_error;
+use crate::sys::time::SystemTime;
+use crate::sys::unsupported;
+use crate::time::Duration;
+use crate::vec;
+
+pub use crate::sys_common::fs::try_exists;
+
+mod pddb;
+
+pub use pddb::{Transfer, MAX_TRANSFER};
+
+/// A key in the PDDB, opened through the service's file interface.
+///
+/// The PDDB keeps recent writes in memory and commits them to flash later,
+/// so data that has been written can still be lost to a power failure until
+/// `fsync` is called. Closing a file doesn't commit it either.
+///
+/// Clones made by `duplicate` share the handle, and also its position, as
+/// duplicated file descriptors do on Unix.
+///
+/// Opening a key again gives an independent handle with its own position,
+/// and that works from any thread or process, whatever the modes. Every read
+/// or write is atomic with respect to the others on the same key, though a
+/// write of more than `pddb::MAX_TRANSFER` bytes is split into several, and
+/// a reader can see the parts land one at a time. Changes of length are seen
+/// by every handle right away. Truncating the key on open, or shortening it
+/// with `set_len`, fails with `ResourceBusy` while another handle has it
+/// open for writing.
+pub struct File(Arc<Handle>);
+
+struct Handle {
+    id: usize,
+    writable: bool,
+    append: bool,
+    /// The position the next read or write starts at.
+    pos: Mutex<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileAttr {
+    size: u64,
+    file_type: FileType,
+    /// Milliseconds since the Unix epoch, or zero if unknown.
+    modified: u64,
+}
+
+pub struct ReadDir {
+    root: Arc<PathBuf>,
+    location: pddb::Location,
+    entries: vec::IntoIter<pddb::ListEntry>,
+    /// Where the service should carry on listing, or `None` once it is done.
+    token: Option<usize>,
+}
+
+pub struct DirEntry {
+    root: Arc<PathBuf>,
+    name: OsString,
+    is_dict: bool,
+    /// The entry's metadata as of the listing, if the service sent it.
+    attr: Option<FileAttr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+/// The PDDB has no permissions, so everything is writable.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FilePermissions {
+    readonly: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FileType {
+    is_dict: bool,
+}
+
+#[derive(Debug)]
+pub struct DirBuilder {}
+
+impl FileAttr {
+    fn from_stat(stat: pddb::Stat) -> FileAttr {
+        FileAttr {
+            size: if stat.is_dict { 0 } else { stat.length },
+            file_type: FileType { is_dict: stat.is_dict },
+            modified: stat.modified,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn perm(&self) -> FilePermissions {
+        FilePermissions { readonly: false }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        if self.modified == 0 {
+            return Err(io::const_io_error!(
+                io::ErrorKind::Unsupported,
+                &"modification time is not recorded for this key",
+            ));
+        }
+        crate::sys::time::UNIX_EPOCH
+            .checked_add_duration(&Duration::from_millis(self.modified))
+            .ok_or(io::const_io_error!(io::ErrorKind::InvalidData, &"invalid modification time"))
+    }
+
+    pub fn accessed(&self) -> io::Result<SystemTime> {
+        unsupported()
+    }
+
+    pub fn created(&self) -> io::Result<SystemTime> {
+        unsupported()
+    }
+}
+
+impl FilePermissions {
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+}
+
+impl FileType {
+    pub fn is_dir(&self) -> bool {
+        self.is_dict
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dict
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for ReadDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // std::fs::ReadDir wraps this in "ReadDir()".
+        fmt::Debug::fmt(&*self.root, f)
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(DirEntry {
+                    root: self.root.clone(),
+                    name: entry.name,
+                    is_dict: entry.is_dict,
+                    attr: entry.stat.map(FileAttr::from_stat),
+                }));
+            }
+            let token = self.token?;
+            match pddb::list(&self.location, token) {
+                Ok((entries, next)) => {
+                    self.entries = entries.into_iter();
+                    self.token = next;
+                }
+                Err(e) => {
+                    self.token = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl DirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.root.join(&self.name)
+    }
+
+    pub fn file_name(&self) -> OsString {
+        self.name.clone()
+    }
+
+    /// Returns the metadata sent along with the listing, so it describes the
+    /// entry as it was when `read_dir` got to it, not as it is now. The
+    /// service is only asked again if it didn't send any.
+    pub fn metadata(&self) -> io::Result<FileAttr> {
+        match &self.attr {
+            Some(attr) => Ok(attr.clone()),
+            None => stat(&self.path()),
+        }
+    }
+
+    pub fn file_type(&self) -> io::Result<FileType> {
+        Ok(FileType { is_dict: self.is_dict })
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+        }
+    }
+
+    pub fn read(&mut self, read: bool) {
+        self.read = read;
+    }
+    pub fn write(&mut self, write: bool) {
+        self.write = write;
+    }
+    pub fn append(&mut self, append: bool) {
+        self.append = append;
+    }
+    pub fn truncate(&mut self, truncate: bool) {
+        self.truncate = truncate;
+    }
+    pub fn create(&mut self, create: bool) {
+        self.create = create;
+    }
+    pub fn create_new(&mut self, create_new: bool) {
+        self.create_new = create_new;
+    }
+
+    fn flags(&self) -> io::Result<usize> {
+        let access = match (self.read, self.write, self.append) {
+            (true, false, false) => pddb::OPEN_READ,
+            (false, true, false) => pddb::OPEN_WRITE,
+            (true, true, false) => pddb::OPEN_READ | pddb::OPEN_WRITE,
+            (false, _, true) => pddb::OPEN_WRITE | pddb::OPEN_APPEND,
+            (true, _, true) => pddb::OPEN_READ | pddb::OPEN_WRITE | pddb::OPEN_APPEND,
+            (false, false, false) => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::InvalidInput,
+                    &"invalid access mode",
+                ));
+            }
+        };
+
+        match (self.write, self.append) {
+            (true, false) => {}
+            (false, false) => {
+                if self.truncate || self.create || self.create_new {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::InvalidInput,
+                        &"invalid creation mode",
+                    ));
+                }
+            }
+            (_, true) => {
+                if self.truncate && !self.create_new {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::InvalidInput,
+                        &"invalid creation mode",
+                    ));
+                }
+            }
+        }
+
+        let creation = match (self.create, self.truncate, self.create_new) {
+            (false, false, false) => 0,
+            (true, false, false) => pddb::OPEN_CREATE,
+            (false, true, false) => pddb::OPEN_TRUNCATE,
+            (true, true, false) => pddb::OPEN_CREATE | pddb::OPEN_TRUNCATE,
+            (_, _, true) => pddb::OPEN_CREATE_NEW,
+        };
+        Ok(access | creation)
+    }
+}
+
+impl File {
+    pub fn open(path: &Path, opts: &OpenOptions) -> io::Result<File> {
+        let flags = opts.flags()?;
+        if ends_with_slash(path) {
+            // Only dicts can be named that way, and they can't be opened.
+            stat(path)?;
+            return Err(pddb_error(2 /* path names a dict */));
+        }
+        let id = pddb::open(&pddb::locate(path)?, flags)?;
+        Ok(File(Arc::new(Handle {
+            id,
+            writable: flags & pddb::OPEN_WRITE != 0,
+            append: flags & pddb::OPEN_APPEND != 0,
+            pos: Mutex::new(0),
+        })))
+    }
+
+    fn pos(&self) -> MutexGuard<'_, u64> {
+        self.0.pos.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn file_attr(&self) -> io::Result<FileAttr> {
+        pddb::stat_key(self.0.id).map(FileAttr::from_stat)
+    }
+
+    pub fn fsync(&self) -> io::Result<()> {
+        pddb::sync(self.0.id)
+    }
+
+    /// The PDDB commits a key's data and metadata together.
+    pub fn datasync(&self) -> io::Result<()> {
+        self.fsync()
+    }
+
+    /// Sets the length of the key. The position stays where it is, even if
+    /// that is now past the end.
+    pub fn truncate(&self, size: u64) -> io::Result<()> {
+        if !self.0.writable {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"file was not opened for writing",
+            ));
+        }
+        pddb::resize(self.0.id, size)
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pos = self.pos();
+        let count = pddb::read(self.0.id, *pos, buf)?;
+        *pos += count as u64;
+        Ok(count)
+    }
+
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        crate::io::default_read_vectored(|buf| self.read(buf), bufs)
+    }
+
+    #[inline]
+    pub fn is_read_vectored(&self) -> bool {
+        false
+    }
+
+    /// Reads like `read`, but straight into the unfilled part of `buf`,
+    /// which doesn't have to be initialized first.
+    pub fn read_buf(&self, buf: &mut ReadBuf<'_>) -> io::Result<()> {
+        let length = buf.remaining().min(MAX_TRANSFER);
+        let mut transfer = Transfer::new(length);
+        let count = self.read_transfer(&mut transfer, length)?;
+        buf.append(&transfer.data()[..count]);
+        Ok(())
+    }
+
+    /// Writes as much of `buf` as the PDDB will take, in pieces of up to
+    /// `MAX_TRANSFER` bytes.
+    pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.check_writable()?;
+        let mut pos = self.pos();
+        let mut written = 0;
+        while written < buf.len() {
+            let chunk = &buf[written..buf.len().min(written + pddb::MAX_TRANSFER)];
+            let count = match pddb::write(self.0.id, *pos, chunk) {
+                Ok(count) => count,
+                // Report what did get written, and leave the error for the
+                // next call.
+                Err(_) if written > 0 => break,
+                Err(e) => return Err(e),
+            };
+            *pos += count as u64;
+            written += count;
+            if count < chunk.len() {
+                break;
+            }
+        }
+        if self.0.append && written > 0 {
+            // The service put the data at the end, wherever that was, so
+            // find out where the position ended up.
+            *pos = pddb::len(self.0.id)?;
+        }
+        Ok(written)
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if !self.0.writable {
+            return Err(io::const_io_error!(
+                io::ErrorKind::PermissionDenied,
+                &"file was not opened for writing",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads up to `length` bytes into the data of `transfer`, which must be
+    /// large enough, as `read` would into a buffer. Lets a copy hand the
+    /// same buffer on without copying out of it first.
+    pub fn read_transfer(&self, transfer: &mut Transfer, length: usize) -> io::Result<usize> {
+        if length == 0 {
+            return Ok(0);
+        }
+        let mut pos = self.pos();
+        let count = pddb::read_into(self.0.id, *pos, transfer, length)?;
+        *pos += count as u64;
+        Ok(count)
+    }
+
+    /// Writes the first `length` bytes of the data of `transfer` in one
+    /// piece, as `write` would from a buffer, and returns how many the PDDB
+    /// took.
+    pub fn write_transfer(&self, transfer: &mut Transfer, length: usize) -> io::Result<usize> {
+        self.check_writable()?;
+        if length == 0 {
+            return Ok(0);
+        }
+        let mut pos = self.pos();
+        let count = pddb::write_from(self.0.id, *pos, transfer, length)?;
+        *pos += count as u64;
+        if self.0.append && count > 0 {
+            *pos = pddb::len(self.0.id)?;
+        }
+        Ok(count)
+    }
+
+    pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        crate::io::default_write_vectored(|buf| self.write(buf), bufs)
+    }
+
+    #[inline]
+    pub fn is_write_vectored(&self) -> bool {
+        false
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Moves the position. It can be put past the end of the key, where
+    /// reads return nothing, and a write fills the gap with zeroes.
+    pub fn seek(&self, pos: SeekFrom) -> io::Result<u64> {
+        let mut current = self.pos();
+        let new = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => current.checked_add_signed(offset),
+            SeekFrom::End(offset) => pddb::len(self.0.id)?.checked_add_signed(offset),
+        };
+        *current = new.ok_or(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            &"invalid seek to a negative or overflowing position",
+        ))?;
+        Ok(*current)
+    }
+
+    pub fn duplicate(&self) -> io::Result<File> {
+        Ok(File(self.0.clone()))
+    }
+
+    pub fn set_permissions(&self, _perm: FilePermissions) -> io::Result<()> {
+        unsupported()
+    }
+
+    pub fn lock(&self, exclusive: bool, block: bool, timeout: Option<Duration>) -> io::Result<()> {
+        let mode = if exclusive { pddb::LOCK_EXCLUSIVE } else { pddb::LOCK_SHARED };
+        pddb::lock(self.0.id, mode, block, timeout)
+    }
+
+    pub fn unlock(&self) -> io::Result<()> {
+        pddb::lock(self.0.id, pddb::UNLOCK, false, None)
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        pddb::close(self.id);
+    }
+}
+
+impl DirBuilder {
+    pub fn new() -> DirBuilder {
+        DirBuilder {}
+    }
+
+    pub fn mkdir(&self, p: &Path) -> io::Result<()> {
+        pddb::create_dict(&pddb::locate(p)?)
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File").field("handle", &self.0.id).finish()
+    }
+}
+
+/// Lists the dict at `p`. The first part of the listing is fetched right
+/// away, so that a missing dict is reported here rather than by the first
+/// call to `next`.
+pub fn readdir(p: &Path) -> io::Result<ReadDir> {
+    let location = pddb::locate(p)?;
+    let (entries, token) = pddb::list(&location, 0)?;
+    Ok(ReadDir { root: Arc::new(p.to_path_buf()), location, entries: entries.into_iter(), token })
+}
+
+/// Deletes the key at `p`. This fails with `ResourceBusy` while the key is
+/// open, unlike on Unix, where the file would live on until it was closed.
+pub fn unlink(p: &Path) -> io::Result<()> {
+    pddb::delete_key(&pddb::locate(p)?)
+}
+
+/// Moves a key, within its dict or to another one. A crash part way
+/// through leaves the key under either its old or its new name, never both
+/// or neither. Renaming a dict fails with `Unsupported`.
+pub fn rename(old: &Path, new: &Path) -> io::Result<()> {
+    let (old, new) = (pddb::locate(old)?, pddb::locate(new)?);
+    if !old.same_basis(&new) {
+        return Err(io::const_io_error!(
+            io::ErrorKind::CrossesDevices,
+            &"keys can't be renamed into another basis",
+        ));
+    }
+    pddb::rename(&old, &new)
+}
+
+pub fn set_perm(_p: &Path, _perm: FilePermissions) -> io::Result<()> {
+    unsupported()
+}
+
+pub fn rmdir(p: &Path) -> io::Result<()> {
+    pddb::delete_dict(&pddb::locate(p)?)
+}
+
+/// Has the PDDB delete the whole dict at once, unless the service is too old
+/// to do that, in which case the dict is emptied a key at a time.
+pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    match pddb::delete_tree(&pddb::locate(path)?) {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+        result => return result,
+    }
+    if !stat(path)?.file_type.is_dict {
+        return Err(pddb_error(4 /* path names a key */));
+    }
+    ignore_not_found(remove_dict_by_keys(path))
+}
+
+/// Deletes everything in the dict at `path`, and then the dict. Keys added
+/// meanwhile make the last step fail with `DirectoryNotEmpty`, so it starts
+/// over a few times before giving up. Anything that has already disappeared
+/// by the time it is deleted counts as deleted.
+fn remove_dict_by_keys(path: &Path) -> io::Result<()> {
+    const MAX_ATTEMPTS: usize = 5;
+    let mut attempts = 0;
+    loop {
+        for child in readdir(path)? {
+            let child = child?;
+            let path = child.path();
+            let result = if child.is_dict { remove_dict_by_keys(&path) } else { unlink(&path) };
+            ignore_not_found(result)?;
+        }
+        attempts += 1;
+        match rmdir(path) {
+            Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty && attempts < MAX_ATTEMPTS => {}
+            result => return result,
+        }
+    }
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+pub fn readlink(_p: &Path) -> io::Result<PathBuf> {
+    unsupported()
+}
+
+pub fn symlink(_original: &Path, _link: &Path) -> io::Result<()> {
+    unsupported()
+}
+
+/// The PDDB can't give one key two names, so this copies `src` to `dst`
+/// instead, the same way `copy` does: the two keys start out the same, but
+/// changes to either don't show through the other. `dst` must not exist, and
+/// a `src` that is a dict fails with `IsADirectory`.
+pub fn link(src: &Path, dst: &Path) -> io::Result<()> {
+    if stat(src)?.file_type.is_dict {
+        return Err(pddb_error(2 /* path names a dict */));
+    }
+    // Services that predate `COPY_NO_REPLACE` would replace `dst`, so it is
+    // checked here as well.
+    match stat(dst) {
+        Ok(_) => return Err(pddb_error(3 /* key or dict already exists */)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let (from, to) = (pddb::locate(src)?, pddb::locate(dst)?);
+    match pddb::copy(&from, &to, pddb::COPY_NO_REPLACE) {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            let mut reader = crate::fs::File::open(src)?;
+            let mut writer =
+                crate::fs::OpenOptions::new().write(true).create_new(true).open(dst)?;
+            io::copy(&mut reader, &mut writer).map(drop)
+        }
+        result => result.map(drop),
+    }
+}
+
+/// Tells keys from dicts, the same way `open` and `readdir` do: a path
+/// names a dict if the PDDB has a dict with all of its components as the
+/// name, and a key otherwise. Dicts have a size of zero. As on Unix, a path
+/// ending with a slash must name a dict.
+pub fn stat(p: &Path) -> io::Result<FileAttr> {
+    let attr = FileAttr::from_stat(pddb::stat(&pddb::locate(p)?)?);
+    if ends_with_slash(p) && !attr.file_type.is_dict {
+        return Err(pddb_error(4 /* path names a key */));
+    }
+    Ok(attr)
+}
+
+fn ends_with_slash(p: &Path) -> bool {
+    p.as_os_str().bytes().ends_with(b"/")
+}
+
+pub fn lstat(p: &Path) -> io::Result<FileAttr> {
+    // There are no symlinks.
+    stat(p)
+}
+
+/// There are no symlinks, so this only makes `p` absolute and tidies it up
+/// lexically, then checks that it exists. The PDDB only keeps a key or dict
+/// while the dicts above it exist, so checking the last component checks
+/// all of them.
+pub fn canonicalize(p: &Path) -> io::Result<PathBuf> {
+    let absolute = crate::sys::path::absolute(p)?;
+    stat(&absolute)?;
+    // Collecting the components drops any trailing slash.
+    Ok(absolute.components().collect())
+}
+
+/// Has the PDDB copy the key itself, rather than passing all of it through
+/// this process, unless the service is too old to do that.
+pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
+    match pddb::copy(&pddb::locate(from)?, &pddb::locate(to)?, 0) {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => crate::sys_common::fs::copy(from, to),
+        result => result,
+    }
+}
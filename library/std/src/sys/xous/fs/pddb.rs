@@ -0,0 +1,566 @@
+//! The standard library's side of the PDDB's file interface.
+//!
+//! A path names both a key and a dict. Its last component is the name of a
+//! key, and the components before it, joined with `/`, are the name of the
+//! dict holding that key: `/config/wifi/ssid` is the key `ssid` in the dict
+//! `config/wifi`. All of its components together are the name of a dict,
+//! `config/wifi/ssid`. The PDDB never lets a key and a dict with the same
+//! path exist at once, so a path refers to at most one of them. The root
+//! holds dicts only. Only `/` separates the components of a path, so names
+//! can contain colons; see `sys::path::is_sep_byte`.
+//!
+//! Paths normally refer to the default view of the PDDB, which merges all of
+//! the open bases. A path can instead name a single basis by starting with
+//! the basis's name and a colon, as in `secrets:/config/wifi/ssid`. Which
+//! names count is up to `sys::path::parse_prefix`, which this goes by.
+//!
+//! Requests that refer to a path lend a page that starts with its location:
+//! the basis, the dict and the key, each as a `u16` length followed by that
+//! many bytes. Requests about two paths have the second location right after
+//! the first. An empty basis means the default view of all open bases, and
+//! an empty key means the dict itself. The service overwrites the page with
+//! its reply, which starts with a `u32` result code that is zero on success.
+//! The result codes, and what they mean, are listed in `sys::error`.
+//!
+//! Requests on an open key carry the handle the service gave out for it in
+//! the upper bits of the opcode, like the network service's sockets. Reads
+//! and writes lend up to `TRANSFER_PAGES` pages, starting with a header of
+//! `offset: u64`, the position in the key, `length: u32`, the number of bytes
+//! to move, and `result: u32`. The data follows the header. The service
+//! replaces `length` with the number of bytes it moved, and `result` with its
+//! result code. Since the position travels with every request, handles never
+//! depend on state the service keeps for them between requests.
+//!
+//! A key can be open through any number of handles at once, in any mode and
+//! from any number of processes. The service handles the requests on a key
+//! one at a time, so each read or write happens entirely before or after any
+//! other: a read sees all of a write request or none of it, and never data
+//! torn between the two. A change of length, whether from a write past the
+//! end or a resize, is seen by every handle as soon as the request that made
+//! it has been answered. The only modes that conflict are those that would
+//! pull data out from under another writer: opening with `OPEN_TRUNCATE`, or
+//! making a key shorter, fails with code 14 while another handle has the key
+//! open for writing.
+//!
+//! All integers are little-endian.
+
+use super::super::params::Reader;
+use crate::ffi::{OsStr, OsString};
+use crate::io;
+use crate::path::{Component, Path, Prefix};
+use crate::sys::error::{pddb_error, xous_error};
+use crate::sys::services;
+use crate::sys_common::AsInner;
+use crate::time::Duration;
+
+const PAGE_SIZE: usize = 4096;
+
+#[repr(C, align(4096))]
+pub struct Page {
+    pub raw: [u8; PAGE_SIZE],
+}
+
+impl Page {
+    pub fn new() -> Page {
+        Page { raw: [0u8; PAGE_SIZE] }
+    }
+
+    fn range(&mut self) -> xous::MemoryRange {
+        unsafe { xous::MemoryRange::new(self as *mut Page as usize, PAGE_SIZE).unwrap() }
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_le_bytes(self.raw[at..at + 4].try_into().unwrap())
+    }
+}
+
+/// Flags passed when opening a key.
+pub const OPEN_READ: usize = 1 << 0;
+pub const OPEN_WRITE: usize = 1 << 1;
+/// Makes every write go to the end of the key, wherever the handle's
+/// position is. The service does this atomically, so appends from several
+/// handles never overwrite each other.
+pub const OPEN_APPEND: usize = 1 << 2;
+/// Empties the key.
+pub const OPEN_TRUNCATE: usize = 1 << 3;
+/// Creates the key if it doesn't exist. Its dict must exist already, unless
+/// it is the temp dict.
+pub const OPEN_CREATE: usize = 1 << 4;
+/// Creates the key, failing if it exists already.
+pub const OPEN_CREATE_NEW: usize = 1 << 5;
+
+/// The dict in the default view that `env::temp_dir` names as `/.tmp`
+/// unless `TMPDIR` says otherwise. Nothing creates it ahead of time, so
+/// creating a key or dict directly inside it creates it first if need be.
+const TEMP_DICT: &[u8] = b".tmp";
+
+/// Where a path lives in the PDDB.
+pub struct Location {
+    /// Empty for the default view.
+    basis: Vec<u8>,
+    dict: Vec<u8>,
+    key: Vec<u8>,
+}
+
+/// Works out which dict and key `path` refers to. Relative paths are taken
+/// from the working directory. Every path goes through `absolute` on the way
+/// to the service, so that spellings `Path` considers equal, such as `a/./b`
+/// and `a/b`, always reach the same location.
+pub fn locate(path: &Path) -> io::Result<Location> {
+    let path = crate::sys::path::absolute(path)?;
+    let mut basis = Vec::new();
+    let mut names: Vec<&OsStr> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::DeviceNS(name) => basis = name.as_inner().inner.to_vec(),
+                _ => {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::InvalidInput,
+                        &"unsupported path prefix",
+                    ));
+                }
+            },
+            Component::Normal(name) => names.push(name),
+            // `absolute` has already resolved `.` and `..`.
+            Component::RootDir | Component::CurDir | Component::ParentDir => {}
+        }
+    }
+
+    let key = names.pop().map(|name| name.as_inner().inner.to_vec()).unwrap_or_default();
+    let mut dict = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            dict.push(b'/');
+        }
+        dict.extend_from_slice(&name.as_inner().inner);
+    }
+    Ok(Location { basis, dict, key })
+}
+
+impl Location {
+    fn is_root(&self) -> bool {
+        self.dict.is_empty() && self.key.is_empty()
+    }
+
+    pub fn same_basis(&self, other: &Location) -> bool {
+        self.basis == other.basis
+    }
+
+    fn in_temp_dict(&self) -> bool {
+        self.basis.is_empty() && self.dict == TEMP_DICT
+    }
+
+    /// Writes the location into `page` at `at`, and returns where it ends.
+    fn write(&self, page: &mut Page, mut at: usize) -> io::Result<usize> {
+        for name in [&self.basis[..], &self.dict[..], &self.key[..]] {
+            let length = u16::try_from(name.len())
+                .ok()
+                .filter(|&length| at + 2 + length as usize <= PAGE_SIZE)
+                .ok_or(io::const_io_error!(io::ErrorKind::InvalidFilename, &"path is too long"))?;
+            page.raw[at..at + 2].copy_from_slice(&length.to_le_bytes());
+            page.raw[at + 2..at + 2 + name.len()].copy_from_slice(name);
+            at += 2 + name.len();
+        }
+        Ok(at)
+    }
+}
+
+/// A code past the ones the PDDB reports, for replies that don't follow the
+/// protocol, which are its internal errors.
+const INTERNAL_ERROR: usize = 0xff;
+
+/// Converts a reply of a kind the request can't get. A reply that is an
+/// error from the kernel keeps its code.
+fn unexpected_reply(reply: xous::Result) -> io::Error {
+    match reply {
+        xous::Result::Error(e) => xous_error(e),
+        _ => pddb_error(INTERNAL_ERROR),
+    }
+}
+
+/// Sends a request about `locations`, one after the other in the lent page,
+/// passing `arg` as the offset of the page, and returns the reply.
+fn request(opcode: usize, locations: &[&Location], arg: usize) -> io::Result<Page> {
+    let mut page = Page::new();
+    let mut at = 0;
+    for location in locations {
+        at = location.write(&mut page, at)?;
+    }
+    let range = page.range();
+    services::send(
+        services::pddb(),
+        xous::Message::new_lend_mut(opcode, range, xous::MemoryAddress::new(arg), None),
+    )?;
+    match page.u32(0) {
+        0 => Ok(page),
+        code => Err(pddb_error(code as usize)),
+    }
+}
+
+/// Runs `create`, which makes something at `location`. If that fails with
+/// `NotFound` because `location` is in the temp dict and the temp dict
+/// doesn't exist yet, creates it and runs `create` again.
+fn creating_temp_dict<T>(location: &Location, create: impl Fn() -> io::Result<T>) -> io::Result<T> {
+    match create() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && location.in_temp_dict() => {
+            let temp = Location { basis: Vec::new(), dict: Vec::new(), key: TEMP_DICT.to_vec() };
+            match request(49 /* CreateDictStd */, &[&temp], 0) {
+                // Another thread or process may have just made it.
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            create()
+        }
+        result => result,
+    }
+}
+
+/// Sends a request about the key open as `handle`, with a page for the reply.
+fn handle_request(opcode: usize, handle: usize) -> io::Result<Page> {
+    let mut page = Page::new();
+    let range = page.range();
+    services::send(
+        services::pddb(),
+        xous::Message::new_lend_mut(opcode | (handle << 16), range, None, None),
+    )?;
+    match page.u32(0) {
+        0 => Ok(page),
+        code => Err(pddb_error(code as usize)),
+    }
+}
+
+/// Opens the key at `location`, returning its handle. Opening it with
+/// `OPEN_TRUNCATE` fails with `ResourceBusy` while another handle has it
+/// open for writing.
+pub fn open(location: &Location, flags: usize) -> io::Result<usize> {
+    let open = || request(41 /* OpenKeyStd */, &[location], flags);
+    let reply = if flags & (OPEN_CREATE | OPEN_CREATE_NEW) != 0 {
+        creating_temp_dict(location, open)?
+    } else {
+        open()?
+    };
+    Ok(reply.u32(4) as usize)
+}
+
+/// The most pages lent for a single read or write.
+const TRANSFER_PAGES: usize = 16;
+const TRANSFER_HEADER: usize = 16;
+/// The most bytes moved by a single read or write.
+pub const MAX_TRANSFER: usize = TRANSFER_PAGES * PAGE_SIZE - TRANSFER_HEADER;
+
+/// A buffer for the data of one read or write, preceded by its header.
+pub struct Transfer {
+    pages: Vec<Page>,
+}
+
+impl Transfer {
+    /// Makes a buffer large enough to move `length` bytes, which must not
+    /// be more than `MAX_TRANSFER`.
+    pub fn new(length: usize) -> Transfer {
+        let count = (TRANSFER_HEADER + length + PAGE_SIZE - 1) / PAGE_SIZE;
+        Transfer { pages: (0..count).map(|_| Page::new()).collect() }
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        // SAFETY: the pages are contiguous and have no padding.
+        unsafe {
+            crate::slice::from_raw_parts_mut(
+                self.pages.as_mut_ptr() as *mut u8,
+                self.pages.len() * PAGE_SIZE,
+            )
+        }
+    }
+
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut self.bytes()[TRANSFER_HEADER..]
+    }
+
+    /// Sends the buffer with a request to move `length` bytes at `offset`,
+    /// and returns how many the service moved.
+    fn send(&mut self, opcode: usize, offset: u64, length: usize) -> io::Result<usize> {
+        let bytes = self.bytes();
+        bytes[0..8].copy_from_slice(&offset.to_le_bytes());
+        bytes[8..12].copy_from_slice(&(length as u32).to_le_bytes());
+        bytes[12..16].copy_from_slice(&0u32.to_le_bytes());
+        let range = unsafe {
+            xous::MemoryRange::new(self.pages.as_mut_ptr() as usize, self.pages.len() * PAGE_SIZE)
+                .unwrap()
+        };
+        services::send(services::pddb(), xous::Message::new_lend_mut(opcode, range, None, None))?;
+
+        let bytes = self.bytes();
+        match u32::from_le_bytes(bytes[12..16].try_into().unwrap()) {
+            0 => Ok((u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize).min(length)),
+            code => Err(pddb_error(code as usize)),
+        }
+    }
+}
+
+/// Reads from the key open as `handle`, starting `offset` bytes in. Returns
+/// the number of bytes read, which is zero at the end of the key, and at
+/// most `MAX_TRANSFER`.
+pub fn read(handle: usize, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let length = buf.len().min(MAX_TRANSFER);
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let mut transfer = Transfer::new(length);
+    let count = read_into(handle, offset, &mut transfer, length)?;
+    buf[..count].copy_from_slice(&transfer.data()[..count]);
+    Ok(count)
+}
+
+/// Reads like `read`, but into the data of `transfer`, which must be large
+/// enough for `length` bytes.
+pub fn read_into(
+    handle: usize,
+    offset: u64,
+    transfer: &mut Transfer,
+    length: usize,
+) -> io::Result<usize> {
+    transfer.send(42 | (handle << 16) /* ReadKeyStd */, offset, length)
+}
+
+/// What the service knows about a key or dict. Its reply to a stat request
+/// is the key's `length: u64`, its modification time in milliseconds since
+/// the Unix epoch as `modified: u64`, and `kind: u8`, which is 1 for a dict.
+/// Dicts have a length and modification time of zero, and so do keys written
+/// before the PDDB recorded modification times.
+pub struct Stat {
+    pub length: u64,
+    pub modified: u64,
+    pub is_dict: bool,
+}
+
+impl Stat {
+    fn parse(reply: &Page) -> Stat {
+        Stat {
+            length: u64::from_le_bytes(reply.raw[4..12].try_into().unwrap()),
+            modified: u64::from_le_bytes(reply.raw[12..20].try_into().unwrap()),
+            is_dict: reply.raw[20] == 1,
+        }
+    }
+}
+
+/// Looks up the key or dict at `location`. The service checks for a dict
+/// named by the whole location before it looks for the key, and reports the
+/// root of a basis, or of the default view, as a dict.
+pub fn stat(location: &Location) -> io::Result<Stat> {
+    Ok(Stat::parse(&request(47 /* StatPathStd */, &[location], 0)?))
+}
+
+/// Looks up the key open as `handle`.
+pub fn stat_key(handle: usize) -> io::Result<Stat> {
+    Ok(Stat::parse(&handle_request(48 /* StatKeyStd */, handle)?))
+}
+
+/// Deletes the key at `location`. Keys that are open through any handle,
+/// in this process or another, can't be deleted, since the PDDB has no way
+/// to keep a deleted key around until its last handle is closed.
+pub fn delete_key(location: &Location) -> io::Result<()> {
+    request(45 /* DeleteKeyStd */, &[location], 0).map(drop)
+}
+
+/// Creates an empty dict named by the whole of `location`. The dict holding
+/// it must exist already, unless it is the temp dict, and nothing may exist
+/// at `location` yet.
+pub fn create_dict(location: &Location) -> io::Result<()> {
+    creating_temp_dict(location, || request(49 /* CreateDictStd */, &[location], 0)).map(drop)
+}
+
+/// Deletes the dict named by the whole of `location`, which must be empty.
+pub fn delete_dict(location: &Location) -> io::Result<()> {
+    if location.is_root() {
+        return Err(io::const_io_error!(io::ErrorKind::ResourceBusy, &"can't remove the root"));
+    }
+    request(50 /* DeleteDictStd */, &[location], 0).map(drop)
+}
+
+/// Deletes the dict named by the whole of `location`, with every key and
+/// dict inside it, as a single update to the journal, so a crash leaves all
+/// of them or none. Nothing is deleted if any key inside is open. Services
+/// that predate this fail with `Unsupported`.
+pub fn delete_tree(location: &Location) -> io::Result<()> {
+    if location.is_root() {
+        return Err(io::const_io_error!(io::ErrorKind::ResourceBusy, &"can't remove the root"));
+    }
+    request(56 /* DeleteTreeStd */, &[location], 0).map(drop)
+}
+
+/// Moves the key at `from` to `to`, replacing any key already there. The
+/// PDDB makes the move as a single update to its journal, so a crash leaves
+/// the key under exactly one of the two names. Dicts can't be moved.
+pub fn rename(from: &Location, to: &Location) -> io::Result<()> {
+    request(51 /* RenameKeyStd */, &[from, to], 0).map(drop)
+}
+
+/// Flag for `copy` to fail with `AlreadyExists` if there is a key at `to`,
+/// rather than replace it. Services that predate the flag ignore it.
+pub const COPY_NO_REPLACE: usize = 1 << 0;
+
+/// Copies the key at `from` to `to` inside the PDDB, replacing any key
+/// already at `to` unless `flags` has `COPY_NO_REPLACE`, and returns the
+/// number of bytes copied. Services that predate this fail with
+/// `Unsupported`.
+pub fn copy(from: &Location, to: &Location, flags: usize) -> io::Result<u64> {
+    let reply = request(52 /* CopyKeyStd */, &[from, to], flags)?;
+    Ok(u64::from_le_bytes(reply.raw[4..12].try_into().unwrap()))
+}
+
+/// One entry of a dict's listing, named relative to the dict.
+pub struct ListEntry {
+    pub name: OsString,
+    pub is_dict: bool,
+    /// What the service knew about the entry when it was listed, if it said.
+    pub stat: Option<Stat>,
+}
+
+/// `kind` bit of a listed entry that is a dict.
+const LIST_DICT: u8 = 1;
+/// `kind` bit of a listed entry that is followed by its length and
+/// modification time.
+const LIST_STAT: u8 = 2;
+
+/// Lists part of the dict named by the whole of `location`. The service
+/// returns entries in byte order of their names, as many as fit in a page,
+/// preceded by `next: u32` and `count: u16`. Each entry is `kind: u8`, with
+/// bit 0 set for a dict, followed by its name as a length-prefixed string.
+/// If bit 1 of `kind` is set, the name is followed by `length: u64` and
+/// `modified: u64`, as in the reply to a stat request, which saves a request
+/// per entry for callers that want its metadata. Services that predate this
+/// leave it clear.
+///
+/// `token` is zero for the first part, and after that the `next` of the
+/// part before it. The service carries on after the last name it returned,
+/// so entries added or removed in between are either listed or not, but
+/// never listed twice. Returns the entries and the next token, which is
+/// `None` once the listing is complete.
+pub fn list(location: &Location, token: usize) -> io::Result<(Vec<ListEntry>, Option<usize>)> {
+    let reply = request(46 /* ListPathStd */, &[location], token)?;
+    let malformed = || pddb_error(INTERNAL_ERROR);
+    let mut reader = Reader(&reply.raw[4..]);
+    let next = reader.u32().ok_or_else(malformed)? as usize;
+    let count = reader.u16().ok_or_else(malformed)?;
+    let entries = (0..count)
+        .map(|_| {
+            let kind = reader.bytes(1)?[0];
+            let is_dict = kind & LIST_DICT != 0;
+            let name = reader.string()?;
+            let stat = if kind & LIST_STAT != 0 {
+                Some(Stat { length: reader.u64()?, modified: reader.u64()?, is_dict })
+            } else {
+                None
+            };
+            Some(ListEntry { name, is_dict, stat })
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(malformed)?;
+    Ok((entries, if next == 0 { None } else { Some(next) }))
+}
+
+/// Cuts the key open as `handle` short, or pads it with zeroes, so that it
+/// is `length` bytes long. Cutting it short fails with `ResourceBusy` while
+/// another handle has it open for writing.
+pub fn resize(handle: usize, length: u64) -> io::Result<()> {
+    match services::send(
+        services::pddb(),
+        xous::Message::new_blocking_scalar(
+            54 | (handle << 16), /* ResizeKeyStd */
+            length as u32 as usize,
+            (length >> 32) as usize,
+            0,
+            0,
+        ),
+    )? {
+        xous::Result::Scalar1(0) => Ok(()),
+        xous::Result::Scalar1(code) => Err(pddb_error(code)),
+        reply => Err(unexpected_reply(reply)),
+    }
+}
+
+/// Lock modes, as understood by `lock`.
+pub const UNLOCK: usize = 0;
+pub const LOCK_SHARED: usize = 1;
+pub const LOCK_EXCLUSIVE: usize = 2;
+
+/// Takes an advisory lock on the key open as `handle`, or with `UNLOCK`
+/// releases it. A handle holds at most one lock, so locking again changes
+/// its mode. Unless `block` is set, a conflicting lock makes this fail right
+/// away with `WouldBlock`. Otherwise the service holds off its reply until
+/// the lock can be taken, or until `timeout` has passed.
+///
+/// Locks belong to the handle, and the service drops them when the handle
+/// is closed or the process that opened it ends.
+pub fn lock(handle: usize, mode: usize, block: bool, timeout: Option<Duration>) -> io::Result<()> {
+    // As with the ticktimer, zero means no timeout, so round up.
+    let timeout = timeout.map_or(0, |dur| {
+        let millis = dur.as_nanos().saturating_add(999_999) / 1_000_000;
+        millis.clamp(1, usize::MAX as u128) as usize
+    });
+    match services::send(
+        services::pddb(),
+        xous::Message::new_blocking_scalar(
+            55 | (handle << 16), /* LockKeyStd */
+            mode,
+            if block { 0 } else { 1 },
+            timeout,
+            0,
+        ),
+    )? {
+        xous::Result::Scalar1(0) => Ok(()),
+        xous::Result::Scalar1(code) => Err(pddb_error(code)),
+        reply => Err(unexpected_reply(reply)),
+    }
+}
+
+/// Waits until everything written to the basis holding the key open as
+/// `handle` has been committed to flash.
+pub fn sync(handle: usize) -> io::Result<()> {
+    handle_request(53 /* SyncKeyStd */, handle).map(drop)
+}
+
+/// Returns the length of the key open as `handle`.
+pub fn len(handle: usize) -> io::Result<u64> {
+    Ok(stat_key(handle)?.length)
+}
+
+/// Writes to the key open as `handle`, starting `offset` bytes in and
+/// extending the key if that goes past its end. If `offset` itself is past
+/// the end, the service fills the gap with zeroes first. Handles opened with
+/// `OPEN_APPEND` ignore `offset` and always write at the end. Returns the
+/// number of bytes the service took, which may be fewer than it was given,
+/// and is at most `MAX_TRANSFER`.
+pub fn write(handle: usize, offset: u64, buf: &[u8]) -> io::Result<usize> {
+    let length = buf.len().min(MAX_TRANSFER);
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let mut transfer = Transfer::new(length);
+    transfer.data()[..length].copy_from_slice(&buf[..length]);
+    write_from(handle, offset, &mut transfer, length)
+}
+
+/// Writes like `write`, but the first `length` bytes of the data of
+/// `transfer`.
+pub fn write_from(
+    handle: usize,
+    offset: u64,
+    transfer: &mut Transfer,
+    length: usize,
+) -> io::Result<usize> {
+    transfer.send(43 | (handle << 16) /* WriteKeyStd */, offset, length)
+}
+
+/// Closes `handle`. Errors are ignored, since there is nothing to be done
+/// about them.
+pub fn close(handle: usize) {
+    services::send(
+        services::pddb(),
+        xous::Message::new_blocking_scalar(44 | (handle << 16), 0, 0, 0, 0), /* CloseKeyStd */
+    )
+    .ok();
+}
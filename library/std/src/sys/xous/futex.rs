@@ -0,0 +1,16 @@
+use crate::sync::atomic::{AtomicI32, AtomicU32};
+use crate::sys::locks::waiter;
+use crate::time::Duration;
+
+fn as_u32(futex: &AtomicI32) -> &AtomicU32 {
+    // SAFETY: `AtomicI32` and `AtomicU32` have the same size and alignment.
+    unsafe { &*(futex as *const AtomicI32 as *const AtomicU32) }
+}
+
+pub fn futex_wait(futex: &AtomicI32, expected: i32, timeout: Option<Duration>) {
+    waiter::wait(as_u32(futex), expected as u32, timeout);
+}
+
+pub fn futex_wake(futex: &AtomicI32) {
+    waiter::wake(as_u32(futex), 1);
+}
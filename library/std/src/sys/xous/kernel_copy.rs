@@ -0,0 +1,109 @@
+//! `io::copy` between a `TcpStream` and a `File`, without the intermediate
+//! buffer of the generic copy loop.
+//!
+//! The generic loop copies every byte twice: out of the pages lent for the
+//! read into its own buffer, and from there into the pages lent for the
+//! write. Here the file's side lends the pages of a `Transfer` directly, and
+//! the socket's side reads into, or writes from, the data in those same
+//! pages, which leaves one copy per byte.
+//!
+//! Reads and writes are otherwise the same as the generic loop's: a copy
+//! ends at the first read of zero bytes, short writes are retried with what
+//! is left, a write of zero bytes fails with `WriteZero`, and errors,
+//! including read timeouts, end the copy and discard the count.
+//!
+//! Only these exact std types are specialized, so that user code can't
+//! observe that the `Read` and `Write` traits aren't used.
+
+use crate::fs::File;
+use crate::io::copy::generic_copy;
+use crate::io::{self, ErrorKind, Read, Result, Write};
+use crate::net::TcpStream;
+use crate::sys::fs::{Transfer, MAX_TRANSFER};
+use crate::sys_common::AsInner;
+
+pub(crate) fn copy_spec<R: Read + ?Sized, W: Write + ?Sized>(
+    read: &mut R,
+    write: &mut W,
+) -> Result<u64> {
+    let copier = Copier { read, write };
+    SpecCopy::copy(copier)
+}
+
+struct Copier<'a, 'b, R: Read + ?Sized, W: Write + ?Sized> {
+    read: &'a mut R,
+    write: &'b mut W,
+}
+
+trait SpecCopy {
+    fn copy(self) -> Result<u64>;
+}
+
+impl<R: Read + ?Sized, W: Write + ?Sized> SpecCopy for Copier<'_, '_, R, W> {
+    default fn copy(self) -> Result<u64> {
+        generic_copy(self.read, self.write)
+    }
+}
+
+impl SpecCopy for Copier<'_, '_, TcpStream, File> {
+    fn copy(self) -> Result<u64> {
+        let (stream, file) = (self.read.as_inner(), self.write.as_inner());
+        let mut transfer = Transfer::new(MAX_TRANSFER);
+        let mut copied = 0;
+        loop {
+            let length = match stream.read(&mut transfer.data()[..MAX_TRANSFER]) {
+                Ok(0) => return Ok(copied),
+                Ok(length) => length,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let mut left = length;
+            while left > 0 {
+                match file.write_transfer(&mut transfer, left) {
+                    Ok(0) => return Err(write_zero()),
+                    Ok(count) => {
+                        // What is left goes back to the start of the data,
+                        // which is where the next write takes it from.
+                        transfer.data().copy_within(count..left, 0);
+                        left -= count;
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            copied += length as u64;
+        }
+    }
+}
+
+impl SpecCopy for Copier<'_, '_, File, TcpStream> {
+    fn copy(self) -> Result<u64> {
+        let (file, stream) = (self.read.as_inner(), self.write.as_inner());
+        let mut transfer = Transfer::new(MAX_TRANSFER);
+        let mut copied = 0;
+        loop {
+            let length = match file.read_transfer(&mut transfer, MAX_TRANSFER) {
+                Ok(0) => return Ok(copied),
+                Ok(length) => length,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let mut data = &transfer.data()[..length];
+            while !data.is_empty() {
+                match stream.write(data) {
+                    Ok(0) => return Err(write_zero()),
+                    Ok(count) => data = &data[count..],
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            copied += length as u64;
+        }
+    }
+}
+
+fn write_zero() -> io::Error {
+    io::const_io_error!(ErrorKind::WriteZero, &"failed to write whole buffer")
+}
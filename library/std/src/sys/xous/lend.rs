@@ -0,0 +1,132 @@
+//! Lending more than a page to a server in one message.
+//!
+//! Only whole pages can be lent. A buffer that already covers whole pages is
+//! lent where it is, and anything else is copied into pages of its own,
+//! which are lent instead and copied back afterwards. Either way the server
+//! sees the data at the start of the memory it is lent.
+
+use super::page_pool::{self, PagesBuf, PAGE_SIZE};
+use super::services;
+use crate::io;
+use crate::ops::Deref;
+
+#[cfg(test)]
+mod tests;
+
+/// The most bytes that can be lent in one message.
+pub const MAX_LEN: usize = 1024 * 1024;
+
+/// What a server answered a lend with: the offset and valid length it set,
+/// and a view of the memory that was lent, which it may have changed.
+pub struct Returned<'a> {
+    pub offset: Option<xous::MemoryAddress>,
+    pub valid: usize,
+    memory: Memory<'a>,
+}
+
+enum Memory<'a> {
+    /// The caller's buffer, lent where it was.
+    InPlace(&'a [u8]),
+    /// Pages the caller's data was copied into.
+    Bounced(PagesBuf),
+}
+
+impl Deref for Returned<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.memory {
+            Memory::InPlace(buf) => buf,
+            Memory::Bounced(pages) => pages,
+        }
+    }
+}
+
+fn too_long() -> io::Error {
+    io::const_io_error!(io::ErrorKind::InvalidInput, &"too much data to lend to a server at once")
+}
+
+/// Lends `buf` to the server on `cid` for it to change, with `opcode` and
+/// `offset`, and `valid` as the number of bytes in use. Whatever the server
+/// changed is in `buf` afterwards.
+///
+/// `buf` is lent where it is if it starts on a page and is a whole number of
+/// pages long. Otherwise it is copied to pages of its own and back again.
+pub fn lend_mut<'a>(
+    cid: xous::CID,
+    opcode: usize,
+    buf: &'a mut [u8],
+    offset: Option<xous::MemoryAddress>,
+    valid: usize,
+) -> io::Result<Returned<'a>> {
+    if buf.len() > MAX_LEN {
+        return Err(too_long());
+    }
+    if buf.is_empty() || buf.as_ptr() as usize % PAGE_SIZE != 0 || buf.len() % PAGE_SIZE != 0 {
+        let mut returned = lend_mut_copy(cid, opcode, buf, offset, valid)?;
+        if let Memory::Bounced(pages) = &mut returned.memory {
+            buf.copy_from_slice(&pages[..buf.len()]);
+        }
+        return Ok(returned);
+    }
+
+    let range = unsafe { xous::MemoryRange::new(buf.as_mut_ptr() as usize, buf.len()).unwrap() };
+    let (offset, valid) = send(cid, opcode, range, offset, valid)?;
+    Ok(Returned { offset, valid, memory: Memory::InPlace(buf) })
+}
+
+/// Lends a copy of `data` to the server on `cid` for it to change, for
+/// callers that can't let it change `data` itself. The changes are in the
+/// view that is returned.
+pub fn lend_mut_copy(
+    cid: xous::CID,
+    opcode: usize,
+    data: &[u8],
+    offset: Option<xous::MemoryAddress>,
+    valid: usize,
+) -> io::Result<Returned<'static>> {
+    let mut pages = get_pages(data.len())?;
+    pages[..data.len()].copy_from_slice(data);
+    lend_pages_mut(cid, opcode, pages, offset, valid)
+}
+
+/// Returns pages with room for `len` bytes, for a caller to fill in and
+/// pass to `lend_pages_mut`.
+pub fn get_pages(len: usize) -> io::Result<PagesBuf> {
+    if len > MAX_LEN {
+        return Err(too_long());
+    }
+    Ok(page_pool::get_pages((len.max(1) + PAGE_SIZE - 1) / PAGE_SIZE))
+}
+
+/// Lends `pages`, which the caller has filled in, to the server on `cid`
+/// for it to change. The changes are in the view that is returned.
+pub fn lend_pages_mut(
+    cid: xous::CID,
+    opcode: usize,
+    mut pages: PagesBuf,
+    offset: Option<xous::MemoryAddress>,
+    valid: usize,
+) -> io::Result<Returned<'static>> {
+    let (offset, valid) = send(cid, opcode, pages.range(), offset, valid)?;
+    Ok(Returned { offset, valid, memory: Memory::Bounced(pages) })
+}
+
+fn send(
+    cid: xous::CID,
+    opcode: usize,
+    range: xous::MemoryRange,
+    offset: Option<xous::MemoryAddress>,
+    valid: usize,
+) -> io::Result<(Option<xous::MemoryAddress>, usize)> {
+    let message = xous::Message::new_lend_mut(opcode, range, offset, xous::MemorySize::new(valid));
+    match services::send(cid, message)? {
+        xous::Result::MemoryReturned(offset, valid) => {
+            Ok((offset, valid.map_or(0, |valid| valid.get())))
+        }
+        _ => Err(io::const_io_error!(
+            io::ErrorKind::InvalidData,
+            &"the server did not return the lent memory"
+        )),
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+use crate::thread;
+
+/// Starts a server that answers `count` lends, then stops. It adds one to
+/// every byte it is lent, and answers with everything it was lent as valid
+/// and the length it was sent as the offset.
+fn start_server(count: usize) -> (xous::CID, thread::JoinHandle<()>) {
+    let sid = xous::create_server().unwrap();
+    let server = thread::spawn(move || {
+        for _ in 0..count {
+            let mut envelope = xous::receive_message(sid).unwrap();
+            let memory = envelope.body.memory_message_mut().unwrap();
+            for byte in memory.buf.as_slice_mut::<u8>() {
+                *byte = byte.wrapping_add(1);
+            }
+            memory.offset = xous::MemoryAddress::new(memory.valid.map_or(0, |valid| valid.get()));
+            memory.valid = xous::MemorySize::new(memory.buf.len());
+        }
+        xous::destroy_server(sid).unwrap();
+    });
+    (xous::connect(sid).unwrap(), server)
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn changed(data: &[u8]) -> Vec<u8> {
+    data.iter().map(|byte| byte.wrapping_add(1)).collect()
+}
+
+#[test]
+fn several_pages() {
+    let (cid, server) = start_server(1);
+    let data = pattern(3 * PAGE_SIZE + 100);
+    let mut pages = get_pages(data.len()).unwrap();
+    assert_eq!(pages.len(), 4 * PAGE_SIZE);
+    pages[..data.len()].copy_from_slice(&data);
+
+    let returned = lend_pages_mut(cid, 1, pages, None, data.len()).unwrap();
+    server.join().unwrap();
+    assert_eq!(returned.offset.map(|offset| offset.get()), Some(data.len()));
+    assert_eq!(returned.valid, 4 * PAGE_SIZE);
+    assert_eq!(returned[..data.len()], changed(&data));
+    assert!(returned[data.len()..].iter().all(|&byte| byte == 1));
+}
+
+#[test]
+fn several_pages_in_place() {
+    let (cid, server) = start_server(1);
+    let data = pattern(2 * PAGE_SIZE);
+    let mut pages = get_pages(data.len()).unwrap();
+    pages.copy_from_slice(&data);
+
+    let returned = lend_mut(cid, 1, &mut pages, None, data.len()).unwrap();
+    assert!(matches!(returned.memory, Memory::InPlace(_)));
+    assert_eq!(returned.valid, data.len());
+    drop(returned);
+    server.join().unwrap();
+    assert_eq!(pages[..], changed(&data));
+}
+
+#[test]
+fn several_pages_copied() {
+    let (cid, server) = start_server(2);
+
+    // Neither starting on a page nor a whole number of pages long.
+    let data = pattern(2 * PAGE_SIZE + 1);
+    let mut buf = data.clone();
+    let returned = lend_mut(cid, 1, &mut buf[1..], None, 5).unwrap();
+    assert!(matches!(returned.memory, Memory::Bounced(_)));
+    assert_eq!(returned.offset.map(|offset| offset.get()), Some(5));
+    assert_eq!(returned.valid, 3 * PAGE_SIZE);
+    drop(returned);
+    assert_eq!(buf[0], data[0]);
+    assert_eq!(buf[1..], changed(&data[1..]));
+
+    // The data lent is left alone, and the changes are in what's returned.
+    let returned = lend_mut_copy(cid, 1, &data, None, data.len()).unwrap();
+    server.join().unwrap();
+    assert_eq!(returned[..data.len()], changed(&data));
+    assert_eq!(data, pattern(2 * PAGE_SIZE + 1));
+}
+
+#[test]
+fn too_much() {
+    assert_eq!(get_pages(MAX_LEN + 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    let mut buf = vec![0; MAX_LEN + 1];
+    // Refused before anything is sent, so no server is needed.
+    let err = lend_mut(0, 1, &mut buf, None, 0).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
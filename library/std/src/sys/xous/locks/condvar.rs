@@ -1,16 +1,13 @@
 use super::mutex::Mutex;
-use crate::sync::atomic::{AtomicUsize, Ordering::SeqCst};
-use crate::sys::services::ticktimer;
+use super::waiter;
+use crate::sync::atomic::{AtomicU32, Ordering::SeqCst};
 use crate::time::Duration;
 
-static CONDVAR_INDEX: AtomicUsize = AtomicUsize::new(1);
-
-// The implementation is inspired by Andrew D. Birrell's paper
-// "Implementing Condition Variables with Semaphores"
-
 pub struct Condvar {
-    counter: AtomicUsize,
-    index: AtomicUsize,
+    /// Incremented on every notification. A waiter records the value before
+    /// releasing the mutex and sleeps only if it is still unchanged, so a
+    /// notification between the unlock and the sleep is never lost.
+    sequence: AtomicU32,
 }
 
 pub type MovableCondvar = Condvar;
@@ -20,83 +17,34 @@ unsafe impl Sync for Condvar {}
 
 impl Condvar {
     pub const fn new() -> Condvar {
-        Condvar {
-            counter: AtomicUsize::new(0),
-            index: AtomicUsize::new(0),
-        }
+        Condvar { sequence: AtomicU32::new(0) }
     }
 
-    pub unsafe fn init(&mut self) {
-        self.index.store(CONDVAR_INDEX.fetch_add(1, SeqCst), SeqCst);
-    }
+    pub unsafe fn init(&mut self) {}
 
     pub unsafe fn notify_one(&self) {
-        if self.counter.load(SeqCst) > 0 {
-            self.counter.fetch_sub(1, SeqCst);
-            xous::send_message(
-                ticktimer(),
-                xous::Message::new_scalar(
-                    9, /* NotifyCondition */
-                    self.index.load(SeqCst),
-                    1,
-                    0,
-                    0,
-                ),
-            )
-            .expect("Ticktimer: failure to send NotifyCondition command");
-        }
+        self.sequence.fetch_add(1, SeqCst);
+        waiter::wake(&self.sequence, 1);
     }
 
     pub unsafe fn notify_all(&self) {
-        let counter = self.counter.swap(0, SeqCst);
-        xous::send_message(
-            ticktimer(),
-            xous::Message::new_scalar(
-                9, /* NotifyCondition */
-                self.index.load(SeqCst),
-                counter,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to send NotifyCondition command");
+        self.sequence.fetch_add(1, SeqCst);
+        waiter::wake_all(&self.sequence);
     }
 
     pub unsafe fn wait(&self, mutex: &Mutex) {
-        self.counter.fetch_add(1, SeqCst);
+        let sequence = self.sequence.load(SeqCst);
         unsafe { mutex.unlock() };
-        xous::send_message(
-            ticktimer(),
-            xous::Message::new_blocking_scalar(
-                8, /* WaitForCondition */
-                self.index.load(SeqCst),
-                0,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to send WaitForCondition command");
+        waiter::wait(&self.sequence, sequence, None);
         unsafe { mutex.lock() };
     }
 
     pub unsafe fn wait_timeout(&self, mutex: &Mutex, dur: Duration) -> bool {
-        self.counter.fetch_add(1, SeqCst);
+        let sequence = self.sequence.load(SeqCst);
         unsafe { mutex.unlock() };
-        let millis = dur.as_millis() as usize;
-        let result = xous::send_message(
-            ticktimer(),
-            xous::Message::new_blocking_scalar(
-                8, /* WaitForCondition */
-                self.index.load(SeqCst),
-                millis,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to send WaitForCondition command");
+        let woken = waiter::wait(&self.sequence, sequence, Some(dur));
         unsafe { mutex.lock() };
-
-        xous::Result::Scalar1(0) == result
+        woken
     }
 
     pub unsafe fn destroy(&self) {}
@@ -1,7 +1,10 @@
 mod condvar;
 mod mutex;
+mod pi_mutex;
 mod rwlock;
+pub(crate) mod waiter;
 
 pub use condvar::*;
 pub use mutex::*;
+pub use pi_mutex::*;
 pub use rwlock::*;
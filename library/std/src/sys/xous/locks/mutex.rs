@@ -1,28 +1,29 @@
+use super::waiter;
 use crate::cell::UnsafeCell;
-use crate::sync::atomic::{AtomicU32, AtomicUsize, Ordering::SeqCst};
-use crate::sys::services::ticktimer;
+use crate::sync::atomic::{AtomicU32, Ordering::SeqCst};
 use crate::sys::thread;
 use xous::syscall::yield_slice;
 
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2;
+
 pub struct Mutex {
-    /// The "locked" value indicates how many threads are waiting on this
-    /// Mutex. Possible values are:
+    /// The state of the lock. Possible values are:
     ///     0: The lock is unlocked
-    ///     1: The lock is locked and unpoisoned
-    ///   >=2: The lock is locked and poisoned
+    ///     1: The lock is locked and no thread is waiting on it
+    ///     2: The lock is locked and other threads may be waiting on it
     ///
-    /// A lock is "poisoned" when there is heavy contention for a lock,
-    /// or it is locked for long periods of time. Rather than spinning,
-    /// these locks send a Message to the ticktimer server requesting
-    /// that they be woken up when a lock is unlocked.
-    locked: AtomicUsize,
+    /// Rather than spinning, threads that find the lock contended block in
+    /// the ticktimer server until the holder wakes them on unlock.
+    locked: AtomicU32,
 }
 
 pub type MovableMutex = Mutex;
 
 impl Mutex {
     pub const fn new() -> Mutex {
-        Mutex { locked: AtomicUsize::new(0) }
+        Mutex { locked: AtomicU32::new(UNLOCKED) }
     }
 
     #[inline]
@@ -32,8 +33,7 @@ pub unsafe fn init(&mut self) {}
     pub unsafe fn lock(&self) {
         // Try multiple times to acquire the lock without resorting to the ticktimer
         // server. For locks that are held for a short amount of time, this will
-        // result in the ticktimer server never getting invoked. This will result
-        // in the lock being either 0 or 1
+        // result in the ticktimer server never getting invoked.
         for _attempts in 0..3 {
             if unsafe { self.try_lock() } {
                 return;
@@ -41,68 +41,36 @@ pub unsafe fn lock(&self) {
             yield_slice();
         }
 
-        // Try one more time to lock. If the lock is released between the previous code and
-        // here, then the inner `locked` value will be 1 at the end of this. If it was not
-        // locked, then the value will be more than 1, for example if there are multiple other
-        // threads waiting on this lock.
-        if unsafe { self.try_lock_or_poison() } {
-            return;
+        // Mark the lock as contended so the holder knows to wake us. If it was
+        // released in the meantime, we now own it (in the contended state, which
+        // at worst costs one unnecessary wake).
+        while self.locked.swap(CONTENDED, SeqCst) != UNLOCKED {
+            waiter::wait(&self.locked, CONTENDED, None);
         }
-
-        // The lock is now "poisoned". When the lock is released, a Message will get sent to the
-        // ticktimer server to wake it up. Note that this may already have happened, so the actual
-        // value of `lock` may be anything (0, 1, 2, ...).
-        xous::send_message(
-            ticktimer(),
-            xous::Message::new_blocking_scalar(
-                6, /* LockMutex */
-                self as *const Mutex as usize,
-                0,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to send LockMutex command");
     }
 
     #[inline]
     pub unsafe fn unlock(&self) {
-        let prev = self.locked.fetch_sub(1, SeqCst);
+        let prev = self.locked.swap(UNLOCKED, SeqCst);
 
         // If the previous value was 1, then this was a "fast path" unlock, so no
         // need to involve the Ticktimer server
-        if prev == 1 {
+        if prev == LOCKED {
             return;
         }
 
-        // If it was 0, then something has gone seriously wrong and the counter
-        // has just wrapped around.
-        if prev == 0 {
-            panic!("mutex lock count underflowed");
+        // If it was 0, then something has gone seriously wrong.
+        if prev == UNLOCKED {
+            panic!("mutex unlocked while not locked");
         }
 
-        // Unblock one thread that is waiting on this message.
-        xous::send_message(
-            ticktimer(),
-            xous::Message::new_scalar(
-                7, /* UnlockMutex */
-                self as *const Mutex as usize,
-                0,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to send UnlockMutex command");
+        // Unblock one thread that is waiting on this lock.
+        waiter::wake(&self.locked, 1);
     }
 
     #[inline]
     pub unsafe fn try_lock(&self) -> bool {
-        self.locked.compare_exchange(0, 1, SeqCst, SeqCst).is_ok()
-    }
-
-    #[inline]
-    pub unsafe fn try_lock_or_poison(&self) -> bool {
-        self.locked.fetch_add(1, SeqCst) == 0
+        self.locked.compare_exchange(UNLOCKED, LOCKED, SeqCst, SeqCst).is_ok()
     }
 
     #[inline]
@@ -126,8 +94,8 @@ pub unsafe fn init(&self) {}
 
     pub unsafe fn lock(&self) {
         let me = thread::my_id();
-        while let Err(_owner) = unsafe { self._try_lock(me) } {
-            yield_slice();
+        while let Err(owner) = unsafe { self._try_lock(me) } {
+            waiter::wait(&self.owner, owner, None);
         }
     }
 
@@ -165,10 +133,7 @@ pub unsafe fn unlock(&self) {
         match unsafe { *self.recursions.get() } {
             0 => {
                 self.owner.swap(0, SeqCst);
-                // // SAFETY: the caller must gurantee that `self.ptr()` is valid i32.
-                // unsafe {
-                //     wasm32::memory_atomic_notify(self.ptr() as *mut i32, 1);
-                // } // wake up one waiter, if any
+                waiter::wake(&self.owner, 1);
             }
             ref mut n => *n -= 1,
         }
@@ -0,0 +1,147 @@
+use super::waiter;
+use crate::sync::atomic::{AtomicPtr, AtomicU32, Ordering::SeqCst};
+use crate::sys::services::ticktimer;
+use crate::sys::thread;
+use xous::syscall::yield_slice;
+
+/// Hooks used to adjust the priority of a lock holder. By default these send
+/// messages to the ticktimer server, which forwards the request to the kernel.
+/// They may be replaced in order to observe the boost/restore sequence.
+#[derive(Copy, Clone)]
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct PriorityHooks {
+    /// Raise the priority of thread `holder` to at least that of thread `waiter`.
+    pub boost: fn(holder: u32, waiter: u32),
+    /// Return thread `holder` to the priority it had before any boost.
+    pub restore: fn(holder: u32),
+}
+
+static DEFAULT_HOOKS: PriorityHooks =
+    PriorityHooks { boost: ticktimer_boost, restore: ticktimer_restore };
+
+static HOOKS: AtomicPtr<PriorityHooks> = AtomicPtr::new(crate::ptr::null_mut());
+
+fn ticktimer_boost(holder: u32, waiter: u32) {
+    // The ticktimer compares the priorities of the two threads and only
+    // raises `holder` if `waiter` is the more urgent of the two.
+    xous::send_message(
+        ticktimer(),
+        xous::Message::new_blocking_scalar(
+            12, /* BoostPriority */
+            holder as usize,
+            waiter as usize,
+            0,
+            0,
+        ),
+    )
+    .expect("Ticktimer: failure to send BoostPriority command");
+}
+
+fn ticktimer_restore(holder: u32) {
+    xous::send_message(
+        ticktimer(),
+        xous::Message::new_blocking_scalar(
+            13, /* RestorePriority */
+            holder as usize,
+            0,
+            0,
+            0,
+        ),
+    )
+    .expect("Ticktimer: failure to send RestorePriority command");
+}
+
+fn hooks() -> &'static PriorityHooks {
+    let hooks = HOOKS.load(SeqCst);
+    if hooks.is_null() { &DEFAULT_HOOKS } else { unsafe { &*hooks } }
+}
+
+/// Replace the functions used to boost and restore thread priorities.
+#[unstable(feature = "xous_std", issue = "none")]
+pub fn set_priority_hooks(hooks: &'static PriorityHooks) {
+    HOOKS.store(hooks as *const PriorityHooks as *mut PriorityHooks, SeqCst);
+}
+
+/// A mutex that lends the priority of its most urgent waiter to the thread
+/// currently holding it.
+///
+/// Unlike `Mutex`, this lock needs to know *who* holds it, so the `owner`
+/// field stores the holder's thread ID plus one (zero means unlocked).
+pub struct PiMutex {
+    owner: AtomicU32,
+    /// Tracks whether a waiter has asked for the holder to be boosted. The
+    /// holder checks this on unlock and asks for its original priority back.
+    boost: AtomicU32,
+}
+
+const BOOST_NONE: u32 = 0;
+const BOOST_PENDING: u32 = 1;
+const BOOST_ACTIVE: u32 = 2;
+
+unsafe impl Send for PiMutex {}
+unsafe impl Sync for PiMutex {}
+
+impl PiMutex {
+    pub const fn new() -> PiMutex {
+        PiMutex { owner: AtomicU32::new(0), boost: AtomicU32::new(BOOST_NONE) }
+    }
+
+    #[inline]
+    pub unsafe fn try_lock(&self) -> bool {
+        let me = thread::my_id() + 1;
+        self.owner.compare_exchange(0, me, SeqCst, SeqCst).is_ok()
+    }
+
+    pub unsafe fn lock(&self) {
+        let me = thread::my_id();
+        loop {
+            let holder = match self.owner.compare_exchange(0, me + 1, SeqCst, SeqCst) {
+                Ok(_) => return,
+                Err(holder) => holder,
+            };
+            assert_ne!(holder, me + 1, "priority inheritance mutex locked recursively");
+
+            // Only ask for a boost once per holder. The holder clears the flag as it
+            // releases the lock, so a new holder will get boosted again if required.
+            if self.boost.compare_exchange(BOOST_NONE, BOOST_PENDING, SeqCst, SeqCst).is_ok() {
+                // The lock may have been released between the `compare_exchange` above
+                // and here. If so, the previous holder already ran its restore step and
+                // the boost would be leaked, so undo our claim and retry.
+                if self.owner.load(SeqCst) != holder {
+                    self.boost.store(BOOST_NONE, SeqCst);
+                    continue;
+                }
+                (hooks().boost)(holder - 1, me);
+                self.boost.store(BOOST_ACTIVE, SeqCst);
+            }
+
+            // With the holder running at our priority, it will make progress and
+            // wake us when it releases the lock.
+            waiter::wait(&self.owner, holder, None);
+        }
+    }
+
+    pub unsafe fn unlock(&self) {
+        let me = thread::my_id();
+        let prev = self.owner.swap(0, SeqCst);
+        debug_assert_eq!(prev, me + 1, "priority inheritance mutex unlocked by non-owner");
+
+        // Any boost recorded now was applied to us, since waiters re-check the owner
+        // before boosting. Until the boost is cleared, no waiter can boost the next
+        // holder, so the two restores can never be confused.
+        loop {
+            match self.boost.load(SeqCst) {
+                BOOST_NONE => break,
+                // A waiter is in the middle of boosting us. It runs at a higher
+                // priority than we do, so it will finish shortly.
+                BOOST_PENDING => yield_slice(),
+                _ => {
+                    (hooks().restore)(me);
+                    self.boost.store(BOOST_NONE, SeqCst);
+                    break;
+                }
+            }
+        }
+        waiter::wake(&self.owner, 1);
+    }
+}
@@ -1,68 +1,134 @@
-use crate::cell::Cell;
+use super::waiter;
+use crate::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+#[cfg(test)]
+mod tests;
+
+/// `mode` holds the number of active readers, or `WRITE_LOCKED` when a
+/// writer holds the lock.
+const WRITE_LOCKED: u32 = u32::MAX;
 
 pub struct RWLock {
-    // This platform has no threads, so we can use a Cell here.
-    mode: Cell<isize>,
+    mode: AtomicU32,
 }
 
 pub type MovableRWLock = RWLock;
 
 unsafe impl Send for RWLock {}
-unsafe impl Sync for RWLock {} // no threads on this platform
+unsafe impl Sync for RWLock {}
 
 impl RWLock {
     pub const fn new() -> RWLock {
-        RWLock { mode: Cell::new(0) }
+        RWLock { mode: AtomicU32::new(0) }
     }
 
     #[inline]
     pub unsafe fn read(&self) {
-        let m = self.mode.get();
-        if m >= 0 {
-            self.mode.set(m + 1);
-        } else {
-            rtabort!("rwlock locked for writing");
+        loop {
+            let m = self.mode.load(SeqCst);
+            if m == WRITE_LOCKED {
+                waiter::wait(&self.mode, WRITE_LOCKED, None);
+            } else if m == WRITE_LOCKED - 1 {
+                // One more reader would look like a writer.
+                panic!("too many active read locks on RwLock");
+            } else if self.mode.compare_exchange(m, m + 1, SeqCst, SeqCst).is_ok() {
+                return;
+            }
         }
     }
 
     #[inline]
     pub unsafe fn try_read(&self) -> bool {
-        let m = self.mode.get();
-        if m >= 0 {
-            self.mode.set(m + 1);
-            true
-        } else {
-            false
+        let m = self.mode.load(SeqCst);
+        if m == WRITE_LOCKED || m + 1 == WRITE_LOCKED {
+            return false;
         }
+        self.mode.compare_exchange(m, m + 1, SeqCst, SeqCst).is_ok()
     }
 
     #[inline]
     pub unsafe fn write(&self) {
-        if self.mode.replace(-1) != 0 {
-            rtabort!("rwlock locked for reading")
+        loop {
+            match self.mode.compare_exchange(0, WRITE_LOCKED, SeqCst, SeqCst) {
+                Ok(_) => return,
+                // Readers only wake us when the count drops to zero, and writers
+                // wake everyone on unlock, so this can't sleep through a release.
+                Err(m) => {
+                    waiter::wait(&self.mode, m, None);
+                }
+            }
         }
     }
 
     #[inline]
     pub unsafe fn try_write(&self) -> bool {
-        if self.mode.get() == 0 {
-            self.mode.set(-1);
-            true
-        } else {
-            false
-        }
+        self.mode.compare_exchange(0, WRITE_LOCKED, SeqCst, SeqCst).is_ok()
     }
 
     #[inline]
     pub unsafe fn read_unlock(&self) {
-        self.mode.set(self.mode.get() - 1);
+        let prev = self.mode.fetch_sub(1, SeqCst);
+        debug_assert!(prev != 0 && prev != WRITE_LOCKED);
+        if prev == 1 {
+            waiter::wake_all(&self.mode);
+        }
     }
 
     #[inline]
     pub unsafe fn write_unlock(&self) {
-        assert_eq!(self.mode.replace(0), -1);
+        assert_eq!(self.mode.swap(0, SeqCst), WRITE_LOCKED);
+        waiter::wake_all(&self.mode);
+    }
+
+    /// Releases whichever kind of lock the caller holds, for libunwind,
+    /// whose `pthread_rwlock_unlock` doesn't say.
+    #[inline]
+    unsafe fn unlock(&self) {
+        if self.mode.load(SeqCst) == WRITE_LOCKED {
+            unsafe { self.write_unlock() };
+        } else {
+            unsafe { self.read_unlock() };
+        }
     }
 
     #[inline]
     pub unsafe fn destroy(&self) {}
 }
+
+// The following functions are needed by libunwind. The `pthread.h` it is
+// built with for Xous (library/unwind/xous/pthread.h) maps the pthread
+// read-write lock functions to them, and initializes locks to the same zeroes
+// as `RWLock::new()`. These symbols are named in pre-link args for the target
+// specification, so keep that in sync.
+#[cfg(not(test))]
+const EINVAL: i32 = 22;
+
+#[cfg(not(test))]
+#[no_mangle]
+pub unsafe extern "C" fn __rust_rwlock_rdlock(p: *mut RWLock) -> i32 {
+    if p.is_null() {
+        return EINVAL;
+    }
+    unsafe { (*p).read() };
+    0
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub unsafe extern "C" fn __rust_rwlock_wrlock(p: *mut RWLock) -> i32 {
+    if p.is_null() {
+        return EINVAL;
+    }
+    unsafe { (*p).write() };
+    0
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub unsafe extern "C" fn __rust_rwlock_unlock(p: *mut RWLock) -> i32 {
+    if p.is_null() {
+        return EINVAL;
+    }
+    unsafe { (*p).unlock() };
+    0
+}
@@ -0,0 +1,131 @@
+use super::{RWLock, WRITE_LOCKED};
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::{Arc, Barrier};
+use crate::thread;
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 2_000;
+
+struct Shared {
+    lock: RWLock,
+    readers: AtomicUsize,
+    writers: AtomicUsize,
+}
+
+/// Has every thread take the lock over and over, writing every few rounds,
+/// and checks that a writer never overlaps anyone else.
+#[test]
+fn readers_and_writers() {
+    let shared = Arc::new(Shared {
+        lock: RWLock::new(),
+        readers: AtomicUsize::new(0),
+        writers: AtomicUsize::new(0),
+    });
+    let start = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let shared = shared.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                start.wait();
+                for round in 0..ROUNDS {
+                    if (i + round) % THREADS == 0 {
+                        unsafe { shared.lock.write() };
+                        assert_eq!(shared.writers.fetch_add(1, Ordering::SeqCst), 0);
+                        assert_eq!(shared.readers.load(Ordering::SeqCst), 0);
+                        thread::yield_now();
+                        shared.writers.fetch_sub(1, Ordering::SeqCst);
+                        unsafe { shared.lock.write_unlock() };
+                    } else {
+                        unsafe { shared.lock.read() };
+                        shared.readers.fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(shared.writers.load(Ordering::SeqCst), 0);
+                        thread::yield_now();
+                        shared.readers.fetch_sub(1, Ordering::SeqCst);
+                        unsafe { shared.lock.read_unlock() };
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(shared.lock.mode.load(Ordering::SeqCst), 0);
+}
+
+/// A writer waiting behind readers is let in once the last one leaves, and
+/// readers waiting behind a writer are let in once it does.
+#[test]
+fn waiters_are_woken() {
+    let lock = Arc::new(RWLock::new());
+    unsafe { lock.read() };
+    unsafe { lock.read() };
+
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || unsafe {
+            lock.write();
+            lock.write_unlock();
+        })
+    };
+    unsafe { lock.read_unlock() };
+    unsafe { lock.read_unlock() };
+    writer.join().unwrap();
+
+    unsafe { lock.write() };
+    let readers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || unsafe {
+                lock.read();
+                lock.read_unlock();
+            })
+        })
+        .collect();
+    unsafe { lock.write_unlock() };
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(lock.mode.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn reader_count_cannot_reach_write_locked() {
+    let lock = RWLock::new();
+    lock.mode.store(WRITE_LOCKED - 1, Ordering::SeqCst);
+    assert!(!unsafe { lock.try_read() });
+    assert!(crate::panic::catch_unwind(|| unsafe { lock.read() }).is_err());
+    assert_eq!(lock.mode.load(Ordering::SeqCst), WRITE_LOCKED - 1);
+}
+
+// Verify that the bytes libunwind initializes a lock with are an unlocked
+// `RWLock`. If this changes, `PTHREAD_RWLOCK_INITIALIZER` and
+// `pthread_rwlock_t` in library/unwind/xous/pthread.h need to change too.
+#[test]
+fn c_rwlock_initializer() {
+    const C_RWLOCK_INIT: [u8; 4] = [0; 4];
+    assert_eq!(crate::mem::size_of::<RWLock>(), C_RWLOCK_INIT.len());
+    static RUST_RWLOCK_INIT: RWLock = RWLock::new();
+    let init = unsafe { crate::mem::transmute_copy::<_, [u8; 4]>(&RUST_RWLOCK_INIT) };
+    assert_eq!(init, C_RWLOCK_INIT);
+}
+
+/// libunwind unlocks without saying whether it read or wrote.
+#[test]
+fn unlock_either() {
+    let lock = RWLock::new();
+    unsafe {
+        lock.read();
+        lock.read();
+        lock.unlock();
+        assert_eq!(lock.mode.load(Ordering::SeqCst), 1);
+        lock.unlock();
+        lock.write();
+        assert_eq!(lock.mode.load(Ordering::SeqCst), WRITE_LOCKED);
+        lock.unlock();
+        assert!(lock.try_write());
+        lock.unlock();
+    }
+    assert_eq!(lock.mode.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,109 @@
+//! Futex-style blocking shared by all of the lock primitives.
+//!
+//! A thread calls [`wait`] with the address of an atomic and the value it
+//! expects to find there, and sleeps until another thread calls [`wake`] on
+//! the same address. The ticktimer server keeps the actual queue of sleeping
+//! threads, keyed by address.
+//!
+//! The server cannot read our memory, so the "compare" half of
+//! compare-and-sleep happens here. To keep a wakeup from slipping in between
+//! the comparison and the moment the server enqueues us, every waiter first
+//! registers itself in a per-address counter. `wake` only contacts the server
+//! when that counter is nonzero, and the server remembers wakeups addressed to
+//! threads that have registered but not yet arrived, handing them out as soon
+//! as those threads block. A registered waiter can therefore never miss a
+//! wake that was issued after its comparison. The cost is that a stale wakeup
+//! may occasionally cause a spurious return from `wait`, which all callers
+//! must tolerate anyway.
+
+use crate::sync::atomic::{AtomicU32, AtomicUsize, Ordering::SeqCst};
+use crate::sys::services::ticktimer;
+use crate::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of waiter counters. Addresses that share a counter only cost each
+/// other the occasional unnecessary `wake` message. Must be a power of two.
+const BUCKET_COUNT: usize = 64;
+
+static WAITERS: [AtomicUsize; BUCKET_COUNT] = {
+    const EMPTY: AtomicUsize = AtomicUsize::new(0);
+    [EMPTY; BUCKET_COUNT]
+};
+
+fn waiters(futex: &AtomicU32) -> &'static AtomicUsize {
+    // Atomics are word-aligned, so drop the bottom two bits.
+    &WAITERS[(futex as *const AtomicU32 as usize >> 2) & (BUCKET_COUNT - 1)]
+}
+
+/// Block the current thread as long as `futex` contains `expected`.
+///
+/// Returns `false` if `timeout` elapsed, and `true` if the thread was woken
+/// or the value had already changed. Spurious returns are possible, so the
+/// caller must re-check its condition.
+pub fn wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) -> bool {
+    let waiters = waiters(futex);
+    waiters.fetch_add(1, SeqCst);
+
+    if futex.load(SeqCst) != expected {
+        waiters.fetch_sub(1, SeqCst);
+        return true;
+    }
+
+    // The ticktimer treats a timeout of 0 as "forever", so round short
+    // timeouts up rather than down.
+    let millis = match timeout {
+        None => 0,
+        Some(dur) => {
+            let millis = dur.as_nanos().saturating_add(999_999) / 1_000_000;
+            millis.clamp(1, usize::MAX as u128) as usize
+        }
+    };
+
+    let result = xous::send_message(
+        ticktimer(),
+        xous::Message::new_blocking_scalar(
+            14, /* WaitForAddress */
+            futex as *const AtomicU32 as usize,
+            millis,
+            0,
+            0,
+        ),
+    )
+    .expect("Ticktimer: failure to send WaitForAddress command");
+
+    waiters.fetch_sub(1, SeqCst);
+    result == xous::Result::Scalar1(0)
+}
+
+/// Wake up to `count` threads blocked in [`wait`] on `futex`. Returns the
+/// number of threads the ticktimer woke, which may include wakeups it holds
+/// for threads that have not arrived yet.
+pub fn wake(futex: &AtomicU32, count: usize) -> usize {
+    let waiting = waiters(futex).load(SeqCst);
+    if waiting == 0 || count == 0 {
+        return 0;
+    }
+
+    match xous::send_message(
+        ticktimer(),
+        xous::Message::new_blocking_scalar(
+            15, /* WakeAddress */
+            futex as *const AtomicU32 as usize,
+            count.min(waiting),
+            0,
+            0,
+        ),
+    )
+    .expect("Ticktimer: failure to send WakeAddress command")
+    {
+        xous::Result::Scalar1(woken) => woken,
+        _ => 0,
+    }
+}
+
+/// Wake every thread blocked in [`wait`] on `futex`.
+pub fn wake_all(futex: &AtomicU32) -> usize {
+    wake(futex, usize::MAX)
+}
@@ -0,0 +1,95 @@
+use super::{wait, wake, wake_all};
+use crate::sync::atomic::{AtomicU32, AtomicUsize, Ordering::SeqCst};
+use crate::sync::{Arc, Barrier};
+use crate::thread;
+use crate::time::{Duration, Instant};
+
+const THREADS: usize = 8;
+
+#[test]
+fn wait_returns_at_once_on_a_changed_value() {
+    let futex = AtomicU32::new(1);
+    assert!(wait(&futex, 0, None));
+}
+
+#[test]
+fn wait_times_out() {
+    let futex = AtomicU32::new(0);
+    let start = Instant::now();
+    assert!(!wait(&futex, 0, Some(Duration::from_millis(10))));
+    assert!(start.elapsed() >= Duration::from_millis(10));
+}
+
+#[test]
+fn wake_without_waiters() {
+    let futex = AtomicU32::new(0);
+    assert_eq!(wake(&futex, 1), 0);
+    assert_eq!(wake_all(&futex), 0);
+}
+
+/// Passes a token around a ring of threads, each one waiting for its turn
+/// and then handing it to the next. A single lost wakeup leaves the ring
+/// stuck, so this tests that a wake that races with a wait is never lost.
+#[test]
+fn token_ring() {
+    const ROUNDS: u32 = 500;
+    let turn = Arc::new(AtomicU32::new(0));
+    let threads: Vec<_> = (0..THREADS as u32)
+        .map(|i| {
+            let turn = turn.clone();
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    let mine = round * THREADS as u32 + i;
+                    loop {
+                        let current = turn.load(SeqCst);
+                        if current == mine {
+                            break;
+                        }
+                        wait(&turn, current, None);
+                    }
+                    turn.store(mine + 1, SeqCst);
+                    wake_all(&turn);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(turn.load(SeqCst), ROUNDS * THREADS as u32);
+}
+
+/// Releases many waiters at once, over and over, and checks that every one
+/// of them gets through each time.
+#[test]
+fn wake_all_releases_everyone() {
+    const ROUNDS: u32 = 200;
+    let generation = Arc::new(AtomicU32::new(0));
+    let passed = Arc::new(AtomicUsize::new(0));
+    let ready = Arc::new(Barrier::new(THREADS + 1));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let generation = generation.clone();
+            let passed = passed.clone();
+            let ready = ready.clone();
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    ready.wait();
+                    while generation.load(SeqCst) == round {
+                        wait(&generation, round, None);
+                    }
+                    passed.fetch_add(1, SeqCst);
+                }
+            })
+        })
+        .collect();
+    for round in 0..ROUNDS {
+        ready.wait();
+        generation.store(round + 1, SeqCst);
+        wake_all(&generation);
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(passed.load(SeqCst), THREADS * ROUNDS as usize);
+}
@@ -2,31 +2,41 @@
 
 pub mod alloc;
 pub mod args;
+mod atomic;
+pub mod backtrace;
 pub mod cmath;
 pub mod env;
-#[path = "../unsupported/fs.rs"]
+pub mod error;
+pub mod ffi;
 pub mod fs;
+pub mod futex;
 #[path = "../unsupported/io.rs"]
 pub mod io;
+pub mod kernel_copy;
+mod lend;
 pub mod locks;
 pub mod net;
 pub mod os;
 #[path = "../unix/os_str.rs"]
 pub mod os_str;
+pub mod page_pool;
+mod params;
 pub mod path;
-#[path = "../unsupported/pipe.rs"]
 pub mod pipe;
-#[path = "../unsupported/process.rs"]
 pub mod process;
+pub mod rand;
 pub mod stdio;
 pub mod services;
 pub mod thread;
 pub mod thread_local_dtor;
 pub mod thread_local_key;
 pub mod time;
+#[cfg(feature = "xous-trace-ipc")]
+mod trace;
 
 mod common;
 pub use common::*;
+pub use rand::hashmap_random_keys;
 
 // This function is needed by the panic runtime. The symbol is named in
 // pre-link args for the target specification, so keep that in sync.
@@ -34,11 +44,11 @@
 #[no_mangle]
 // NB. used by both libunwind and libpanic_abort
 pub extern "C" fn __rust_abort() {
-    xous::syscall::terminate_process(1);
+    abort_internal();
 }
 
 #[cfg(not(test))]
 #[no_mangle]
 pub extern "C" fn abort() {
-    xous::syscall::terminate_process(1);
+    abort_internal();
 }
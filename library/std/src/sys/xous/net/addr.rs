@@ -0,0 +1,82 @@
+//! The wire format of socket addresses, used by every request to the network
+//! service that carries one:
+//!
+//! ```text
+//! version: u8  port: u16  octets: [u8; 4 or 16]  flowinfo: u32  scope_id: u32
+//! ```
+//!
+//! `version` is 4 or 6, and `flowinfo` and `scope_id` are only there for
+//! IPv6. Integers are little-endian, and the octets are in network order, as
+//! `octets()` returns them. An address takes at most `MAX_LEN` bytes, and
+//! requests set aside that much for one wherever it is followed by something
+//! else, so that what follows is always at the same offset:
+//!
+//! * `StdTcpConnect` and `StdTcpListen`, `StdUdpBind` and `StdUdpTx` start
+//!   with the address. `StdTcpConnect` follows it with the timeout in
+//!   milliseconds as a `u64`, `StdTcpListen` with a byte of flags, which the
+//!   reply updates in place, and `StdUdpTx` with the length of the datagram
+//!   as a `u16` and then the datagram.
+//! * Replies to `StdTcpAccept` and `StdUdpRx` have the address at offset 3,
+//!   after the result code and a `u16`: the new stream's handle, or the
+//!   length of the datagram, which follows the address.
+//! * Replies to `StdGetAddress` start with the address.
+//!
+//! The network service has to use the same format, so it changes only
+//! together with the service.
+
+use super::LIBRARY_ERROR;
+use crate::io;
+use crate::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use crate::sys::error::net_error;
+
+#[cfg(test)]
+mod tests;
+
+/// The most bytes an encoded address takes, which is what an IPv6 one does.
+pub const MAX_LEN: usize = 1 + 2 + 16 + 4 + 4;
+
+/// Writes `addr` to the start of `buf`, returning how many bytes it took.
+/// Panics if `buf` is shorter than that.
+pub fn encode_socket_addr(addr: &SocketAddr, buf: &mut [u8]) -> usize {
+    buf[1..3].copy_from_slice(&addr.port().to_le_bytes());
+    match addr {
+        SocketAddr::V4(addr) => {
+            buf[0] = 4;
+            buf[3..7].copy_from_slice(&addr.ip().octets());
+            7
+        }
+        SocketAddr::V6(addr) => {
+            buf[0] = 6;
+            buf[3..19].copy_from_slice(&addr.ip().octets());
+            buf[19..23].copy_from_slice(&addr.flowinfo().to_le_bytes());
+            buf[23..27].copy_from_slice(&addr.scope_id().to_le_bytes());
+            27
+        }
+    }
+}
+
+/// Reads an address from the start of `buf`, returning it and how many
+/// bytes it took.
+pub fn decode_socket_addr(buf: &[u8]) -> io::Result<(SocketAddr, usize)> {
+    let malformed = || net_error(LIBRARY_ERROR);
+    let u32_at = |at: usize| u32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+
+    let version = *buf.get(0).ok_or_else(malformed)?;
+    let length = match version {
+        4 => 7,
+        6 => 27,
+        _ => return Err(malformed()),
+    };
+    if buf.len() < length {
+        return Err(malformed());
+    }
+    let port = u16::from_le_bytes(buf[1..3].try_into().unwrap());
+    let addr = if version == 4 {
+        let octets: [u8; 4] = buf[3..7].try_into().unwrap();
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(octets), port))
+    } else {
+        let octets: [u8; 16] = buf[3..19].try_into().unwrap();
+        SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(octets), port, u32_at(19), u32_at(23)))
+    };
+    Ok((addr, length))
+}
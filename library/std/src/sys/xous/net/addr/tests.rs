@@ -0,0 +1,59 @@
+use super::*;
+use crate::io::ErrorKind;
+
+fn round_trip(addr: SocketAddr, len: usize) {
+    let mut buf = [0xa5; MAX_LEN + 4];
+    assert_eq!(encode_socket_addr(&addr, &mut buf), len);
+    // Whatever follows the address is left alone.
+    assert!(buf[len..].iter().all(|&b| b == 0xa5));
+    assert_eq!(decode_socket_addr(&buf).unwrap(), (addr, len));
+    assert_eq!(decode_socket_addr(&buf[..len]).unwrap(), (addr, len));
+}
+
+#[test]
+fn v4_round_trip() {
+    for addr in ["0.0.0.0:0", "127.0.0.1:80", "192.168.1.23:8080", "255.255.255.255:65535"] {
+        round_trip(addr.parse().unwrap(), 7);
+    }
+}
+
+#[test]
+fn v6_round_trip() {
+    for addr in ["[::]:0", "[::1]:443", "[2001:db8::ff00:42:8329]:65535"] {
+        round_trip(addr.parse().unwrap(), 27);
+    }
+    let scoped = SocketAddrV6::new("fe80::2a0:c9ff:fe14:8254".parse().unwrap(), 5353, 0x12345, 3);
+    round_trip(SocketAddr::V6(scoped), 27);
+    let extremes = SocketAddrV6::new(Ipv6Addr::from([0xff; 16]), u16::MAX, u32::MAX, u32::MAX);
+    round_trip(SocketAddr::V6(extremes), 27);
+}
+
+#[test]
+fn layout() {
+    let mut buf = [0; MAX_LEN];
+    encode_socket_addr(&"10.0.0.1:8080".parse().unwrap(), &mut buf);
+    assert_eq!(buf[..7], [4, 0x90, 0x1f, 10, 0, 0, 1]);
+
+    let addr = SocketAddrV6::new("2001:db8::1".parse().unwrap(), 443, 0x01020304, 0x0a0b0c0d);
+    encode_socket_addr(&SocketAddr::V6(addr), &mut buf);
+    #[rustfmt::skip]
+    let expected = [
+        6, 0xbb, 0x01,
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        0x04, 0x03, 0x02, 0x01,
+        0x0d, 0x0c, 0x0b, 0x0a,
+    ];
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn malformed() {
+    let v4 = [4, 0x90, 0x1f, 10, 0, 0, 1];
+    let mut v6 = [0; MAX_LEN];
+    encode_socket_addr(&"[::1]:443".parse().unwrap(), &mut v6);
+    let bufs: [&[u8]; 6] = [&[], &[0; 7], &[5; MAX_LEN], &v4[..6], &v6[..26], &v6[..7]];
+    for buf in bufs {
+        let err = decode_socket_addr(buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other, "{buf:?}");
+    }
+}
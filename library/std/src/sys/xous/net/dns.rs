@@ -1,22 +1,25 @@
 use crate::io;
 use crate::net::{Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use crate::time::Duration;
 use core::convert::{TryFrom, TryInto};
 
+use super::super::page_pool::{self, PageBuf};
 use super::super::services;
+use super::timeout;
+use crate::sys::error::dns_error;
 
-pub struct Dns {
-    cid: xous::CID,
-}
+/// How long to wait for the resolver to answer a lookup, which may involve
+/// several queries over the network.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
 
-#[derive(Debug)]
-pub struct DnsError {
-    pub code: u8,
-}
+/// How long to wait for the resolver to answer an mDNS lookup. The resolver
+/// gives up on responders after a second, so this only guards against a
+/// resolver that is stuck.
+const MDNS_LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Debug)]
-#[repr(C, align(4096))]
 pub struct LookupHost {
-    data: [u8; 4096],
+    data: PageBuf,
     port: u16,
     offset: usize,
     count: usize,
@@ -74,49 +77,70 @@ fn next(&mut self) -> Option<SocketAddr> {
     }
 }
 
-impl Dns {
-    pub fn new() -> Dns {
-        Dns { cid: services::dns() }
-    }
+/// Returns whether `name` is in the `.local` domain, which only mDNS can
+/// resolve.
+fn is_mdns(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name).as_bytes();
+    name.len() > 6 && name[name.len() - 6..].eq_ignore_ascii_case(b".local")
+}
 
-    pub fn lookup(&self, query: &str, port: u16) -> Result<LookupHost, DnsError> {
-        let mut result = LookupHost { data: [0u8; 4096], offset: 0, count: 0, port };
+/// Looks up `query`, giving up if the resolver hasn't answered in time. A
+/// resolver that has been restarted is asked again.
+///
+/// Names in `.local` are looked up with mDNS on the local network, and never
+/// sent to the DNS servers, whose answers for them couldn't be trusted. A
+/// name no responder answers for fails with `NotFound`, and resolvers that
+/// predate mDNS fail with `Unsupported`.
+fn lookup(query: &str, port: u16) -> io::Result<LookupHost> {
+    if is_mdns(query) {
+        return match raw_lookup(query, port, 7 /* RawMdnsLookup */, MDNS_LOOKUP_TIMEOUT) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(dns_error(3 /* NameError */)),
+            result => result,
+        };
+    }
+    raw_lookup(query, port, 6 /* RawLookup */, LOOKUP_TIMEOUT)
+}
 
-        // Copy the query into the message that gets sent to the DNS server
-        for (query_byte, result_byte) in query.as_bytes().iter().zip(result.data.iter_mut()) {
-            *result_byte = *query_byte;
+/// Sends `query` to the resolver with `opcode`, either `RawLookup` or
+/// `RawMdnsLookup`, which take the same request and give the same reply.
+///
+/// The query is followed by how long the resolver may take to answer, in
+/// milliseconds (see `timeout`), which is also passed as the offset for
+/// resolvers that read it from there. A resolver that runs out of time
+/// answers with `Timeout`.
+fn raw_lookup(query: &str, port: u16, opcode: usize, timeout: Duration) -> io::Result<LookupHost> {
+    let query = query.as_bytes();
+    let millis = timeout::to_millis(Some(timeout));
+    let data = services::retry_if_gone(|| {
+        let mut data = page_pool::get();
+        if query.len() + timeout::LEN > data.len() {
+            return Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"host name is too long"));
         }
+        data[..query.len()].copy_from_slice(query);
+        data[query.len()..query.len() + timeout::LEN].copy_from_slice(&millis.to_le_bytes());
 
-        let buf = unsafe {
-            xous::MemoryRange::new(&mut result as *mut LookupHost as usize, 4096).unwrap()
-        };
-        let response = xous::send_message(
-            self.cid,
+        let range = data.range();
+        services::send(
+            services::dns(),
             xous::Message::new_lend_mut(
-                6, /* RawLookup */
-                buf,
-                None,
-                xous::MemorySize::new(query.as_bytes().len()),
+                opcode,
+                range,
+                timeout::to_offset(millis),
+                xous::MemorySize::new(query.len()),
             ),
-        );
-        if let Ok(xous::Result::MemoryReturned(_, _)) = response {
-            // The first element in the Status message is the result code.
-            let data = buf.as_slice::<u8>();
-
-            if data[0] != 0 {
-                Err(DnsError { code: data[1] })
-            } else {
-                assert_eq!(result.offset, 0);
-                result.count = data[1] as usize;
-
-                // Advance the offset to the first record
-                result.offset = 2;
-                Ok(result)
-            }
-        } else {
-            Err(DnsError { code: 0 })
-        }
+        )?;
+        Ok(data)
+    })?;
+
+    // The first byte of the reply is nonzero on failure, in which case the
+    // second is the resolver's error code.
+    if data[0] != 0 {
+        return Err(dns_error(data[1]));
     }
+    let count = data[1] as usize;
+
+    // Start at the first record
+    Ok(LookupHost { data, offset: 2, count, port })
 }
 
 impl TryFrom<&str> for LookupHost {
@@ -145,8 +169,6 @@ impl TryFrom<(&str, u16)> for LookupHost {
 
     fn try_from(v: (&str, u16)) -> io::Result<LookupHost> {
         // println!("Trying to look up {}:{}", v.0, v.1);
-        Dns::new()
-            .lookup(v.0, v.1)
-            .map_err(|_e| io::const_io_error!(io::ErrorKind::InvalidInput, &"DNS failure"))
+        lookup(v.0, v.1)
     }
 }
@@ -0,0 +1,134 @@
+//! The device's own name and network interfaces, as the network service
+//! sees them.
+//!
+//! Both requests lend a page for the service to fill in. The first byte of
+//! the reply is zero on success, or else the second byte is a `NetError`
+//! code. A successful reply continues with:
+//!
+//! * `StdGetHostname`: `length: u8`, then the name in that many bytes of
+//!   UTF-8.
+//! * `StdGetInterfaces`: `count: u8`, then `count` interfaces, each
+//!   `name_length: u8  name  index: u32  flags: u8  address_count: u8`
+//!   followed by the addresses. Bit 0 of `flags` is set when the interface
+//!   is up. An address is `4` and then four octets, or `6` and then
+//!   sixteen. Integers are little-endian.
+//!
+//! The service answers both while Wi-Fi is down, listing whatever
+//! interfaces it has at the time. Older services that report "not
+//! connected" instead are treated as having only the loopback interface,
+//! and no hostname of their own, so the device is called `localhost`.
+
+use super::super::page_pool::{self, PageBuf};
+use super::super::params::Reader;
+use super::LIBRARY_ERROR;
+use crate::io;
+use crate::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::sys::error::net_error;
+use crate::sys::services;
+
+#[cfg(test)]
+mod tests;
+
+/// `NetError::NotConnected`.
+const NOT_CONNECTED: u8 = 5;
+
+/// The hostname reported when the network service has none to give.
+const OFFLINE_HOSTNAME: &str = "localhost";
+
+/// A network interface of the device.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct InterfaceInfo {
+    /// The interface's name, such as `"wlan0"` or `"lo"`.
+    pub name: String,
+    /// The index the network service knows the interface by.
+    pub index: u32,
+    /// The addresses assigned to the interface, which is empty while it has
+    /// none, for example before DHCP has completed.
+    pub addrs: Vec<IpAddr>,
+    /// Whether the interface is up.
+    pub up: bool,
+}
+
+/// Sends `opcode` with a lent page, and returns the page the service filled
+/// in.
+fn request(opcode: usize) -> io::Result<PageBuf> {
+    let mut request = page_pool::get();
+    let buf = request.range();
+    services::retry_if_gone(|| {
+        services::send(services::network(), xous::Message::new_lend_mut(opcode, buf, None, None))
+    })?;
+    Ok(request)
+}
+
+fn malformed() -> io::Error {
+    net_error(LIBRARY_ERROR)
+}
+
+pub fn hostname() -> io::Result<String> {
+    decode_hostname(&request(47 /* StdGetHostname */)?)
+}
+
+pub fn interfaces() -> io::Result<Vec<InterfaceInfo>> {
+    decode_interfaces(&request(48 /* StdGetInterfaces */)?)
+}
+
+/// Decodes the page returned by `StdGetHostname`.
+fn decode_hostname(reply: &[u8]) -> io::Result<String> {
+    match (reply[0], reply[1]) {
+        (0, _) => parse_hostname(&reply[1..]).ok_or_else(malformed),
+        (_, NOT_CONNECTED) => Ok(OFFLINE_HOSTNAME.to_owned()),
+        (_, code) => Err(net_error(code)),
+    }
+}
+
+/// Decodes the page returned by `StdGetInterfaces`.
+fn decode_interfaces(reply: &[u8]) -> io::Result<Vec<InterfaceInfo>> {
+    match (reply[0], reply[1]) {
+        (0, _) => parse_interfaces(&reply[1..]).ok_or_else(malformed),
+        (_, NOT_CONNECTED) => Ok(vec![loopback()]),
+        (_, code) => Err(net_error(code)),
+    }
+}
+
+fn loopback() -> InterfaceInfo {
+    InterfaceInfo {
+        name: "lo".to_owned(),
+        index: 0,
+        addrs: vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()],
+        up: true,
+    }
+}
+
+fn u8(reader: &mut Reader<'_>) -> Option<u8> {
+    Some(reader.bytes(1)?[0])
+}
+
+fn parse_hostname(data: &[u8]) -> Option<String> {
+    let mut reader = Reader(data);
+    let length = u8(&mut reader)? as usize;
+    String::from_utf8(reader.bytes(length)?.to_vec()).ok()
+}
+
+fn parse_interfaces(data: &[u8]) -> Option<Vec<InterfaceInfo>> {
+    let mut reader = Reader(data);
+    let count = u8(&mut reader)?;
+    (0..count)
+        .map(|_| {
+            let name_length = u8(&mut reader)? as usize;
+            let name = String::from_utf8(reader.bytes(name_length)?.to_vec()).ok()?;
+            let index = reader.u32()?;
+            let up = u8(&mut reader)? & 1 != 0;
+            let address_count = u8(&mut reader)?;
+            let addrs = (0..address_count)
+                .map(|_| match u8(&mut reader)? {
+                    4 => Some(IpAddr::from(<[u8; 4]>::try_from(reader.bytes(4)?).ok()?)),
+                    6 => Some(IpAddr::from(<[u8; 16]>::try_from(reader.bytes(16)?).ok()?)),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(InterfaceInfo { name, index, addrs, up })
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+//! The replies here are laid out as the network service writes them: a full
+//! page, with whatever the page held before left after the reply.
+
+use super::*;
+use crate::io::ErrorKind;
+
+fn page(reply: &[u8]) -> Vec<u8> {
+    let mut page = vec![0xa5; page_pool::PAGE_SIZE];
+    page[..reply.len()].copy_from_slice(reply);
+    page
+}
+
+#[rustfmt::skip]
+const CONNECTED_HOSTNAME: &[u8] = &[
+    0,
+    10, b'p', b'r', b'e', b'c', b'u', b'r', b's', b'o', b'r', b'1',
+];
+
+#[rustfmt::skip]
+const CONNECTED_INTERFACES: &[u8] = &[
+    0,
+    2,
+    // wlan0: index 1, up, 192.168.1.23 and fe80::2a0:c9ff:fe14:8254
+    5, b'w', b'l', b'a', b'n', b'0',
+    1, 0, 0, 0,
+    1,
+    2,
+    4, 192, 168, 1, 23,
+    6, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0xa0, 0xc9, 0xff, 0xfe, 0x14, 0x82, 0x54,
+    // lo: index 0, up, 127.0.0.1
+    2, b'l', b'o',
+    0, 0, 0, 0,
+    1,
+    1,
+    4, 127, 0, 0, 1,
+];
+
+/// While Wi-Fi is down, the interface is still listed, but is down and has
+/// no addresses.
+#[rustfmt::skip]
+const OFFLINE_INTERFACES: &[u8] = &[
+    0,
+    2,
+    5, b'w', b'l', b'a', b'n', b'0',
+    1, 0, 0, 0,
+    0,
+    0,
+    2, b'l', b'o',
+    0, 0, 0, 0,
+    1,
+    1,
+    4, 127, 0, 0, 1,
+];
+
+/// What older services answer while Wi-Fi is down: `NetError::NotConnected`.
+const NOT_CONNECTED_REPLY: &[u8] = &[1, 5];
+
+#[test]
+fn connected() {
+    assert_eq!(decode_hostname(&page(CONNECTED_HOSTNAME)).unwrap(), "precursor1");
+
+    let interfaces = decode_interfaces(&page(CONNECTED_INTERFACES)).unwrap();
+    assert_eq!(interfaces.len(), 2);
+    let wlan = &interfaces[0];
+    assert_eq!((wlan.name.as_str(), wlan.index, wlan.up), ("wlan0", 1, true));
+    assert_eq!(
+        wlan.addrs,
+        [IpAddr::from([192, 168, 1, 23]), "fe80::2a0:c9ff:fe14:8254".parse::<IpAddr>().unwrap()]
+    );
+    let lo = &interfaces[1];
+    assert_eq!((lo.name.as_str(), lo.index, lo.up), ("lo", 0, true));
+    assert_eq!(lo.addrs, [IpAddr::from(Ipv4Addr::LOCALHOST)]);
+}
+
+#[test]
+fn offline() {
+    let interfaces = decode_interfaces(&page(OFFLINE_INTERFACES)).unwrap();
+    let wlan = &interfaces[0];
+    assert_eq!((wlan.name.as_str(), wlan.up), ("wlan0", false));
+    assert!(wlan.addrs.is_empty());
+    assert!(interfaces[1].up);
+
+    assert_eq!(decode_hostname(&page(NOT_CONNECTED_REPLY)).unwrap(), OFFLINE_HOSTNAME);
+    let interfaces = decode_interfaces(&page(NOT_CONNECTED_REPLY)).unwrap();
+    assert_eq!(interfaces.len(), 1);
+    assert_eq!((interfaces[0].name.as_str(), interfaces[0].up), ("lo", true));
+    assert_eq!(
+        interfaces[0].addrs,
+        [IpAddr::from(Ipv4Addr::LOCALHOST), IpAddr::from(Ipv6Addr::LOCALHOST)]
+    );
+}
+
+#[test]
+fn errors() {
+    // `NetError::AccessDenied`.
+    let e = decode_interfaces(&page(&[1, 3])).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+    let e = decode_hostname(&page(&[1, 3])).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn malformed_replies() {
+    let malformed = |reply: &[u8]| decode_interfaces(reply).unwrap_err().kind();
+
+    // Cut off partway through an address, with nothing after it.
+    let cut = &CONNECTED_INTERFACES[..20];
+    assert_eq!(malformed(cut), ErrorKind::Other);
+    // An address that is neither IPv4 nor IPv6.
+    let mut bad = CONNECTED_INTERFACES.to_vec();
+    bad[14] = 5;
+    assert_eq!(malformed(&page(&bad)), ErrorKind::Other);
+    // A name that isn't UTF-8.
+    let mut bad = CONNECTED_INTERFACES.to_vec();
+    bad[3] = 0xff;
+    assert_eq!(malformed(&page(&bad)), ErrorKind::Other);
+
+    let mut bad = CONNECTED_HOSTNAME.to_vec();
+    bad[2] = 0xff;
+    assert_eq!(decode_hostname(&page(&bad)).unwrap_err().kind(), ErrorKind::Other);
+}
@@ -1,49 +1,36 @@
+mod addr;
 mod dns;
+mod info;
+pub use info::{hostname, interfaces, InterfaceInfo};
 mod tcpstream;
 pub use tcpstream::*;
 mod tcplistener;
 pub use tcplistener::*;
 mod udp;
 pub use udp::*;
+mod timeout;
 
-// this structure needs to be synchronized with what's in net/src/api.rs
-#[repr(C)]
-#[derive(Debug)]
-enum NetError {
-    // Ok = 0,
-    Unaddressable = 1,
-    SocketInUse = 2,
-    // AccessDenied = 3,
-    Invalid = 4,
-    // Finished = 5,
-    LibraryError = 6,
-    // AlreadyUsed = 7,
-    TimedOut = 8,
-    WouldBlock = 9,
-}
+use addr::{decode_socket_addr, encode_socket_addr};
+use super::{lend, page_pool};
+use crate::io;
+use crate::sys::error::{net_error, xous_error};
 
-#[repr(C, align(4096))]
-struct ConnectRequest {
-    raw: [u8; 4096],
-}
+pub use dns::LookupHost;
 
-#[repr(C, align(4096))]
-struct SendData {
-    raw: [u8; 4096],
-}
+/// `NetError::LibraryError`, which the network service reports for requests
+/// it couldn't make sense of, and which stands in for replies that don't
+/// follow its protocol.
+const LIBRARY_ERROR: u8 = 6;
 
-#[repr(C, align(4096))]
-pub struct ReceiveData {
-    raw: [u8; 4096],
-}
-
-#[repr(C, align(4096))]
-pub struct GetAddress {
-    raw: [u8; 4096],
+/// Converts a reply of a kind the request can't get. A reply that is an
+/// error from the kernel keeps its code.
+fn unexpected_reply(reply: xous::Result) -> io::Error {
+    match reply {
+        xous::Result::Error(e) => xous_error(e),
+        _ => net_error(LIBRARY_ERROR),
+    }
 }
 
-pub use dns::LookupHost;
-
 #[allow(nonstandard_style)]
 pub mod netc {
     pub const AF_INET: u8 = 0;
@@ -2,7 +2,7 @@
 use super::*;
 use crate::fmt;
 use crate::io;
-use crate::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use crate::net::SocketAddr;
 use crate::sync::Arc;
 use core::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
 use core::convert::TryInto;
@@ -22,93 +22,95 @@ pub struct TcpListener {
     local: SocketAddr,
     handle_count: Arc<AtomicUsize>,
     nonblocking: Arc<AtomicBool>,
+    /// Whether the network service agreed to reuse the address when the
+    /// listener was bound.
+    reuse_addr: Arc<AtomicBool>,
 }
 
+/// The byte of a `StdTcpListen` request after the address, which holds
+/// these flags (see `addr`).
+const LISTEN_FLAGS: usize = addr::MAX_LEN;
+/// Asks the network service to bind even if the address is still held by
+/// connections that are closing, like `SO_REUSEADDR`.
+const LISTEN_REUSE_ADDR: u8 = 1 << 0;
+/// Set by the network service in its reply if it honored
+/// `LISTEN_REUSE_ADDR`. Services that predate the flag leave it clear.
+const LISTEN_REUSE_ADDR_HONORED: u8 = 1 << 1;
+
 impl TcpListener {
     pub fn bind(socketaddr: io::Result<&SocketAddr>) -> io::Result<TcpListener> {
+        TcpListener::bind_with(socketaddr, false)
+    }
+
+    /// Binds like `bind`, and if `reuse_addr` is set, asks the network
+    /// service to take the address even if connections that are closing
+    /// still hold it.
+    pub fn bind_with(
+        socketaddr: io::Result<&SocketAddr>,
+        reuse_addr: bool,
+    ) -> io::Result<TcpListener> {
         let addr = socketaddr?;
 
-        let fd = TcpListener::bind_inner(addr)?;
+        let (fd, reuse_addr) = TcpListener::bind_inner(addr, reuse_addr)?;
         return Ok(TcpListener {
             fd: Arc::new(AtomicUsize::new(fd)),
             local: *addr,
             handle_count: Arc::new(AtomicUsize::new(1)),
             nonblocking: Arc::new(AtomicBool::new(false)),
+            reuse_addr: Arc::new(AtomicBool::new(reuse_addr)),
         });
     }
 
+    /// Returns whether the network service agreed to reuse the address, if
+    /// that was asked for when binding.
+    pub fn reuse_addr(&self) -> bool {
+        self.reuse_addr.load(Ordering::Relaxed)
+    }
+
     /// This returns the raw fd of a Listener, so that it can also be used by the
     /// accept routine to replenish the Listener object after its handle has been converted into
-    /// a TcpStream object.
-    fn bind_inner(addr: &SocketAddr) -> io::Result<usize> {
+    /// a TcpStream object. Also returns whether the service honored
+    /// `reuse_addr`.
+    fn bind_inner(addr: &SocketAddr, reuse_addr: bool) -> io::Result<(usize, bool)> {
         // Construct the request
-        let mut connect_request = ConnectRequest { raw: [0u8; 4096] };
+        let mut connect_request = page_pool::get();
 
-        // Serialize the StdUdpBind structure. This is done "manually" because we don't want to
-        // make an auto-serdes (like bincode or rkyv) crate a dependency of Xous.
-        let port_bytes = addr.port().to_le_bytes();
-        connect_request.raw[0] = port_bytes[0];
-        connect_request.raw[1] = port_bytes[1];
-        match addr.ip() {
-            IpAddr::V4(addr) => {
-                connect_request.raw[2] = 4;
-                for (dest, src) in connect_request.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
-            IpAddr::V6(addr) => {
-                connect_request.raw[2] = 6;
-                for (dest, src) in connect_request.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
+        // Serialize the request by hand, since Xous has no serialization crate
+        // to depend on here (see `addr`).
+        encode_socket_addr(addr, &mut connect_request[..]);
+        if reuse_addr {
+            connect_request[LISTEN_FLAGS] = LISTEN_REUSE_ADDR;
         }
 
-        let buf = unsafe {
-            xous::MemoryRange::new(
-                &mut connect_request as *mut ConnectRequest as usize,
-                core::mem::size_of::<ConnectRequest>(),
-            )
-            .unwrap()
-        };
+        let buf = connect_request.range();
 
-        let response = xous::send_message(
-            services::network(),
-            xous::Message::new_lend_mut(
-                44, /* StdTcpListen */
-                buf,
-                None,
-                xous::MemorySize::new(4096),
-            ),
-        );
+        let response = services::retry_if_gone(|| {
+            services::send(
+                services::network(),
+                xous::Message::new_lend_mut(
+                    44, /* StdTcpListen */
+                    buf,
+                    None,
+                    xous::MemorySize::new(4096),
+                ),
+            )
+        });
 
-        if let Ok(xous::Result::MemoryReturned(_, valid)) = response {
+        let reply = response?;
+        if let xous::Result::MemoryReturned(_, valid) = reply {
             // The first four bytes should be zero upon success, and will be nonzero
             // for an error.
             let response = buf.as_slice::<u8>();
             if response[0] != 0 || valid.is_none() {
                 let errcode = response[1];
-                if errcode == NetError::SocketInUse as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::ResourceBusy, &"Socket in use"));
-                } else if errcode == NetError::Invalid as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::InvalidInput,
-                        &"Port can't be 0 or invalid address"
-                    ));
-                } else if errcode == NetError::LibraryError as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"Library error"));
-                } else {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::Other,
-                        &"Unable to connect or internal error"
-                    ));
-                }
+                return Err(net_error(errcode));
             }
             let fd = response[1] as usize;
             // println!("TcpListening with file handle of {}\r\n", fd);
-            return Ok(fd);
+            let honored = response[LISTEN_FLAGS] & LISTEN_REUSE_ADDR_HONORED != 0;
+            return Ok((fd, reuse_addr && honored));
         }
-        Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Invalid response"))
+        Err(unexpected_reply(reply))
     }
 
     pub fn socket_addr(&self) -> io::Result<SocketAddr> {
@@ -116,20 +118,18 @@ pub fn socket_addr(&self) -> io::Result<SocketAddr> {
     }
 
     pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
-        let mut receive_request = ReceiveData { raw: [0u8; 4096] };
+        let mut receive_request = page_pool::get();
 
-        let range = unsafe {
-            xous::MemoryRange::new(&mut receive_request as *mut ReceiveData as usize, 4096).unwrap()
-        };
+        let range = receive_request.range();
         if self.nonblocking.load(Ordering::Relaxed) {
             // nonblocking
-            receive_request.raw[0] = 0;
+            receive_request[0] = 0;
         } else {
             // blocking
-            receive_request.raw[0] = 1;
+            receive_request[0] = 1;
         }
 
-        if let Ok(xous::Result::MemoryReturned(_offset, _valid)) = xous::send_message(
+        let reply = services::send(
             services::network(),
             xous::Message::new_lend_mut(
                 45 | (self.fd.load(Ordering::Relaxed) << 16), /* StdTcpAccept */
@@ -137,48 +137,20 @@ pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
                 None,
                 None,
             ),
-        ) {
-            if receive_request.raw[0] != 0 {
+        )?;
+        if let xous::Result::MemoryReturned(_offset, _valid) = reply {
+            if receive_request[0] != 0 {
                 // error case
-                if receive_request.raw[1] == NetError::TimedOut as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::TimedOut, &"accept timed out",));
-                } else if receive_request.raw[1] == NetError::WouldBlock as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::WouldBlock,
-                        &"accept would block",
-                    ));
-                } else if receive_request.raw[1] == NetError::LibraryError as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"Library error"));
-                } else {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"library error",));
-                }
+                return Err(net_error(receive_request[1]));
             } else {
                 // accept successful
-                let rr = &receive_request.raw;
+                let rr = &receive_request[..];
                 let stream_fd = u16::from_le_bytes(rr[1..3].try_into().unwrap());
-                let port = u16::from_le_bytes(rr[20..22].try_into().unwrap());
-                let addr = if rr[3] == 4 {
-                    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(rr[4], rr[5], rr[6], rr[7])), port)
-                } else if rr[3] == 6 {
-                    SocketAddr::new(
-                        IpAddr::V6(Ipv6Addr::new(
-                            u16::from_be_bytes(rr[4..6].try_into().unwrap()),
-                            u16::from_be_bytes(rr[6..8].try_into().unwrap()),
-                            u16::from_be_bytes(rr[8..10].try_into().unwrap()),
-                            u16::from_be_bytes(rr[10..12].try_into().unwrap()),
-                            u16::from_be_bytes(rr[12..14].try_into().unwrap()),
-                            u16::from_be_bytes(rr[14..16].try_into().unwrap()),
-                            u16::from_be_bytes(rr[16..18].try_into().unwrap()),
-                            u16::from_be_bytes(rr[18..20].try_into().unwrap()),
-                        )),
-                        port,
-                    )
-                } else {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"library error",));
-                };
+                let (addr, _) = decode_socket_addr(&rr[3..])?;
+                let port = addr.port();
 
                 // replenish the listener
-                let new_fd = TcpListener::bind_inner(&self.local)?;
+                let (new_fd, _) = TcpListener::bind_inner(&self.local, self.reuse_addr())?;
                 self.fd.store(new_fd, Ordering::Relaxed);
 
                 // now return a stream converted from the old stream's fd
@@ -193,7 +165,7 @@ pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
                 ))
             }
         } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unable to accept"))
+            Err(unexpected_reply(reply))
         }
     }
 
@@ -203,7 +175,7 @@ pub fn duplicate(&self) -> io::Result<TcpListener> {
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 37 | ((self.fd.load(Ordering::Relaxed) as usize) << 16), //StdSetTtl = 37
@@ -213,12 +185,11 @@ pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .map(|_| ())
     }
 
     pub fn ttl(&self) -> io::Result<u32> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 36 | ((self.fd.load(Ordering::Relaxed) as usize) << 16), //StdGetTtl = 36
@@ -228,12 +199,11 @@ pub fn ttl(&self) -> io::Result<u32> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .and_then(|res| {
             if let xous::Result::Scalar1(ttl) = res {
                 Ok(ttl as u32)
             } else {
-                Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value"))
+                Err(unexpected_reply(res))
             }
         })
     }
@@ -267,7 +237,7 @@ impl Drop for TcpListener {
     fn drop(&mut self) {
         if self.handle_count.fetch_sub(1, Ordering::Relaxed) == 1 {
             // only drop if we're the last clone
-            match xous::send_message(
+            match services::send(
                 services::network(),
                 xous::Message::new_blocking_scalar(
                     34 | ((self.fd.load(Ordering::Relaxed) as usize) << 16), // StdTcpClose - re-using an implementation
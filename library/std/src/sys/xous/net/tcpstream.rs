@@ -1,11 +1,11 @@
 use super::super::services;
 use super::*;
 use crate::fmt;
-use crate::io::{self, IoSlice, IoSliceMut};
-use crate::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6};
-use crate::sync::Arc;
+use crate::io::{self, IoSlice, IoSliceMut, ReadBuf};
+use crate::net::{Shutdown, SocketAddr};
+use crate::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use crate::time::Duration;
-use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 macro_rules! unimpl {
     () => {
@@ -22,35 +22,39 @@ pub struct TcpStream {
     local_port: u16,
     remote_port: u16,
     peer_addr: SocketAddr,
-    // milliseconds
-    read_timeout: Arc<AtomicU32>,
-    // milliseconds
-    write_timeout: Arc<AtomicU32>,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+    write_timeout: Arc<Mutex<Option<Duration>>>,
     handle_count: Arc<AtomicUsize>,
 }
 
+/// Writes a `StdTcpConnect` request for `addr`, with `duration` as the
+/// timeout (see `addr`).
 fn sockaddr_to_buf(duration: Duration, addr: &SocketAddr, buf: &mut [u8]) {
-    // Construct the request.
-    let port_bytes = addr.port().to_le_bytes();
-    buf[0] = port_bytes[0];
-    buf[1] = port_bytes[1];
-    for (dest, src) in buf[2..].iter_mut().zip((duration.as_millis() as u64).to_le_bytes()) {
-        *dest = src;
-    }
-    match addr.ip() {
-        IpAddr::V4(addr) => {
-            buf[10] = 4;
-            for (dest, src) in buf[11..].iter_mut().zip(addr.octets()) {
-                *dest = src;
-            }
-        }
-        IpAddr::V6(addr) => {
-            buf[10] = 6;
-            for (dest, src) in buf[11..].iter_mut().zip(addr.octets()) {
-                *dest = src;
-            }
-        }
+    encode_socket_addr(addr, buf);
+    let millis = timeout::to_millis(Some(duration));
+    buf[addr::MAX_LEN..addr::MAX_LEN + timeout::LEN].copy_from_slice(&millis.to_le_bytes());
+}
+
+/// Returns the error code in a reply the network service wrote into lent
+/// memory, if it reports a failure: the first word of the reply is nonzero
+/// on failure, and the second is then the code.
+fn error_code(reply: &[u8]) -> Option<u8> {
+    if u32::from_le_bytes(reply[0..4].try_into().unwrap()) == 0 {
+        return None;
     }
+    Some(u32::from_le_bytes(reply[4..8].try_into().unwrap()).min(0xff) as u8)
+}
+
+fn lock(timeout: &Mutex<Option<Duration>>) -> MutexGuard<'_, Option<Duration>> {
+    timeout.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Rejects a timeout of zero, which other platforms reject too.
+fn check_timeout(timeout: Option<Duration>) -> io::Result<Option<Duration>> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Zero duration is invalid"));
+    }
+    Ok(timeout)
 }
 
 impl TcpStream {
@@ -65,8 +69,8 @@ impl TcpStream {
             local_port,
             remote_port,
             peer_addr,
-            read_timeout: Arc::new(AtomicU32::new(0)),
-            write_timeout: Arc::new(AtomicU32::new(0)),
+            read_timeout: Arc::new(Mutex::new(None)),
+            write_timeout: Arc::new(Mutex::new(None)),
             handle_count: Arc::new(AtomicUsize::new(1)),
         }
     }
@@ -76,52 +80,34 @@ pub fn connect(socketaddr: io::Result<&SocketAddr>) -> io::Result<TcpStream> {
     }
 
     pub fn connect_timeout(addr: &SocketAddr, duration: Duration) -> io::Result<TcpStream> {
-        let mut connect_request = ConnectRequest { raw: [0u8; 4096] };
+        let mut connect_request = page_pool::get();
 
         // Construct the request.
-        sockaddr_to_buf(duration, &addr, &mut connect_request.raw);
+        sockaddr_to_buf(duration, &addr, &mut connect_request[..]);
 
-        let buf = unsafe {
-            xous::MemoryRange::new(
-                &mut connect_request as *mut ConnectRequest as usize,
-                core::mem::size_of::<ConnectRequest>(),
-            )
-            .unwrap()
-        };
+        let buf = connect_request.range();
 
-        let response = xous::send_message(
-            services::network(),
-            xous::Message::new_lend_mut(
-                30, /* StdTcpConnect */
-                buf,
-                None,
-                xous::MemorySize::new(4096),
-            ),
-        );
+        let response = services::retry_if_gone(|| {
+            services::send(
+                services::network(),
+                xous::Message::new_lend_mut(
+                    30, /* StdTcpConnect */
+                    buf,
+                    None,
+                    xous::MemorySize::new(4096),
+                ),
+            )
+        });
 
-        if let Ok(xous::Result::MemoryReturned(_, valid)) = response {
+        let reply = response?;
+        if let xous::Result::MemoryReturned(_, valid) = reply {
             // The first four bytes should be zero upon success, and will be nonzero
             // for an error.
             let response = buf.as_slice::<u16>();
             if response[0] != 0 || valid.is_none() {
                 // errcode is a u8 but stuck in a u16 where the upper byte is invalid. Mask & decode accordingly.
                 let errcode = (response[4] & 0xff) as u8;
-                if errcode == NetError::SocketInUse as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::ResourceBusy,
-                        &"Socket in use",
-                    ));
-                } else if errcode == NetError::Unaddressable as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::InvalidInput,
-                        &"Invalid address",
-                    ));
-                } else {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::Other,
-                        &"Unable to connect or internal error",
-                    ));
-                }
+                return Err(net_error(errcode));
             }
             let fd = response[1] as usize;
             let local_port = response[2];
@@ -135,107 +121,96 @@ pub fn connect_timeout(addr: &SocketAddr, duration: Duration) -> io::Result<TcpS
                 local_port,
                 remote_port,
                 peer_addr: *addr,
-                read_timeout: Arc::new(AtomicU32::new(0)),
-                write_timeout: Arc::new(AtomicU32::new(0)),
+                read_timeout: Arc::new(Mutex::new(None)),
+                write_timeout: Arc::new(Mutex::new(None)),
                 handle_count: Arc::new(AtomicUsize::new(1)),
             });
         }
-        Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Invalid response"))
+        Err(unexpected_reply(reply))
     }
 
+    /// Timeouts are kept as given, however long, and only rounded and
+    /// saturated when they are passed to the network service (see `timeout`).
     pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
-        self.read_timeout.store(
-            timeout.map(|t| t.as_millis().min(u32::MAX as u128) as u32).unwrap_or_default(),
-            Ordering::Relaxed,
-        );
+        *lock(&self.read_timeout) = check_timeout(timeout)?;
         Ok(())
     }
 
     pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
-        self.write_timeout.store(
-            timeout.map(|t| t.as_millis().min(u32::MAX as u128) as u32).unwrap_or_default(),
-            Ordering::Relaxed,
-        );
+        *lock(&self.write_timeout) = check_timeout(timeout)?;
         Ok(())
     }
 
     pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
-        match self.read_timeout.load(Ordering::Relaxed) {
-            0 => Ok(None),
-            t => Ok(Some(Duration::from_millis(t as u64))),
-        }
+        Ok(*lock(&self.read_timeout))
     }
 
     pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
-        match self.write_timeout.load(Ordering::Relaxed) {
-            0 => Ok(None),
-            t => Ok(Some(Duration::from_millis(t as u64))),
-        }
+        Ok(*lock(&self.write_timeout))
     }
 
-    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut receive_request = ReceiveData { raw: [0u8; 4096] };
-        let data_to_read = buf.len().min(receive_request.raw.len());
-
-        let range = unsafe {
-            xous::MemoryRange::new(&mut receive_request as *mut ReceiveData as usize, 4096).unwrap()
-        };
-
-        if let Ok(xous::Result::MemoryReturned(offset, valid)) = xous::send_message(
+    /// Asks for up to a page of data, waiting at most `timeout` for it, and
+    /// returns the page it was received into with how much of it there is.
+    fn receive(
+        &self,
+        length: usize,
+        timeout: Option<Duration>,
+    ) -> io::Result<(page_pool::PageBuf, usize)> {
+        let mut receive_request = page_pool::get();
+        let data_to_read = length.min(receive_request.len());
+        let millis = timeout::to_millis(timeout);
+        receive_request[..timeout::LEN].copy_from_slice(&millis.to_le_bytes());
+
+        let range = receive_request.range();
+
+        let reply = services::send(
             services::network(),
             xous::Message::new_lend_mut(
                 33 | (self.fd << 16), /* StdTcpRx */
                 range,
-                None,
+                timeout::to_offset(millis),
                 xous::MemorySize::new(data_to_read),
             ),
-        ) {
+        )?;
+        if let xous::Result::MemoryReturned(offset, valid) = reply {
             // println!("offset: {:?}, valid: {:?}", offset, valid);
             if offset.is_some() {
-                let length = valid.map_or(0, |v| v.get());
-                for (dest, src) in buf.iter_mut().zip(receive_request.raw[..length].iter()) {
-                    *dest = *src;
-                }
-                Ok(length)
+                let length = valid.map_or(0, |v| v.get()).min(data_to_read);
+                Ok((receive_request, length))
             } else {
-                Err(io::const_io_error!(io::ErrorKind::Other, &"peek_slice failure"))
+                // A failure the service gave no code for is its own.
+                Err(net_error(error_code(&receive_request).unwrap_or(LIBRARY_ERROR)))
             }
         } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Library failure: wrong message type or messaging error"))
+            Err(unexpected_reply(reply))
         }
     }
 
-    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut receive_request = ReceiveData { raw: [0u8; 4096] };
-        let data_to_read = buf.len().min(receive_request.raw.len());
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let (received, length) = self.receive(buf.len(), None)?;
+        buf[..length].copy_from_slice(&received[..length]);
+        Ok(length)
+    }
 
-        let range = unsafe {
-            xous::MemoryRange::new(&mut receive_request as *mut ReceiveData as usize, 4096).unwrap()
-        };
+    /// Receives into the page lent to the network service, waiting at most
+    /// the read timeout.
+    fn receive_with_timeout(&self, length: usize) -> io::Result<(page_pool::PageBuf, usize)> {
+        let timeout = *lock(&self.read_timeout);
+        self.receive(length, timeout)
+    }
 
-        if let Ok(xous::Result::MemoryReturned(offset, valid)) = xous::send_message(
-            services::network(),
-            xous::Message::new_lend_mut(
-                33 | (self.fd << 16), /* StdTcpRx */
-                range,
-                // Reuse the `offset` as the read timeout
-                xous::MemoryAddress::new(self.read_timeout.load(Ordering::Relaxed) as usize),
-                xous::MemorySize::new(data_to_read),
-            ),
-        ) {
-            // println!("offset: {:?}, valid: {:?}", offset, valid);
-            if offset.is_some() {
-                let length = valid.map_or(0, |v| v.get());
-                for (dest, src) in buf.iter_mut().zip(receive_request.raw[..length].iter()) {
-                    *dest = *src;
-                }
-                Ok(length)
-            } else {
-                Err(io::const_io_error!(io::ErrorKind::Other, &"recv_slice failure"))
-            }
-        } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Library failure: wrong message type or messaging error"))
-        }
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let (received, length) = self.receive_with_timeout(buf.len())?;
+        buf[..length].copy_from_slice(&received[..length]);
+        Ok(length)
+    }
+
+    /// Reads like `read`, but straight into the unfilled part of `buf`,
+    /// which doesn't have to be initialized first.
+    pub fn read_buf(&self, buf: &mut ReadBuf<'_>) -> io::Result<()> {
+        let (received, length) = self.receive_with_timeout(buf.remaining())?;
+        buf.append(&received[..length]);
+        Ok(())
     }
 
     pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
@@ -247,43 +222,25 @@ pub fn is_read_vectored(&self) -> bool {
     }
 
     pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
-        let mut send_request = SendData { raw: [0u8; 4096] };
-        for (dest, src) in send_request.raw.iter_mut().zip(buf) {
-            *dest = *src;
-        }
-
-        let range = unsafe {
-            xous::MemoryRange::new(
-                &mut send_request as *mut SendData as usize,
-                core::mem::size_of::<SendData>(),
-            )
-            .unwrap()
-        };
-
-        let response = xous::send_message(
+        // Anything past what can be lent at once, with the timeout after it,
+        // is left for the next write.
+        let data = &buf[..buf.len().min(lend::MAX_LEN - timeout::LEN)];
+        let millis = timeout::to_millis(*lock(&self.write_timeout));
+        let mut request = lend::get_pages(data.len() + timeout::LEN)?;
+        request[..data.len()].copy_from_slice(data);
+        request[data.len()..data.len() + timeout::LEN].copy_from_slice(&millis.to_le_bytes());
+        let result = lend::lend_pages_mut(
             services::network(),
-            xous::Message::new_lend_mut(
-                31 | (self.fd << 16), /* StdTcpTx */
-                range,
-                // Reuse the offset as the timeout
-                xous::MemoryAddress::new(self.write_timeout.load(Ordering::Relaxed) as usize),
-                xous::MemorySize::new(buf.len().min(send_request.raw.len())),
-            ),
-        )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Internal error")))?;
-
-        if let xous::Result::MemoryReturned(_offset, _valid) = response {
-            let result = range.as_slice::<u32>();
-            if result[0] != 0 {
-                return Err(io::const_io_error!(
-                    io::ErrorKind::InvalidInput,
-                    &"Error when sending",
-                ));
-            }
-            Ok(result[1] as usize)
-        } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value"))
+            31 | (self.fd << 16), /* StdTcpTx */
+            request,
+            timeout::to_offset(millis),
+            data.len(),
+        )?;
+
+        if let Some(code) = error_code(&result) {
+            return Err(net_error(code));
         }
+        Ok(u32::from_le_bytes(result[4..8].try_into().unwrap()) as usize)
     }
 
     pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
@@ -299,16 +256,10 @@ pub fn peer_addr(&self) -> io::Result<SocketAddr> {
     }
 
     pub fn socket_addr(&self) -> io::Result<SocketAddr> {
-        let mut get_addr = GetAddress { raw: [0u8; 4096] };
-        let range = unsafe {
-            xous::MemoryRange::new(
-                &mut get_addr as *mut GetAddress as usize,
-                core::mem::size_of::<GetAddress>(),
-            )
-            .unwrap()
-        };
+        let mut get_addr = page_pool::get();
+        let range = get_addr.range();
 
-        match xous::send_message(
+        match services::send(
             services::network(),
             xous::Message::new_lend_mut(
                 35 | (self.fd << 16), /* StdGetAddress */
@@ -316,35 +267,11 @@ pub fn socket_addr(&self) -> io::Result<SocketAddr> {
                 None,
                 None,
             ),
-        ) {
-            Ok(xous::Result::MemoryReturned(_offset, _valid)) => {
-                let mut i = get_addr.raw.iter();
-                match *i.next().unwrap() {
-                    4 => Ok(SocketAddr::V4(SocketAddrV4::new(
-                        Ipv4Addr::new(
-                            *i.next().unwrap(),
-                            *i.next().unwrap(),
-                            *i.next().unwrap(),
-                            *i.next().unwrap(),
-                        ),
-                        self.local_port,
-                    ))),
-                    6 => {
-                        let mut new_addr = [0u8; 16];
-                        for (src, octet) in i.zip(new_addr.iter_mut()) {
-                            *octet = *src;
-                        }
-                        Ok(SocketAddr::V6(SocketAddrV6::new(
-                            new_addr.into(),
-                            self.local_port,
-                            0,
-                            0,
-                        )))
-                    }
-                    _ => Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Internal error")),
-                }
+        )? {
+            xous::Result::MemoryReturned(_offset, _valid) => {
+                decode_socket_addr(&get_addr[..]).map(|(addr, _)| addr)
             }
-            _ => Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Internal error")),
+            reply => Err(unexpected_reply(reply)),
         }
     }
 
@@ -355,7 +282,7 @@ pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
             crate::net::Shutdown::Both => 3,
         };
 
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 46 | ((self.fd as usize) << 16), // StdTcpStreamShutdown
@@ -365,7 +292,6 @@ pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .map(|_| ())
     }
 
@@ -383,7 +309,7 @@ pub fn linger(&self) -> io::Result<Option<Duration>> {
     }
 
     pub fn set_nodelay(&self, enabled: bool) -> io::Result<()> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 39 | ((self.fd as usize) << 16), //StdSetNodelay = 39
@@ -393,12 +319,11 @@ pub fn set_nodelay(&self, enabled: bool) -> io::Result<()> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .map(|_| ())
     }
 
     pub fn nodelay(&self) -> io::Result<bool> {
-        let result = xous::send_message(
+        let result = services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 38 | ((self.fd as usize) << 16), //StdGetNodelay = 38
@@ -407,17 +332,16 @@ pub fn nodelay(&self) -> io::Result<bool> {
                 0,
                 0,
             ),
-        )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))?;
+        )?;
         if let xous::Result::Scalar1(enabled) = result {
             Ok(enabled != 0)
         } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value"))
+            Err(unexpected_reply(result))
         }
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 37 | ((self.fd as usize) << 16), //StdSetTtl = 37
@@ -427,12 +351,11 @@ pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .map(|_| ())
     }
 
     pub fn ttl(&self) -> io::Result<u32> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 36 | ((self.fd as usize) << 16), //StdGetTtl = 36
@@ -442,12 +365,11 @@ pub fn ttl(&self) -> io::Result<u32> {
                 0,
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .and_then(|res| {
             if let xous::Result::Scalar1(ttl) = res {
                 Ok(ttl as u32)
             } else {
-                Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value"))
+                Err(unexpected_reply(res))
             }
         })
     }
@@ -476,7 +398,7 @@ impl Drop for TcpStream {
     fn drop(&mut self) {
         if self.handle_count.fetch_sub(1, Ordering::Relaxed) == 1 {
             // only drop if we're the last clone
-            match xous::send_message(
+            match services::send(
                 services::network(),
                 xous::Message::new_blocking_scalar(
                     34 | ((self.fd as usize) << 16), // StdTcpClose
@@ -0,0 +1,46 @@
+//! How socket and lookup timeouts are passed to the network service and the
+//! resolver.
+//!
+//! A timeout is a number of milliseconds as a `u64`, where zero means none.
+//! It is carried in the request itself, little-endian, wherever the request
+//! puts it:
+//!
+//! * `StdTcpConnect` has it after the address (see `addr`).
+//! * `StdTcpRx` has it at the start of the lent memory, which the data that
+//!   is received then overwrites.
+//! * `StdTcpTx` has it right after the data, at offset `valid`.
+//! * `StdUdpRx` has it at offset 1, after the blocking flag.
+//! * The resolver's `RawLookup` and `RawMdnsLookup` have it right after the
+//!   name being looked up, at offset `valid`, and also as the offset.
+//!
+//! `StdTcpRx` and `StdTcpTx` also pass the timeout as the offset of the lent
+//! memory, cut short at `usize::MAX` milliseconds, which is about 49.7 days
+//! on Xous. That is where services that predate the timeout in the request
+//! read it from. A service that reads the request can tell a request that
+//! carries a timeout by the offset being that timeout cut short the same way,
+//! which the bytes of a request from before are very unlikely to match.
+
+use crate::time::Duration;
+
+/// The bytes a timeout takes in a request.
+pub const LEN: usize = 8;
+
+/// Converts a socket timeout to what the network service takes. Durations
+/// are rounded up to the next millisecond, so that a short timeout doesn't
+/// turn into none at all, and saturate at `u64::MAX` milliseconds, the
+/// longest the service can wait for short of forever.
+pub fn to_millis(timeout: Option<Duration>) -> u64 {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return 0,
+    };
+    let partial = timeout.subsec_nanos() % 1_000_000 != 0;
+    let millis = timeout.as_millis() + partial as u128;
+    millis.min(u64::MAX as u128) as u64
+}
+
+/// Returns `millis` as the offset of lent memory, for services that predate
+/// the timeout in the request.
+pub fn to_offset(millis: u64) -> Option<xous::MemoryAddress> {
+    xous::MemoryAddress::new(millis.min(usize::MAX as u64) as usize)
+}
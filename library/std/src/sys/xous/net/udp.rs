@@ -3,7 +3,7 @@
 use crate::cell::Cell;
 use crate::fmt;
 use crate::io;
-use crate::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use crate::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use crate::sync::Arc;
 use crate::time::Duration;
 use core::convert::TryInto;
@@ -23,10 +23,10 @@ pub struct UdpSocket {
     fd: usize,
     local: SocketAddr,
     remote: Cell<Option<SocketAddr>>,
-    // in milliseconds. The setting applies only to `recv` calls after the timeout is set.
-    read_timeout: Cell<u64>,
-    // in milliseconds. The setting applies only to `send` calls after the timeout is set.
-    write_timeout: Cell<u64>,
+    // The setting applies only to `recv` calls after the timeout is set.
+    read_timeout: Cell<Option<Duration>>,
+    // The setting applies only to `send` calls after the timeout is set.
+    write_timeout: Cell<Option<Duration>>,
     handle_count: Arc<AtomicUsize>,
     nonblocking: Cell<bool>,
 }
@@ -35,67 +35,34 @@ impl UdpSocket {
     pub fn bind(socketaddr: io::Result<&SocketAddr>) -> io::Result<UdpSocket> {
         let addr = socketaddr?;
         // Construct the request
-        let mut connect_request = ConnectRequest { raw: [0u8; 4096] };
-
-        // Serialize the StdUdpBind structure. This is done "manually" because we don't want to
-        // make an auto-serdes (like bincode or rkyv) crate a dependency of Xous.
-        let port_bytes = addr.port().to_le_bytes();
-        connect_request.raw[0] = port_bytes[0];
-        connect_request.raw[1] = port_bytes[1];
-        match addr.ip() {
-            IpAddr::V4(addr) => {
-                connect_request.raw[2] = 4;
-                for (dest, src) in connect_request.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
-            IpAddr::V6(addr) => {
-                connect_request.raw[2] = 6;
-                for (dest, src) in connect_request.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
-        }
+        let mut connect_request = page_pool::get();
 
-        let buf = unsafe {
-            xous::MemoryRange::new(
-                &mut connect_request as *mut ConnectRequest as usize,
-                core::mem::size_of::<ConnectRequest>(),
-            )
-            .unwrap()
-        };
+        // Serialize the request by hand, since Xous has no serialization crate
+        // to depend on here (see `addr`).
+        encode_socket_addr(addr, &mut connect_request[..]);
 
-        let response = xous::send_message(
-            services::network(),
-            xous::Message::new_lend_mut(
-                40, /* StdUdpBind */
-                buf,
-                None,
-                xous::MemorySize::new(4096),
-            ),
-        );
+        let buf = connect_request.range();
+
+        let response = services::retry_if_gone(|| {
+            services::send(
+                services::network(),
+                xous::Message::new_lend_mut(
+                    40, /* StdUdpBind */
+                    buf,
+                    None,
+                    xous::MemorySize::new(4096),
+                ),
+            )
+        });
 
-        if let Ok(xous::Result::MemoryReturned(_, valid)) = response {
+        let reply = response?;
+        if let xous::Result::MemoryReturned(_, valid) = reply {
             // The first four bytes should be zero upon success, and will be nonzero
             // for an error.
             let response = buf.as_slice::<u8>();
             if response[0] != 0 || valid.is_none() {
                 let errcode = response[1];
-                if errcode == NetError::SocketInUse as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::ResourceBusy, &"Socket in use"));
-                } else if errcode == NetError::Invalid as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::InvalidInput,
-                        &"Port can't be 0 or invalid address"
-                    ));
-                } else if errcode == NetError::LibraryError as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"Library error"));
-                } else {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::Other,
-                        &"Unable to connect or internal error"
-                    ));
-                }
+                return Err(net_error(errcode));
             }
             let fd = response[1] as usize;
             // println!("Connected with file handle of {}\r\n", fd);
@@ -103,13 +70,13 @@ pub fn bind(socketaddr: io::Result<&SocketAddr>) -> io::Result<UdpSocket> {
                 fd,
                 local: *addr,
                 remote: Cell::new(None),
-                read_timeout: Cell::new(0),
-                write_timeout: Cell::new(0),
+                read_timeout: Cell::new(None),
+                write_timeout: Cell::new(None),
                 handle_count: Arc::new(AtomicUsize::new(1)),
                 nonblocking: Cell::new(false),
             });
         }
-        Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Invalid response"))
+        Err(unexpected_reply(reply))
     }
 
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
@@ -124,29 +91,20 @@ pub fn socket_addr(&self) -> io::Result<SocketAddr> {
     }
 
     fn recv_inner(&self, buf: &mut [u8], do_peek: bool) -> io::Result<(usize, SocketAddr)> {
-        let mut receive_request = ReceiveData { raw: [0u8; 4096] };
+        let mut receive_request = page_pool::get();
 
-        let range = unsafe {
-            xous::MemoryRange::new(&mut receive_request as *mut ReceiveData as usize, 4096).unwrap()
-        };
+        let range = receive_request.range();
         if self.nonblocking.get() {
             // nonblocking
-            receive_request.raw[0] = 0;
+            receive_request[0] = 0;
         } else {
             // blocking
-            receive_request.raw[0] = 1;
-            for (&s, d) in self
-                .read_timeout
-                .get()
-                .to_le_bytes()
-                .iter()
-                .zip(receive_request.raw[1..9].iter_mut())
-            {
-                *d = s;
-            }
+            receive_request[0] = 1;
+            let millis = timeout::to_millis(self.read_timeout.get());
+            receive_request[1..1 + timeout::LEN].copy_from_slice(&millis.to_le_bytes());
         }
         let peek = if do_peek { Some(core::num::NonZeroUsize::new(1).unwrap()) } else { None };
-        if let Ok(xous::Result::MemoryReturned(_offset, _valid)) = xous::send_message(
+        let reply = services::send(
             services::network(),
             xous::Message::new_lend_mut(
                 42 | (self.fd << 16), /* StdUdpRx */
@@ -154,51 +112,23 @@ fn recv_inner(&self, buf: &mut [u8], do_peek: bool) -> io::Result<(usize, Socket
                 peek,
                 None,
             ),
-        ) {
-            if receive_request.raw[0] != 0 {
+        )?;
+        if let xous::Result::MemoryReturned(_offset, _valid) = reply {
+            if receive_request[0] != 0 {
                 // error case
-                if receive_request.raw[1] == NetError::TimedOut as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::TimedOut, &"recv timed out",));
-                } else if receive_request.raw[1] == NetError::WouldBlock as u8 {
-                    return Err(io::const_io_error!(
-                        io::ErrorKind::WouldBlock,
-                        &"recv would block",
-                    ));
-                } else if receive_request.raw[1] == NetError::LibraryError as u8 {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"Library error"));
-                } else {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"library error",));
-                }
+                return Err(net_error(receive_request[1]));
             } else {
-                let rr = &receive_request.raw;
+                let rr = &receive_request[..];
                 let rxlen = u16::from_le_bytes(rr[1..3].try_into().unwrap());
-                let port = u16::from_le_bytes(rr[20..22].try_into().unwrap());
-                let addr = if rr[3] == 4 {
-                    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(rr[4], rr[5], rr[6], rr[7])), port)
-                } else if rr[3] == 6 {
-                    SocketAddr::new(
-                        IpAddr::V6(Ipv6Addr::new(
-                            u16::from_be_bytes(rr[4..6].try_into().unwrap()),
-                            u16::from_be_bytes(rr[6..8].try_into().unwrap()),
-                            u16::from_be_bytes(rr[8..10].try_into().unwrap()),
-                            u16::from_be_bytes(rr[10..12].try_into().unwrap()),
-                            u16::from_be_bytes(rr[12..14].try_into().unwrap()),
-                            u16::from_be_bytes(rr[14..16].try_into().unwrap()),
-                            u16::from_be_bytes(rr[16..18].try_into().unwrap()),
-                            u16::from_be_bytes(rr[18..20].try_into().unwrap()),
-                        )),
-                        port,
-                    )
-                } else {
-                    return Err(io::const_io_error!(io::ErrorKind::Other, &"library error",));
-                };
-                for (&s, d) in rr[22..22 + rxlen as usize].iter().zip(buf.iter_mut()) {
+                let (addr, _) = decode_socket_addr(&rr[3..])?;
+                let data = &rr[3 + addr::MAX_LEN..];
+                for (&s, d) in data[..(rxlen as usize).min(data.len())].iter().zip(buf.iter_mut()) {
                     *d = s;
                 }
                 Ok((rxlen as usize, addr))
             }
         } else {
-            Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unable to recv"))
+            Err(unexpected_reply(reply))
         }
     }
 
@@ -233,41 +163,17 @@ pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
     }
 
     pub fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
-        let mut tx_req = SendData { raw: [0u8; 4096] };
-
-        // Construct the request.
-        let port_bytes = addr.port().to_le_bytes();
-        tx_req.raw[0] = port_bytes[0];
-        tx_req.raw[1] = port_bytes[1];
-        match addr.ip() {
-            IpAddr::V4(addr) => {
-                tx_req.raw[2] = 4;
-                for (dest, src) in tx_req.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
-            IpAddr::V6(addr) => {
-                tx_req.raw[2] = 6;
-                for (dest, src) in tx_req.raw[3..].iter_mut().zip(addr.octets()) {
-                    *dest = src;
-                }
-            }
-        }
+        let mut tx_req = page_pool::get();
+
+        // Construct the request (see `addr`).
+        encode_socket_addr(addr, &mut tx_req[..]);
         let len = buf.len() as u16;
-        let len_bytes = len.to_le_bytes();
-        tx_req.raw[19] = len_bytes[0];
-        tx_req.raw[20] = len_bytes[1];
-        for (&s, d) in buf.iter().zip(tx_req.raw[21..].iter_mut()) {
+        tx_req[addr::MAX_LEN..addr::MAX_LEN + 2].copy_from_slice(&len.to_le_bytes());
+        for (&s, d) in buf.iter().zip(tx_req[addr::MAX_LEN + 2..].iter_mut()) {
             *d = s;
         }
 
-        let buf = unsafe {
-            xous::MemoryRange::new(
-                &mut tx_req as *mut SendData as usize,
-                core::mem::size_of::<SendData>(),
-            )
-            .unwrap()
-        };
+        let buf = tx_req.range();
 
         // write time-outs are implemented on the caller side. Basically, if the Net crate server
         // is too busy to take the call immediately: retry, until the timeout is reached.
@@ -276,14 +182,8 @@ pub fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
             // nonblocking
             core::time::Duration::ZERO
         } else {
-            // blocking
-            if self.write_timeout.get() == 0 {
-                // forever
-                core::time::Duration::from_millis(u64::MAX)
-            } else {
-                // or this amount of time
-                core::time::Duration::from_millis(self.write_timeout.get())
-            }
+            // blocking, forever or for this amount of time
+            self.write_timeout.get().unwrap_or(Duration::MAX)
         };
         loop {
             let response = xous::try_send_message(
@@ -300,27 +200,7 @@ pub fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
                     let response = buf.as_slice::<u8>();
                     if response[0] != 0 || valid.is_none() {
                         let errcode = response[1];
-                        if errcode == NetError::SocketInUse as u8 {
-                            return Err(io::const_io_error!(
-                                io::ErrorKind::ResourceBusy,
-                                &"Socket in use"
-                            ));
-                        } else if errcode == NetError::Invalid as u8 {
-                            return Err(io::const_io_error!(
-                                io::ErrorKind::InvalidInput,
-                                &"Socket not valid"
-                            ));
-                        } else if errcode == NetError::LibraryError as u8 {
-                            return Err(io::const_io_error!(
-                                io::ErrorKind::Other,
-                                &"Library error"
-                            ));
-                        } else {
-                            return Err(io::const_io_error!(
-                                io::ErrorKind::Other,
-                                &"Unable to connect"
-                            ));
-                        }
+                        return Err(net_error(errcode));
                     } else {
                         // no error
                         return Ok(len as usize);
@@ -328,16 +208,14 @@ pub fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
                 }
                 Ok(xous::Result::RetryCall) | Err(xous::Error::ServerQueueFull) => {
                     if now.elapsed() >= write_timeout {
-                        return Err(io::const_io_error!(
-                            io::ErrorKind::WouldBlock,
-                            &"Write timed out"
-                        ));
+                        return Err(xous_error(xous::Error::ServerQueueFull));
                     } else {
                         // question: do we want to do something a bit more gentle than immediately retrying?
                         xous::yield_slice();
                     }
                 }
-                _ => return Err(io::const_io_error!(io::ErrorKind::Other, &"Library error")),
+                Err(e) => return Err(xous_error(e)),
+                Ok(reply) => return Err(unexpected_reply(reply)),
             }
         }
     }
@@ -356,8 +234,7 @@ pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
                 ));
             }
         }
-        self.read_timeout
-            .set(timeout.map(|t| t.as_millis().min(u64::MAX as u128) as u64).unwrap_or_default());
+        self.read_timeout.set(timeout);
         Ok(())
     }
 
@@ -370,30 +247,23 @@ pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
                 ));
             }
         }
-        self.write_timeout
-            .set(timeout.map(|t| t.as_millis().min(u64::MAX as u128) as u64).unwrap_or_default());
+        self.write_timeout.set(timeout);
         Ok(())
     }
 
     pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
-        match self.read_timeout.get() {
-            0 => Ok(None),
-            t => Ok(Some(Duration::from_millis(t as u64))),
-        }
+        Ok(self.read_timeout.get())
     }
 
     pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
-        match self.write_timeout.get() {
-            0 => Ok(None),
-            t => Ok(Some(Duration::from_millis(t as u64))),
-        }
+        Ok(self.write_timeout.get())
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
         if ttl > 255 {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "TTL must be less than 256"));
         }
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 37 | ((self.fd as usize) << 16), //StdSetTtl = 37
@@ -403,12 +273,11 @@ pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
                 1, // specify udp
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .map(|_| ())
     }
 
     pub fn ttl(&self) -> io::Result<u32> {
-        xous::send_message(
+        services::send(
             services::network(),
             xous::Message::new_blocking_scalar(
                 36 | ((self.fd as usize) << 16), //StdGetTtl = 36
@@ -418,12 +287,11 @@ pub fn ttl(&self) -> io::Result<u32> {
                 1, // specify udp
             ),
         )
-        .or(Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value")))
         .and_then(|res| {
             if let xous::Result::Scalar1(ttl) = res {
                 Ok(ttl as u32)
             } else {
-                Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Unexpected return value"))
+                Err(unexpected_reply(res))
             }
         })
     }
@@ -498,7 +366,7 @@ impl Drop for UdpSocket {
     fn drop(&mut self) {
         if self.handle_count.fetch_sub(1, Ordering::Relaxed) == 1 {
             // only drop if we're the last clone
-            match xous::send_message(
+            match services::send(
                 services::network(),
                 xous::Message::new_blocking_scalar(
                     41 | ((self.fd as usize) << 16), // StdUdpClose
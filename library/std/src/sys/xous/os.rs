@@ -1,24 +1,54 @@
-use super::{unsupported, Void};
+use super::error::loader_error;
+use super::{lend, params, services, Void};
+use crate::collections::BTreeMap;
 use crate::error::Error as StdError;
 use crate::ffi::{OsStr, OsString};
 use crate::fmt;
 use crate::io;
+use crate::lazy::SyncOnceCell;
 use crate::path::{self, PathBuf};
+use crate::sys::os_str::Buf;
+use crate::sys_common::mutex::StaticMutex;
+use crate::sys_common::FromInner;
+use crate::vec;
 
-pub fn errno() -> i32 {
-    0
-}
+pub use super::error::{errno, set_errno};
 
-pub fn error_string(_errno: i32) -> String {
-    "operation successful".to_string()
+pub fn error_string(errno: i32) -> String {
+    super::error::error_string(errno).to_string()
 }
 
+/// The working directory is kept by the process itself, since there is no
+/// kernel notion of one. It starts out as whatever the loader passed in its
+/// parameter block, or the root, and `CWD` is only touched with `CWD_LOCK`
+/// held, so changes are seen by every thread.
+static CWD_LOCK: StaticMutex = StaticMutex::new();
+static mut CWD: Option<PathBuf> = None;
+
 pub fn getcwd() -> io::Result<PathBuf> {
-    unsupported()
+    unsafe {
+        let _guard = CWD_LOCK.lock();
+        Ok(CWD.clone().unwrap_or_else(|| match params::block(params::CWD_TAG) {
+            Some(dir) if !dir.is_empty() => {
+                PathBuf::from(OsString::from_inner(Buf { inner: dir.to_vec() }))
+            }
+            _ => PathBuf::from("/"),
+        }))
+    }
 }
 
-pub fn chdir(_: &path::Path) -> io::Result<()> {
-    unsupported()
+pub fn chdir(p: &path::Path) -> io::Result<()> {
+    // Resolve relative paths against the current directory before taking
+    // the lock, since doing so reads the current directory.
+    let p = super::path::absolute(p)?;
+    if !super::fs::stat(&p)?.file_type().is_dir() {
+        return Err(io::const_io_error!(io::ErrorKind::NotADirectory, &"not a directory"));
+    }
+    unsafe {
+        let _guard = CWD_LOCK.lock();
+        CWD = Some(p);
+    }
+    Ok(())
 }
 
 pub struct SplitPaths<'a>(&'a Void);
@@ -58,45 +88,141 @@ fn description(&self) -> &str {
     }
 }
 
+/// The name this process was started as, once it has been found. It can't
+/// change, so it is only looked up once.
+static EXE_NAME: SyncOnceCell<Vec<u8>> = SyncOnceCell::new();
+
+/// Returns the name the loader started this process as, such as
+/// `shellchat`. Programs aren't loaded from a filesystem, so this is a bare
+/// name rather than a path: the same name `Command::new` takes to start the
+/// program again.
+///
+/// The name is taken from the loader's parameter block if it is there, and
+/// otherwise asked of the loader itself.
 pub fn current_exe() -> io::Result<PathBuf> {
-    unsupported()
+    let name = EXE_NAME.get_or_try_init(|| match params::block(params::NAME_TAG) {
+        Some(name) if !name.is_empty() => Ok(name.to_vec()),
+        _ => query_exe_name(),
+    })?;
+    Ok(PathBuf::from(OsString::from_inner(Buf { inner: name.clone() })))
+}
+
+/// Asks the loader what the calling process was started as. `ProcessNameStd`
+/// lends the loader a page to write the name into, and the loader tells who
+/// is asking from the sender of the message. It answers with the length of
+/// the name as the valid size, or with a result code in the offset if it
+/// doesn't know the caller.
+fn query_exe_name() -> io::Result<Vec<u8>> {
+    let returned =
+        lend::lend_mut_copy(services::loader(), 9 /* ProcessNameStd */, &[], None, 0)?;
+    if let Some(code) = returned.offset {
+        return Err(loader_error(code.get()));
+    }
+    match &returned[..returned.valid.min(returned.len())] {
+        [] => Err(io::const_io_error!(
+            io::ErrorKind::NotFound,
+            &"the loader did not provide a name for this process"
+        )),
+        name => Ok(name.to_vec()),
+    }
 }
 
-pub struct Env(Void);
+/// The environment belongs to the process, and starts out as whatever the
+/// loader passed in its parameter block. `ENV` is only touched with
+/// `ENV_LOCK` held. The lock doesn't poison and is released if a caller
+/// panics, so an early panic can't lock everyone else out of the
+/// environment.
+static ENV_LOCK: StaticMutex = StaticMutex::new();
+static mut ENV: Option<BTreeMap<OsString, OsString>> = None;
+
+fn with_env<R>(f: impl FnOnce(&mut BTreeMap<OsString, OsString>) -> R) -> R {
+    unsafe {
+        let _guard = ENV_LOCK.lock();
+        let env = ENV.get_or_insert_with(|| {
+            params::block(params::ENV_TAG)
+                .and_then(params::decode_env)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        });
+        f(env)
+    }
+}
+
+pub struct Env {
+    iter: vec::IntoIter<(OsString, OsString)>,
+}
 
 impl Iterator for Env {
     type Item = (OsString, OsString);
     fn next(&mut self) -> Option<(OsString, OsString)> {
-        match self.0 {}
+        self.iter.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
     }
 }
 
+/// Returns a snapshot of the environment. Changes made afterwards aren't
+/// reflected in it.
 pub fn env() -> Env {
-    panic!("not supported on this platform")
+    let vars: Vec<_> = with_env(|env| env.iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+    Env { iter: vars.into_iter() }
 }
 
-pub fn getenv(_: &OsStr) -> Option<OsString> {
-    None
+pub fn getenv(k: &OsStr) -> Option<OsString> {
+    with_env(|env| env.get(k).cloned())
 }
 
-pub fn setenv(_: &OsStr, _: &OsStr) -> io::Result<()> {
-    Err(io::Error::new(io::ErrorKind::Other, "cannot set env vars on this platform"))
+pub fn setenv(k: &OsStr, v: &OsStr) -> io::Result<()> {
+    if k.is_empty() || k.bytes().contains(&b'=') {
+        return Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            &"environment variable names must be nonempty and not contain '='"
+        ));
+    }
+    with_env(|env| env.insert(k.to_owned(), v.to_owned()));
+    Ok(())
 }
 
-pub fn unsetenv(_: &OsStr) -> io::Result<()> {
-    Err(io::Error::new(io::ErrorKind::Other, "cannot unset env vars on this platform"))
+pub fn unsetenv(k: &OsStr) -> io::Result<()> {
+    with_env(|env| env.remove(k));
+    Ok(())
 }
 
+/// Scratch files go in the `.tmp` dict of the default view of the PDDB.
+/// Nothing creates it ahead of time; creating a file or directory directly
+/// inside it creates it first. It is ordinary PDDB storage, so anything left
+/// in it survives a reboot unless the basis is set up to be discarded.
+/// `TMPDIR` overrides it.
 pub fn temp_dir() -> PathBuf {
-    panic!("no filesystem on this platform")
+    match getenv(OsStr::new("TMPDIR")) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from("/.tmp"),
+    }
 }
 
+/// A process's home is the dict named after it, or the root if the loader
+/// didn't say what the process is called. `HOME` overrides it.
 pub fn home_dir() -> Option<PathBuf> {
-    None
+    if let Some(dir) = getenv(OsStr::new("HOME")).filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let mut home = PathBuf::from("/");
+    if let Some(name) = current_exe().ok().as_deref().and_then(path::Path::file_name) {
+        home.push(name);
+    }
+    Some(home)
 }
 
+/// Ends the process, handing `code` to the kernel as the argument of
+/// `TerminateProcess`. Whoever started the process collects it from there,
+/// and reads it back as the same `i32`.
 pub fn exit(code: i32) -> ! {
     use xous::syscall::terminate_process;
+    // Not every caller goes through the runtime cleanup, so don't leave the
+    // console raw.
+    super::stdio::restore_cooked_mode();
     terminate_process(code as u32);
 }
 
@@ -0,0 +1,194 @@
+//! Page-aligned buffers for lending to servers.
+//!
+//! Memory can only be lent a whole page at a time, so every request that
+//! lends a buffer needs a page-aligned one. Declaring it on the stack would
+//! take up a page of the thread's stack, which may be all there is, so
+//! buffers come from here instead. A few pages that have been given back are
+//! kept for the next request, and more are allocated whenever none is free,
+//! so taking a page never waits and a request can hold as many as it needs.
+
+use crate::alloc::{alloc_zeroed, handle_alloc_error, Layout};
+use crate::fmt;
+use crate::ops::{Deref, DerefMut};
+use crate::ptr;
+use crate::slice;
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sys_common::mutex::StaticMutex;
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// The most pages kept for reuse. Pages given back beyond this are freed.
+const MAX_FREE: usize = 8;
+
+#[repr(C, align(4096))]
+struct Page([u8; PAGE_SIZE]);
+
+static FREE_LOCK: StaticMutex = StaticMutex::new();
+static mut FREE: Vec<Box<Page>> = Vec::new();
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static REUSED: AtomicUsize = AtomicUsize::new(0);
+
+fn with_free<R>(f: impl FnOnce(&mut Vec<Box<Page>>) -> R) -> R {
+    unsafe {
+        let _guard = FREE_LOCK.lock();
+        f(&mut FREE)
+    }
+}
+
+/// A zeroed page from the pool, given back when dropped.
+pub struct PageBuf {
+    page: Option<Box<Page>>,
+}
+
+/// Takes a page from the pool, or allocates one if none is free.
+pub fn get() -> PageBuf {
+    let page = match with_free(|free| free.pop()) {
+        Some(mut page) => {
+            REUSED.fetch_add(1, Ordering::Relaxed);
+            page.0.fill(0);
+            page
+        }
+        None => {
+            ALLOCATED.fetch_add(1, Ordering::Relaxed);
+            // Allocated in place, since building it on the stack first is
+            // what this module is meant to avoid.
+            let layout = Layout::new::<Page>();
+            let page = unsafe { alloc_zeroed(layout) } as *mut Page;
+            if page.is_null() {
+                handle_alloc_error(layout);
+            }
+            unsafe { Box::from_raw(page) }
+        }
+    };
+    PageBuf { page: Some(page) }
+}
+
+impl PageBuf {
+    /// The page as a range of memory to lend.
+    pub fn range(&mut self) -> xous::MemoryRange {
+        unsafe { xous::MemoryRange::new(self.as_mut_ptr() as usize, PAGE_SIZE).unwrap() }
+    }
+}
+
+impl Deref for PageBuf {
+    type Target = [u8; PAGE_SIZE];
+
+    fn deref(&self) -> &[u8; PAGE_SIZE] {
+        &self.page.as_ref().unwrap().0
+    }
+}
+
+impl DerefMut for PageBuf {
+    fn deref_mut(&mut self) -> &mut [u8; PAGE_SIZE] {
+        &mut self.page.as_mut().unwrap().0
+    }
+}
+
+impl Drop for PageBuf {
+    fn drop(&mut self) {
+        let page = self.page.take().unwrap();
+        // Anything past the limit is freed once the lock is released.
+        let _excess = with_free(|free| {
+            if free.len() < MAX_FREE {
+                free.push(page);
+                None
+            } else {
+                Some(page)
+            }
+        });
+    }
+}
+
+impl fmt::Debug for PageBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageBuf").finish_non_exhaustive()
+    }
+}
+
+/// Zeroed pages next to each other, for requests that lend more than one.
+/// A single page comes from the pool like any other. Larger buffers are
+/// allocated for the request and freed when dropped, since keeping them
+/// around would tie up memory that is seldom needed.
+pub struct PagesBuf {
+    pages: Pages,
+}
+
+enum Pages {
+    One(PageBuf),
+    Many(Box<[Page]>),
+}
+
+/// Takes `count` pages, which must be at least one.
+pub fn get_pages(count: usize) -> PagesBuf {
+    assert!(count > 0);
+    if count == 1 {
+        return PagesBuf { pages: Pages::One(get()) };
+    }
+    let layout = Layout::array::<Page>(count).unwrap();
+    let pages = unsafe { alloc_zeroed(layout) } as *mut Page;
+    if pages.is_null() {
+        handle_alloc_error(layout);
+    }
+    let pages = unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(pages, count)) };
+    PagesBuf { pages: Pages::Many(pages) }
+}
+
+impl PagesBuf {
+    /// The pages as a range of memory to lend.
+    pub fn range(&mut self) -> xous::MemoryRange {
+        unsafe { xous::MemoryRange::new(self.as_mut_ptr() as usize, self.len()).unwrap() }
+    }
+}
+
+impl Deref for PagesBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.pages {
+            Pages::One(page) => &page[..],
+            Pages::Many(pages) => unsafe {
+                slice::from_raw_parts(pages.as_ptr() as *const u8, pages.len() * PAGE_SIZE)
+            },
+        }
+    }
+}
+
+impl DerefMut for PagesBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.pages {
+            Pages::One(page) => &mut page[..],
+            Pages::Many(pages) => unsafe {
+                slice::from_raw_parts_mut(pages.as_mut_ptr() as *mut u8, pages.len() * PAGE_SIZE)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for PagesBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PagesBuf").field("len", &self.len()).finish_non_exhaustive()
+    }
+}
+
+/// How much the pool has been used.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct PagePoolStats {
+    /// Pages allocated because none was free.
+    pub allocated: usize,
+    /// Pages handed out again after being given back.
+    pub reused: usize,
+    /// Pages waiting to be reused.
+    pub free: usize,
+}
+
+/// Returns how much the pool has been used, for debugging.
+pub fn stats() -> PagePoolStats {
+    PagePoolStats {
+        allocated: ALLOCATED.load(Ordering::Relaxed),
+        reused: REUSED.load(Ordering::Relaxed),
+        free: with_free(|free| free.len()),
+    }
+}
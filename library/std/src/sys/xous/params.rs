@@ -0,0 +1,219 @@
+//! Parameters handed to a process by whoever started it.
+//!
+//! The loader passes the address of a parameter block as the second argument
+//! to `_start`, or zero if there is none. All integers are little-endian and
+//! nothing is aligned:
+//!
+//! ```text
+//! b"AppP"  length: u32  blocks...
+//! ```
+//!
+//! where `length` counts the bytes of the blocks that follow, and each block
+//! is
+//!
+//! ```text
+//! tag: [u8; 4]  length: u32  data: [u8; length]
+//! ```
+//!
+//! Blocks with unknown tags are skipped. The known ones are:
+//!
+//! * `AppN`, the name the process was started as, as raw bytes.
+//! * `ArgL`, the arguments, including the program name: `count: u16`, then
+//!   `count` length-prefixed strings. Arguments are arbitrary bytes, passed
+//!   on exactly as given: they aren't split on whitespace, needn't be UTF-8,
+//!   and may contain NULs, as nothing here treats them as C strings. The
+//!   whole block is at most `ARGS_LIMIT` bytes.
+//! * `EnvB`, the environment: `count: u16`, then `count` pairs of
+//!   length-prefixed strings, each a `u16` length followed by that many
+//!   bytes, giving a variable's name and then its value.
+//! * `CwdD`, the working directory, as raw bytes.
+//! * `StdH`, where stdin, stdout and stderr go: three `u32`s, each
+//!   `STDIO_INHERIT` for the console and log server, `STDIO_NULL` for
+//!   nothing, or the handle of a pipe end (see `pipe`).
+//!
+//! The same encoding is used when building the block for a child process,
+//! so the functions here go both ways.
+
+use crate::ffi::{OsStr, OsString};
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sys::os_str::Buf;
+use crate::sys_common::{AsInner, FromInner};
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8; 4] = b"AppP";
+pub const NAME_TAG: &[u8; 4] = b"AppN";
+pub const ARGS_TAG: &[u8; 4] = b"ArgL";
+pub const ENV_TAG: &[u8; 4] = b"EnvB";
+pub const STDIO_TAG: &[u8; 4] = b"StdH";
+pub const CWD_TAG: &[u8; 4] = b"CwdD";
+
+/// The most bytes an `ArgL` block may hold, leaving room in a spawn request
+/// for the environment and everything else.
+pub const ARGS_LIMIT: usize = 32 * 1024;
+
+pub const STDIO_INHERIT: usize = 0;
+pub const STDIO_NULL: usize = u32::MAX as usize;
+
+static PARAMS_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// Records where the loader put the parameter block. Called once from
+/// `_start`, before anything can look at the parameters.
+pub fn set_address(address: usize) {
+    PARAMS_ADDRESS.store(address, Ordering::Relaxed);
+}
+
+/// Returns the data of the block tagged `tag`, if the loader provided one.
+pub fn block(tag: &[u8; 4]) -> Option<&'static [u8]> {
+    let address = PARAMS_ADDRESS.load(Ordering::Relaxed);
+    if address == 0 {
+        return None;
+    }
+
+    // SAFETY: the loader maps the parameter block for the life of the
+    // process, and its header says how long it is.
+    let header = unsafe { crate::slice::from_raw_parts(address as *const u8, 8) };
+    if &header[..4] != MAGIC {
+        return None;
+    }
+    let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    find(unsafe { crate::slice::from_raw_parts(address as *const u8, 8 + length) }, tag)
+}
+
+/// Returns the data of the block tagged `tag` in the complete parameter
+/// block `params`, header included.
+pub fn find<'a>(params: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut reader = Reader(params);
+    if reader.bytes(4)? != MAGIC {
+        return None;
+    }
+    let length = reader.u32()? as usize;
+    let mut blocks = Reader(reader.bytes(length)?);
+    while let Some(block_tag) = blocks.bytes(4) {
+        let block_length = blocks.u32()? as usize;
+        let data = blocks.bytes(block_length)?;
+        if block_tag == tag {
+            return Some(data);
+        }
+    }
+    None
+}
+
+/// Walks through a block, failing on anything that would run off its end.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> Option<OsString> {
+        let length = self.u16()? as usize;
+        Some(OsString::from_inner(Buf { inner: self.bytes(length)?.to_vec() }))
+    }
+}
+
+/// Appends `s` as a length-prefixed string. Strings longer than a `u16` can
+/// describe are rejected.
+pub fn push_string(out: &mut Vec<u8>, s: &OsStr) -> Option<()> {
+    let bytes = &s.as_inner().inner;
+    out.extend_from_slice(&u16::try_from(bytes.len()).ok()?.to_le_bytes());
+    out.extend_from_slice(bytes);
+    Some(())
+}
+
+/// Appends a block with the given tag and contents.
+pub fn push_block(out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) -> Option<()> {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&u32::try_from(data.len()).ok()?.to_le_bytes());
+    out.extend_from_slice(data);
+    Some(())
+}
+
+/// Wraps blocks built with `push_block` into a complete parameter block.
+pub fn finish(blocks: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(8 + blocks.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&u32::try_from(blocks.len()).ok()?.to_le_bytes());
+    out.extend_from_slice(blocks);
+    Some(out)
+}
+
+/// Decodes the data of an `EnvB` block. Returns `None` if it is malformed.
+pub fn decode_env(data: &[u8]) -> Option<Vec<(OsString, OsString)>> {
+    let mut reader = Reader(data);
+    let count = reader.u16()?;
+    let mut vars = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = reader.string()?;
+        let value = reader.string()?;
+        vars.push((key, value));
+    }
+    Some(vars)
+}
+
+/// Encodes an `EnvB` block for a child process. Returns `None` if there are
+/// too many variables, or one is too long, to be represented.
+pub fn encode_env<'a>(
+    vars: impl ExactSizeIterator<Item = (&'a OsStr, &'a OsStr)>,
+) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&u16::try_from(vars.len()).ok()?.to_le_bytes());
+    for (key, value) in vars {
+        push_string(&mut data, key)?;
+        push_string(&mut data, value)?;
+    }
+    Some(data)
+}
+
+/// Decodes the data of an `ArgL` block. Returns `None` if it is malformed.
+pub fn decode_args(data: &[u8]) -> Option<Vec<OsString>> {
+    let mut reader = Reader(data);
+    let count = reader.u16()?;
+    (0..count).map(|_| reader.string()).collect()
+}
+
+/// Encodes an `ArgL` block for a child process. Returns `None` if there are
+/// too many arguments, or one is too long, to be represented, or if the
+/// block would be larger than `ARGS_LIMIT`.
+pub fn encode_args<'a>(args: impl ExactSizeIterator<Item = &'a OsStr>) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&u16::try_from(args.len()).ok()?.to_le_bytes());
+    for arg in args {
+        push_string(&mut data, arg)?;
+        if data.len() > ARGS_LIMIT {
+            return None;
+        }
+    }
+    Some(data)
+}
+
+/// Decodes the data of a `StdH` block into the handles for stdin, stdout and
+/// stderr. Returns `None` if it is malformed.
+pub fn decode_stdio(data: &[u8]) -> Option<[usize; 3]> {
+    let mut reader = Reader(data);
+    Some([reader.u32()? as usize, reader.u32()? as usize, reader.u32()? as usize])
+}
+
+/// Encodes a `StdH` block for a child process.
+pub fn encode_stdio(handles: [usize; 3]) -> Vec<u8> {
+    handles.iter().flat_map(|&handle| (handle as u32).to_le_bytes()).collect()
+}
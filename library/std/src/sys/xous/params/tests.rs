@@ -0,0 +1,93 @@
+use super::*;
+
+fn os(bytes: &[u8]) -> OsString {
+    OsString::from_inner(Buf { inner: bytes.to_vec() })
+}
+
+fn args_round_trip(args: &[OsString]) {
+    let encoded = encode_args(args.iter().map(|arg| arg.as_os_str())).unwrap();
+    assert_eq!(decode_args(&encoded).as_deref(), Some(args));
+}
+
+#[test]
+fn args() {
+    args_round_trip(&[]);
+    args_round_trip(&[os(b"shellchat")]);
+    args_round_trip(&[os(b"prog"), os(b""), os(b"")]);
+    args_round_trip(&[os(b"prog"), os(b"two words"), os(b"\"quoted\" 'too'"), os(b"a\nb\tc")]);
+    args_round_trip(&[os(b"prog"), os(b"\xff\xfe not UTF-8"), os(b"nul\0inside"), os(b"\0")]);
+}
+
+#[test]
+fn args_layout() {
+    let encoded = encode_args([os(b"ab"), os(b"")].iter().map(|arg| arg.as_os_str())).unwrap();
+    assert_eq!(encoded, b"\x02\x00\x02\x00ab\x00\x00");
+}
+
+#[test]
+fn args_limits() {
+    // The longest string a `u16` length can describe is already over
+    // `ARGS_LIMIT`, and anything longer can't be described at all.
+    let longest = os(&[b'x'; u16::MAX as usize]);
+    assert!(encode_args([longest.as_os_str()].into_iter()).is_none());
+    let too_long = os(&[b'x'; u16::MAX as usize + 1]);
+    assert!(encode_args([too_long.as_os_str()].into_iter()).is_none());
+
+    // Exactly `ARGS_LIMIT` bytes once encoded: the count, then one string.
+    let fits = os(&vec![b'x'; ARGS_LIMIT - 4]);
+    let encoded = encode_args([fits.as_os_str()].into_iter()).unwrap();
+    assert_eq!(encoded.len(), ARGS_LIMIT);
+    assert_eq!(decode_args(&encoded), Some(vec![fits]));
+    let over = os(&vec![b'x'; ARGS_LIMIT - 3]);
+    assert!(encode_args([over.as_os_str()].into_iter()).is_none());
+}
+
+#[test]
+fn malformed_args() {
+    assert_eq!(decode_args(b""), None);
+    assert_eq!(decode_args(b"\x01"), None);
+    // One string promised, none there.
+    assert_eq!(decode_args(b"\x01\x00"), None);
+    // A string that runs off the end.
+    assert_eq!(decode_args(b"\x01\x00\x05\x00abc"), None);
+    assert_eq!(decode_args(b"\x00\x00"), Some(vec![]));
+}
+
+#[test]
+fn env() {
+    let vars =
+        [(os(b"RUST_LOG"), os(b"debug")), (os(b"EMPTY"), os(b"")), (os(b"\xff"), os(b"a=b"))];
+    let encoded = encode_env(vars.iter().map(|(k, v)| (k.as_os_str(), v.as_os_str()))).unwrap();
+    assert_eq!(decode_env(&encoded).as_deref(), Some(&vars[..]));
+    assert_eq!(decode_env(&encoded[..encoded.len() - 1]), None);
+}
+
+#[test]
+fn stdio() {
+    let handles = [STDIO_INHERIT, STDIO_NULL, 0x1234];
+    assert_eq!(decode_stdio(&encode_stdio(handles)), Some(handles));
+    assert_eq!(decode_stdio(&encode_stdio(handles)[..11]), None);
+}
+
+#[test]
+fn blocks() {
+    let mut blocks = Vec::new();
+    push_block(&mut blocks, NAME_TAG, b"shellchat").unwrap();
+    push_block(&mut blocks, b"Xtra", b"").unwrap();
+    push_block(&mut blocks, CWD_TAG, b"/sys.rtc").unwrap();
+    let params = finish(&blocks).unwrap();
+
+    assert_eq!(find(&params, NAME_TAG), Some(&b"shellchat"[..]));
+    assert_eq!(find(&params, b"Xtra"), Some(&b""[..]));
+    assert_eq!(find(&params, CWD_TAG), Some(&b"/sys.rtc"[..]));
+    assert_eq!(find(&params, ENV_TAG), None);
+
+    // A block that runs past the end of the parameter block isn't trusted,
+    // nor is anything without the magic number.
+    let mut cut = params.clone();
+    cut[4] -= 1;
+    assert_eq!(find(&cut, CWD_TAG), None);
+    assert_eq!(find(&params[..params.len() - 1], NAME_TAG), None);
+    assert_eq!(find(&params[1..], NAME_TAG), None);
+    assert_eq!(find(b"AppP\x00\x00\x00\x00", NAME_TAG), None);
+}
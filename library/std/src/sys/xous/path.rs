@@ -2,7 +2,10 @@
 use crate::ffi::OsStr;
 use crate::io;
 use crate::mem;
-use crate::path::{Path, PathBuf, Prefix};
+use crate::path::{Component, Path, PathBuf, Prefix};
+
+#[cfg(test)]
+mod tests;
 
 /// # Safety
 ///
@@ -14,6 +17,11 @@ unsafe fn bytes_as_os_str(bytes: &[u8]) -> &OsStr {
     unsafe { mem::transmute(bytes) }
 }
 
+/// Only `/` separates components. The PDDB is sometimes written about as
+/// `config:wifi:password`, but in a path that is a single name, and the dict
+/// `config:wifi` and its key `password` are `config:wifi/password`. Keeping
+/// `:` out of the separators leaves it free for basis prefixes, and keeps
+/// `Path`'s accessors working as they do on Unix.
 #[inline]
 pub fn is_sep_byte(b: u8) -> bool {
     b == b'/'
@@ -24,13 +32,34 @@ pub fn is_verbatim_sep(b: u8) -> bool {
     b == b'/'
 }
 
-pub fn parse_prefix(prefix: &OsStr) -> Option<Prefix<'_>> {
-    let b = prefix.bytes();
-    let mut components = b.splitn(2, |x| *x == b':');
-    let p = components.next();
-    let remainder = components.next();
-    if remainder.is_some() {
-        Some(Prefix::DeviceNS(unsafe { bytes_as_os_str(p.unwrap()) }))
+/// The most bytes a basis name can have.
+const MAX_BASIS_NAME: usize = 64;
+
+/// Parses the name of a PDDB basis at the start of a path. A prefix is a
+/// name of letters, digits, `_`, `-` and `.`, then a colon that ends the
+/// path or is followed by a separator, so `secrets:` and `secrets:/notes`
+/// have one, but `12:30-notes.txt`, `:/notes` and `a:b:/notes` don't.
+///
+/// Like everything else in a path, basis names are case-sensitive, as the
+/// PDDB's own names are: `Secrets:` and `secrets:` are different bases. So
+/// two paths that `absolute` turns into equal paths name the same key or
+/// dict, and paths that name different ones never compare equal.
+///
+/// `Path` compares and hashes Xous paths as it does Unix ones, so `./a` and
+/// `a`, or `a/../b` and `b`, are different `Path`s even though they name the
+/// same key. Only `absolute` resolves `.` and `..`, and the fs layer goes
+/// through it for every path, so every spelling reaches the same location.
+pub fn parse_prefix(path: &OsStr) -> Option<Prefix<'_>> {
+    let b = path.bytes();
+    let colon = b.iter().position(|&c| c == b':')?;
+    let name = &b[..colon];
+    let valid_name = !name.is_empty()
+        && name.len() <= MAX_BASIS_NAME
+        && name.iter().all(|&c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'.'));
+    let ends_prefix = b.get(colon + 1).map_or(true, |&c| is_sep_byte(c));
+    if valid_name && ends_prefix {
+        // SAFETY: the name is ASCII, and ends just before an ASCII colon.
+        Some(Prefix::DeviceNS(unsafe { bytes_as_os_str(name) }))
     } else {
         None
     }
@@ -39,39 +68,43 @@ pub fn parse_prefix(prefix: &OsStr) -> Option<Prefix<'_>> {
 pub const MAIN_SEP_STR: &str = "/";
 pub const MAIN_SEP: char = '/';
 
-/// Make a POSIX path absolute without changing its semantics.
+/// Make a path absolute without changing its semantics, resolving `.` and
+/// `..` on the way.
+///
+/// Every working directory is in some basis, or in the default view, so a
+/// path with a basis prefix is already rooted and doesn't involve the
+/// working directory. A bare prefix, as in `secrets:`, names the basis's
+/// root; there is no way to write a path relative to a basis, since
+/// `secrets:notes` has no prefix at all. A path with a root but no prefix
+/// stays in whatever basis the working directory is in, much as `\foo`
+/// stays on the current drive on Windows.
 pub(crate) fn absolute(path: &Path) -> io::Result<PathBuf> {
-    // This is mostly a wrapper around collecting `Path::components`, with
-    // exceptions made where this conflicts with the POSIX specification.
-    // See 4.13 Pathname Resolution, IEEE Std 1003.1-2017
-    // https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/V1_chap04.html#tag_04_13
-
+    // This is mostly a wrapper around collecting `Path::components`.
     let mut components = path.components();
     let path_os = path.as_os_str().bytes();
 
-    let mut normalized = if path.is_absolute() {
-        // "If a pathname begins with two successive <slash> characters, the
-        // first component following the leading <slash> characters may be
-        // interpreted in an implementation-defined manner, although more than
-        // two leading <slash> characters shall be treated as a single <slash>
-        // character."
-        if path_os.starts_with(b"//") && !path_os.starts_with(b"///") {
+    let mut normalized = match components.clone().next() {
+        Some(Component::Prefix(prefix)) => {
             components.next();
-            PathBuf::from("//")
-        } else {
-            PathBuf::new()
+            let mut normalized = PathBuf::from(prefix.as_os_str());
+            normalized.push(MAIN_SEP_STR);
+            normalized
         }
-    } else {
-        env::current_dir()?
+        _ => env::current_dir()?,
     };
-    normalized.extend(components);
+    for component in components {
+        match component {
+            // There are no symlinks, so `..` can always be resolved by
+            // dropping the name before it. At the root, there is none to drop.
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            _ => normalized.push(component),
+        }
+    }
 
-    // "Interfaces using pathname resolution may specify additional constraints
-    // when a pathname that does not name an existing directory contains at
-    // least one non- <slash> character and contains one or more trailing
-    // <slash> characters".
-    // A trailing <slash> is also meaningful if "a symbolic link is
-    // encountered during pathname resolution".
+    // A trailing slash says the path must name a dict, so keep it.
     if path_os.ends_with(b"/") {
         normalized.push("");
     }
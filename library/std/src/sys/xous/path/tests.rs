@@ -0,0 +1,124 @@
+use super::*;
+
+fn resolve(path: &str) -> PathBuf {
+    absolute(Path::new(path)).unwrap()
+}
+
+#[test]
+fn same_location() {
+    for (a, b) in [
+        ("a", "./a"),
+        ("a", "a/."),
+        ("a/b", "a//b"),
+        ("a/b", "a/./b"),
+        ("b", "a/../b"),
+        ("a/b", "./a/c/../b/."),
+        ("secrets:/a", "secrets:/./a"),
+        ("secrets:/a", "secrets://a"),
+        ("secrets:/a", "secrets:/../a"),
+        ("secrets:", "secrets:/"),
+    ] {
+        assert_eq!(resolve(a), resolve(b), "{a:?} and {b:?}");
+    }
+}
+
+#[test]
+fn different_locations() {
+    for (a, b) in [
+        ("a", "A"),
+        ("a/b", "b/a"),
+        ("secrets:/a", "Secrets:/a"),
+        ("secrets:/a", "/a"),
+        // Not a basis prefix, so a single name.
+        ("a:b", "a/b"),
+        ("12:30", "12/30"),
+    ] {
+        assert_ne!(resolve(a), resolve(b), "{a:?} and {b:?}");
+    }
+}
+
+#[test]
+fn trailing_slash_is_kept() {
+    assert!(resolve("a/").as_os_str().bytes().ends_with(b"/"));
+    assert!(resolve("a/./").as_os_str().bytes().ends_with(b"/"));
+    assert!(!resolve("a").as_os_str().bytes().ends_with(b"/"));
+}
+
+/// Where a path leads, worked out the long way: the basis, if any, and the
+/// names below its root.
+fn location(path: &str) -> (Option<String>, Vec<String>) {
+    let (basis, rest) = match path.split_once(":/") {
+        Some((basis, rest)) => (Some(basis.to_owned()), rest),
+        None => (None, path),
+    };
+    let cwd = crate::env::current_dir().unwrap();
+    let (cwd_basis, cwd_names) = location_of_cwd(&cwd);
+    let mut names = if basis.is_some() || rest.starts_with('/') { Vec::new() } else { cwd_names };
+    for name in rest.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name.to_owned()),
+        }
+    }
+    (basis.or(cwd_basis), names)
+}
+
+fn location_of_cwd(cwd: &Path) -> (Option<String>, Vec<String>) {
+    let mut basis = None;
+    let mut names = Vec::new();
+    for component in cwd.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                let prefix = prefix.as_os_str().to_str().unwrap();
+                basis = Some(prefix.trim_end_matches(':').to_owned());
+            }
+            Component::Normal(name) => names.push(name.to_str().unwrap().to_owned()),
+            _ => {}
+        }
+    }
+    (basis, names)
+}
+
+/// A small, fixed pseudo-random sequence, so that a failure can be repeated.
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[test]
+fn equal_exactly_when_the_location_is() {
+    const PREFIXES: [&str; 4] = ["", "", "/", "secrets:/"];
+    const NAMES: [&str; 6] = ["a", "b", "A", ".", "..", ""];
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let paths: Vec<String> = (0..150)
+        .map(|_| {
+            let mut path = PREFIXES[rng.below(PREFIXES.len())].to_owned();
+            let names: Vec<_> =
+                (0..1 + rng.below(4)).map(|_| NAMES[rng.below(NAMES.len())]).collect();
+            path.push_str(&names.join("/"));
+            path
+        })
+        .filter(|path| !path.is_empty())
+        .collect();
+
+    for a in &paths {
+        for b in &paths {
+            assert_eq!(
+                resolve(a) == resolve(b),
+                location(a) == location(b),
+                "{a:?} resolves to {:?}, {b:?} to {:?}",
+                resolve(a),
+                resolve(b)
+            );
+        }
+    }
+}
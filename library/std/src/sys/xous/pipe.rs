@@ -0,0 +1,200 @@
+//! Anonymous pipes, kept by the loader so that it can hand their ends to
+//! the processes it starts.
+//!
+//! `PipeCreateStd` is a blocking scalar, answered with a result code and
+//! the handles of the new pipe's read and write ends. Handles are never 0 or
+//! `u32::MAX`, which the parameter block uses for inherited and discarded
+//! stdio. Requests on an end carry its handle in the upper bits of the
+//! opcode. Reads and writes lend a page, with the number of bytes to move as
+//! its size, and the loader returns it with the number it moved as the valid
+//! length and a result code as the offset, which is absent on success.
+//!
+//! The loader keeps at most a page of data in each pipe. A write waits
+//! until there is room for at least one byte, and a read until there is at
+//! least one byte to take. Several processes can hold the same end, for
+//! example a parent and the child it was passed to, and a read returns zero
+//! once every process holding the write end has closed it or exited.
+//!
+//! A read or write lent with an offset of 1 doesn't wait: if it would have
+//! to, the loader returns it at once with code 8, which is `WouldBlock`.
+//! Loaders that predate this ignore the offset, and always wait.
+
+use super::error::{loader_error, xous_error};
+use super::services;
+use crate::io::{self, IoSlice, IoSliceMut};
+use crate::sync::atomic::{AtomicBool, Ordering};
+
+const PAGE_SIZE: usize = 4096;
+
+#[repr(C, align(4096))]
+struct Page {
+    raw: [u8; PAGE_SIZE],
+}
+
+impl Page {
+    fn range(&mut self) -> xous::MemoryRange {
+        unsafe { xous::MemoryRange::new(self as *mut Page as usize, PAGE_SIZE).unwrap() }
+    }
+}
+
+/// One end of a pipe. Dropping it gives up this process's hold on it.
+#[derive(Debug)]
+pub struct AnonPipe {
+    handle: usize,
+    /// Whether reads and writes through this `AnonPipe` fail with
+    /// `WouldBlock` instead of waiting. Other holders of the same end, such
+    /// as a child it was passed to, decide for themselves.
+    nonblocking: AtomicBool,
+}
+
+/// Makes a pipe, returning its read end and then its write end.
+pub fn anon_pipe() -> io::Result<(AnonPipe, AnonPipe)> {
+    match xous::send_message(
+        services::loader(),
+        xous::Message::new_blocking_scalar(4 /* PipeCreateStd */, 0, 0, 0, 0),
+    )
+    .map_err(xous_error)?
+    {
+        xous::Result::Scalar5(0, read, write, _, _) => Ok((
+            AnonPipe { handle: read, nonblocking: AtomicBool::new(false) },
+            AnonPipe { handle: write, nonblocking: AtomicBool::new(false) },
+        )),
+        xous::Result::Scalar5(code, ..) => Err(loader_error(code)),
+        _ => Err(unexpected_reply()),
+    }
+}
+
+fn unexpected_reply() -> io::Error {
+    io::const_io_error!(io::ErrorKind::InvalidData, &"unexpected reply from the loader")
+}
+
+/// Sends a read or write request for `length` bytes, and returns how many
+/// the loader moved.
+fn transfer(message: xous::Message, length: usize) -> io::Result<usize> {
+    match xous::send_message(services::loader(), message).map_err(xous_error)? {
+        xous::Result::MemoryReturned(None, valid) => Ok(valid.map_or(0, |v| v.get()).min(length)),
+        xous::Result::MemoryReturned(Some(code), _) => Err(loader_error(code.get())),
+        _ => Err(unexpected_reply()),
+    }
+}
+
+/// The offset that asks the loader not to wait, if `nonblocking` is set.
+fn wait_flag(nonblocking: bool) -> Option<xous::MemoryAddress> {
+    xous::MemoryAddress::new(nonblocking as usize)
+}
+
+/// Reads from the pipe end `handle`, returning zero once the write end has
+/// been closed by everyone holding it.
+pub fn read(handle: usize, buf: &mut [u8]) -> io::Result<usize> {
+    read_from(handle, buf, false)
+}
+
+fn read_from(handle: usize, buf: &mut [u8], nonblocking: bool) -> io::Result<usize> {
+    let length = buf.len().min(PAGE_SIZE);
+    if length == 0 {
+        return Ok(0);
+    }
+    let mut page = Page { raw: [0u8; PAGE_SIZE] };
+    let message = xous::Message::new_lend_mut(
+        5 | (handle << 16), /* PipeReadStd */
+        page.range(),
+        wait_flag(nonblocking),
+        xous::MemorySize::new(length),
+    );
+    let count = transfer(message, length)?;
+    buf[..count].copy_from_slice(&page.raw[..count]);
+    Ok(count)
+}
+
+/// Writes to the pipe end `handle`. Writing to a pipe whose read end nobody
+/// holds fails with `BrokenPipe`.
+pub fn write(handle: usize, buf: &[u8]) -> io::Result<usize> {
+    write_to(handle, buf, false)
+}
+
+fn write_to(handle: usize, buf: &[u8], nonblocking: bool) -> io::Result<usize> {
+    let length = buf.len().min(PAGE_SIZE);
+    if length == 0 {
+        return Ok(0);
+    }
+    let mut page = Page { raw: [0u8; PAGE_SIZE] };
+    page.raw[..length].copy_from_slice(&buf[..length]);
+    let message = xous::Message::new_lend(
+        6 | (handle << 16), /* PipeWriteStd */
+        page.range(),
+        wait_flag(nonblocking),
+        xous::MemorySize::new(length),
+    );
+    transfer(message, length)
+}
+
+impl AnonPipe {
+    /// The handle the loader knows this end by.
+    pub fn handle(&self) -> usize {
+        self.handle
+    }
+
+    /// Makes reads and writes through this end fail with `WouldBlock`,
+    /// rather than wait for data or for room.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        read_from(self.handle, buf, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        io::default_read_vectored(|buf| self.read(buf), bufs)
+    }
+
+    pub fn is_read_vectored(&self) -> bool {
+        false
+    }
+
+    pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        write_to(self.handle, buf, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        io::default_write_vectored(|buf| self.write(buf), bufs)
+    }
+
+    pub fn is_write_vectored(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for AnonPipe {
+    fn drop(&mut self) {
+        xous::send_message(
+            services::loader(),
+            xous::Message::new_scalar(7 | (self.handle << 16), 0, 0, 0, 0), /* PipeCloseStd */
+        )
+        .ok();
+    }
+}
+
+/// Reads both pipes to the end at the same time, so that a child can't
+/// stall by filling one of them while the other is being read. The second
+/// pipe is read on a thread of its own, and each vector grows only as much
+/// as there is data to put in it.
+pub fn read2(p1: AnonPipe, v1: &mut Vec<u8>, p2: AnonPipe, v2: &mut Vec<u8>) -> io::Result<()> {
+    crate::thread::scope(|scope| {
+        let second = crate::thread::Builder::new().spawn_scoped(scope, || read_to_end(&p2, v2))?;
+        let first = read_to_end(&p1, v1);
+        let second = second.join().unwrap_or_else(|e| crate::panic::resume_unwind(e));
+        first.and(second)
+    })
+}
+
+fn read_to_end(pipe: &AnonPipe, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut chunk = [0u8; PAGE_SIZE];
+    loop {
+        match pipe.read(&mut chunk)? {
+            0 => return Ok(()),
+            count => buf.extend_from_slice(&chunk[..count]),
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! Starting other processes through the loader.
+//!
+//! Programs aren't files, so the loader finds them by name, the same bare
+//! name that `current_exe` returns in the child. A spawn request lends as
+//! many pages as it needs, holding the program's name as a `u16` length
+//! followed by that many bytes, then `length: u32` and the parameter block
+//! the child starts with (see `params`). The loader overwrites the start of
+//! the first page with a `u32` result code, which is zero on success, and
+//! the child's process ID as a `u32`. All integers are little-endian.
+//!
+//! The loader remembers each child's exit code until its parent collects it
+//! with a `WaitStd` blocking scalar, which takes the child's ID and a flag
+//! that asks for an answer right away instead of when the child exits. The
+//! reply is a result code, the exit code, and how the child ended: 0 if it
+//! exited, 1 if it was killed with `KillStd`, 2 if the kernel ended it over
+//! a fault, or 3 if it ran out of memory. A parent that won't collect
+//! the exit code sends `ReleaseStd` instead, so the loader can forget the
+//! child as soon as it exits. Until one or the other, the loader keeps the
+//! child's process ID from being given to another process, so a parent can
+//! never reach an unrelated process through a stale ID.
+//!
+//! The program's name and its arguments are passed as raw bytes, so the
+//! child gets them exactly as given, whether or not they are UTF-8 and even
+//! if they contain spaces, quotes or NULs. The arguments, program name
+//! included, may take up at most 32 KiB once encoded; spawning with more
+//! fails with `InvalidInput`.
+//!
+//! The loader also looks for a `StdH` block in the parameter block, and
+//! gives the child its own hold on every pipe end named there, so the parent
+//! can close its hold once the child has started.
+
+use super::error::loader_error;
+use super::params;
+use super::services;
+use crate::ffi::{OsStr, OsString};
+use crate::fmt;
+use crate::io;
+use crate::num::NonZeroI32;
+use crate::path::Path;
+use crate::sys::fs::File;
+use crate::sys::pipe::{anon_pipe, AnonPipe};
+use crate::sys_common::process::{CommandEnv, CommandEnvs};
+use crate::sys_common::AsInner;
+
+pub use crate::ffi::OsString as EnvKey;
+
+#[cfg(test)]
+mod tests;
+
+const PAGE_SIZE: usize = 4096;
+/// The most pages lent with a spawn request, which limits how much can be
+/// passed to the child.
+const SPAWN_PAGES: usize = 16;
+
+#[repr(C, align(4096))]
+struct Page {
+    raw: [u8; PAGE_SIZE],
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Command
+////////////////////////////////////////////////////////////////////////////////
+
+pub struct Command {
+    program: OsString,
+    /// The arguments, starting with the program's name.
+    args: Vec<OsString>,
+    env: CommandEnv,
+    cwd: Option<OsString>,
+    stdin: Option<Stdio>,
+    stdout: Option<Stdio>,
+    stderr: Option<Stdio>,
+}
+
+// passed back to std::process with the pipes connected to the child, if any
+// were requested
+pub struct StdioPipes {
+    pub stdin: Option<AnonPipe>,
+    pub stdout: Option<AnonPipe>,
+    pub stderr: Option<AnonPipe>,
+}
+
+pub enum Stdio {
+    Inherit,
+    Null,
+    MakePipe,
+    Pipe(AnonPipe),
+    /// A PDDB key, which can't be handed to a child, so spawning with one
+    /// fails.
+    File(File),
+}
+
+impl Stdio {
+    /// Works out what the child gets for this stream, as it goes in the
+    /// parameter block. A pipe made for the stream is returned too, as the
+    /// parent's end and then the child's end, which the parent has to hold
+    /// on to until the child has its own hold on it.
+    fn to_child(&self, child_reads: bool) -> io::Result<(usize, Option<(AnonPipe, AnonPipe)>)> {
+        match self {
+            Stdio::Inherit => Ok((params::STDIO_INHERIT, None)),
+            Stdio::Null => Ok((params::STDIO_NULL, None)),
+            Stdio::Pipe(pipe) => Ok((pipe.handle(), None)),
+            Stdio::File(_) => Err(io::const_io_error!(
+                io::ErrorKind::Unsupported,
+                &"a file can't be used as a child process's stdin, stdout or stderr",
+            )),
+            Stdio::MakePipe => {
+                let (read, write) = anon_pipe()?;
+                let (ours, theirs) = if child_reads { (write, read) } else { (read, write) };
+                Ok((theirs.handle(), Some((ours, theirs))))
+            }
+        }
+    }
+}
+
+impl Command {
+    pub fn new(program: &OsStr) -> Command {
+        Command {
+            program: program.to_owned(),
+            args: vec![program.to_owned()],
+            env: Default::default(),
+            cwd: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    pub fn arg(&mut self, arg: &OsStr) {
+        self.args.push(arg.to_owned());
+    }
+
+    pub fn env_mut(&mut self) -> &mut CommandEnv {
+        &mut self.env
+    }
+
+    pub fn cwd(&mut self, dir: &OsStr) {
+        self.cwd = Some(dir.to_owned());
+    }
+
+    pub fn stdin(&mut self, stdin: Stdio) {
+        self.stdin = Some(stdin);
+    }
+
+    pub fn stdout(&mut self, stdout: Stdio) {
+        self.stdout = Some(stdout);
+    }
+
+    pub fn stderr(&mut self, stderr: Stdio) {
+        self.stderr = Some(stderr);
+    }
+
+    pub fn get_program(&self) -> &OsStr {
+        &self.program
+    }
+
+    pub fn get_args(&self) -> CommandArgs<'_> {
+        CommandArgs { iter: self.args[1..].iter() }
+    }
+
+    pub fn get_envs(&self) -> CommandEnvs<'_> {
+        self.env.iter()
+    }
+
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.cwd.as_ref().map(Path::new)
+    }
+
+    /// Asks the loader to start the program.
+    pub fn spawn(
+        &mut self,
+        default: Stdio,
+        needs_stdin: bool,
+    ) -> io::Result<(Process, StdioPipes)> {
+        let null = Stdio::Null;
+        let default_stdin = if needs_stdin { &default } else { &null };
+        let (stdin, stdin_pipe) = self.stdin.as_ref().unwrap_or(default_stdin).to_child(true)?;
+        let (stdout, stdout_pipe) = self.stdout.as_ref().unwrap_or(&default).to_child(false)?;
+        let (stderr, stderr_pipe) = self.stderr.as_ref().unwrap_or(&default).to_child(false)?;
+
+        let pid = spawn(&self.program, &self.params([stdin, stdout, stderr])?)?;
+        // The child has its own hold on its ends now, so dropping ours here
+        // leaves it as the only holder.
+        let ours = |pipe: Option<(AnonPipe, AnonPipe)>| pipe.map(|(ours, _)| ours);
+        let pipes = StdioPipes {
+            stdin: ours(stdin_pipe),
+            stdout: ours(stdout_pipe),
+            stderr: ours(stderr_pipe),
+        };
+        Ok((Process { pid, status: None }, pipes))
+    }
+
+    /// Builds the parameter block the child starts with. The child gets this
+    /// process's environment with the command's changes applied, and starts
+    /// in the command's working directory, taken relative to this process's,
+    /// or else in this process's.
+    fn params(&self, stdio: [usize; 3]) -> io::Result<Vec<u8>> {
+        let too_large = || {
+            io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"environment or working directory is too large to pass to a child process"
+            )
+        };
+        let args = params::encode_args(self.args.iter().map(|arg| arg.as_os_str())).ok_or(
+            io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"arguments are too long to pass to a child process",
+            ),
+        )?;
+        let mut blocks = Vec::new();
+        params::push_block(&mut blocks, params::NAME_TAG, &self.program.as_inner().inner)
+            .ok_or_else(too_large)?;
+        params::push_block(&mut blocks, params::ARGS_TAG, &args).ok_or_else(too_large)?;
+        params::push_block(&mut blocks, params::STDIO_TAG, &params::encode_stdio(stdio))
+            .ok_or_else(too_large)?;
+        let env = self.env.capture();
+        let env = params::encode_env(env.iter().map(|(k, v)| (k.as_os_str(), v.as_os_str())))
+            .ok_or_else(too_large)?;
+        params::push_block(&mut blocks, params::ENV_TAG, &env).ok_or_else(too_large)?;
+        let cwd = match &self.cwd {
+            Some(dir) => super::path::absolute(Path::new(dir))?,
+            None => super::os::getcwd()?,
+        };
+        params::push_block(&mut blocks, params::CWD_TAG, cwd.as_os_str().bytes())
+            .ok_or_else(too_large)?;
+        params::finish(&blocks).ok_or_else(too_large)
+    }
+}
+
+/// Builds the spawn request for `program`, described at the top of this
+/// module.
+fn spawn_request(program: &OsStr, params: &[u8]) -> io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    params::push_string(&mut request, program)
+        .ok_or(io::const_io_error!(io::ErrorKind::InvalidInput, &"program name is too long"))?;
+    request.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    request.extend_from_slice(params);
+    if request.len() > SPAWN_PAGES * PAGE_SIZE {
+        return Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            &"arguments are too large to pass to a child process",
+        ));
+    }
+    Ok(request)
+}
+
+/// Sends a spawn request for `program`, returning the child's process ID.
+fn spawn(program: &OsStr, params: &[u8]) -> io::Result<u32> {
+    let request = spawn_request(program, params)?;
+    let count = (request.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let mut pages: Vec<Page> = (0..count).map(|_| Page { raw: [0u8; PAGE_SIZE] }).collect();
+    for (page, chunk) in pages.iter_mut().zip(request.chunks(PAGE_SIZE)) {
+        page.raw[..chunk.len()].copy_from_slice(chunk);
+    }
+    let range =
+        unsafe { xous::MemoryRange::new(pages.as_mut_ptr() as usize, count * PAGE_SIZE).unwrap() };
+    services::send(
+        services::loader(),
+        xous::Message::new_lend_mut(1 /* SpawnStd */, range, None, None),
+    )?;
+
+    let reply = &pages[0].raw;
+    match u32::from_le_bytes(reply[0..4].try_into().unwrap()) {
+        0 => Ok(u32::from_le_bytes(reply[4..8].try_into().unwrap())),
+        code => Err(loader_error(code as usize)),
+    }
+}
+
+impl From<AnonPipe> for Stdio {
+    fn from(pipe: AnonPipe) -> Stdio {
+        Stdio::Pipe(pipe)
+    }
+}
+
+impl From<File> for Stdio {
+    fn from(file: File) -> Stdio {
+        Stdio::File(file)
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.program != self.args[0] {
+            write!(f, "[{:?}] ", self.program)?;
+        }
+        write!(f, "{:?}", self.args[0])?;
+
+        for arg in &self.args[1..] {
+            write!(f, " {:?}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a child process ended.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ExitStatus {
+    /// It exited, or returned from `main`, with this code.
+    Exited(i32),
+    /// It was ended without exiting.
+    Terminated(TerminationReason),
+}
+
+/// Why a child process ended without exiting.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+#[non_exhaustive]
+#[unstable(feature = "xous_std", issue = "none")]
+pub enum TerminationReason {
+    /// It was killed with [`Child::kill`](crate::process::Child::kill).
+    Killed,
+    /// The kernel ended it because of a fault it didn't handle, such as an
+    /// illegal instruction or an access to unmapped memory.
+    Fault,
+    /// The kernel ended it because it ran out of memory.
+    OutOfMemory,
+}
+
+impl ExitStatus {
+    /// Decodes the exit code and the way the child ended from a reply to
+    /// `WaitStd`. Ways the loader might add later are taken as faults.
+    fn from_wait(code: usize, how: usize) -> ExitStatus {
+        match how {
+            0 => ExitStatus::Exited(code as u32 as i32),
+            1 => ExitStatus::Terminated(TerminationReason::Killed),
+            3 => ExitStatus::Terminated(TerminationReason::OutOfMemory),
+            _ => ExitStatus::Terminated(TerminationReason::Fault),
+        }
+    }
+
+    pub fn exit_ok(&self) -> Result<(), ExitStatusError> {
+        match self {
+            ExitStatus::Exited(0) => Ok(()),
+            _ => Err(ExitStatusError(*self)),
+        }
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ExitStatus::Exited(code) => Some(*code),
+            ExitStatus::Terminated(_) => None,
+        }
+    }
+
+    pub fn termination_reason(&self) -> Option<TerminationReason> {
+        match self {
+            ExitStatus::Exited(_) => None,
+            ExitStatus::Terminated(reason) => Some(*reason),
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exit code: {}", code),
+            ExitStatus::Terminated(TerminationReason::Killed) => f.write_str("killed"),
+            ExitStatus::Terminated(TerminationReason::Fault) => {
+                f.write_str("terminated after a fault")
+            }
+            ExitStatus::Terminated(TerminationReason::OutOfMemory) => {
+                f.write_str("terminated for running out of memory")
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ExitStatusError(ExitStatus);
+
+impl Into<ExitStatus> for ExitStatusError {
+    fn into(self) -> ExitStatus {
+        self.0
+    }
+}
+
+impl ExitStatusError {
+    pub fn code(self) -> Option<NonZeroI32> {
+        self.0.code().map(|code| NonZeroI32::try_from(code).unwrap())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ExitCode(u8);
+
+impl ExitCode {
+    pub const SUCCESS: ExitCode = ExitCode(0);
+    pub const FAILURE: ExitCode = ExitCode(1);
+
+    pub fn as_i32(&self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl From<u8> for ExitCode {
+    fn from(code: u8) -> Self {
+        Self(code)
+    }
+}
+
+/// A child process. Dropping it without waiting for the child leaves the
+/// child running, as on other platforms, and tells the loader not to keep
+/// its exit code around for a wait that won't come.
+pub struct Process {
+    pid: u32,
+    /// The exit status, once it has been collected from the loader.
+    status: Option<ExitStatus>,
+}
+
+impl Process {
+    pub fn id(&self) -> u32 {
+        self.pid
+    }
+
+    /// Has the loader end the child. A child that has already exited, but
+    /// hasn't been waited for, keeps its process ID until it is, so this
+    /// can't reach an unrelated process.
+    pub fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"invalid argument: can't kill an exited process",
+            ));
+        }
+        match services::send(
+            services::loader(),
+            xous::Message::new_blocking_scalar(8 /* KillStd */, self.pid as usize, 0, 0, 0),
+        )? {
+            xous::Result::Scalar1(0) => Ok(()),
+            xous::Result::Scalar1(code) => Err(loader_error(code)),
+            _ => Err(unexpected_reply()),
+        }
+    }
+
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self.status {
+            Some(status) => Ok(status),
+            None => self.collect(true).map(Option::unwrap),
+        }
+    }
+
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self.status {
+            Some(status) => Ok(Some(status)),
+            None => self.collect(false),
+        }
+    }
+
+    /// Asks the loader for the child's exit code, waiting for the child to
+    /// exit if `block` is set. The loader forgets the child once it has
+    /// handed out the exit code, so it is kept here for later calls.
+    fn collect(&mut self, block: bool) -> io::Result<Option<ExitStatus>> {
+        let reply = services::send(
+            services::loader(),
+            xous::Message::new_blocking_scalar(
+                2, /* WaitStd */
+                self.pid as usize,
+                if block { 0 } else { 1 },
+                0,
+                0,
+            ),
+        )?;
+        let status = match reply {
+            xous::Result::Scalar5(0, code, how, _, _) => ExitStatus::from_wait(code, how),
+            xous::Result::Scalar5(STILL_RUNNING, ..) if !block => return Ok(None),
+            xous::Result::Scalar5(code, ..) => return Err(loader_error(code)),
+            _ => return Err(unexpected_reply()),
+        };
+        self.status = Some(status);
+        Ok(Some(status))
+    }
+}
+
+fn unexpected_reply() -> io::Error {
+    io::const_io_error!(io::ErrorKind::InvalidData, &"unexpected reply from the loader")
+}
+
+/// The result code of a `WaitStd` that doesn't wait, for a child that is
+/// still running.
+const STILL_RUNNING: usize = 5;
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if self.status.is_none() {
+            services::send(
+                services::loader(),
+                xous::Message::new_scalar(3 /* ReleaseStd */, self.pid as usize, 0, 0, 0),
+            )
+            .ok();
+        }
+    }
+}
+
+pub struct CommandArgs<'a> {
+    iter: crate::slice::Iter<'a, OsString>,
+}
+
+impl<'a> Iterator for CommandArgs<'a> {
+    type Item = &'a OsStr;
+    fn next(&mut self) -> Option<&'a OsStr> {
+        self.iter.next().map(|arg| arg.as_os_str())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for CommandArgs<'a> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.iter.is_empty()
+    }
+}
+
+impl<'a> fmt::Debug for CommandArgs<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter.clone()).finish()
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+use crate::sys::os_str::Buf;
+use crate::sys_common::{FromInner, IntoInner};
+
+fn os(bytes: &[u8]) -> OsString {
+    OsString::from_inner(Buf { inner: bytes.to_vec() })
+}
+
+/// Takes a spawn request apart the way the loader does, returning the
+/// program's name and the parameter block.
+fn decode_request(request: &[u8]) -> (OsString, &[u8]) {
+    let mut reader = params::Reader(request);
+    let program = reader.string().unwrap();
+    let length = reader.u32().unwrap() as usize;
+    let params = reader.bytes(length).unwrap();
+    assert!(reader.0.is_empty());
+    (program, params)
+}
+
+/// The arguments and name a child started by `command` would find in its
+/// parameter block.
+fn child_args(command: &Command) -> (OsString, Vec<OsString>) {
+    let block = command.params([params::STDIO_INHERIT; 3]).unwrap();
+    let request = spawn_request(&command.program, &block).unwrap();
+    let (program, block) = decode_request(&request);
+    assert_eq!(params::find(block, params::NAME_TAG), Some(&program.as_inner().inner[..]));
+    (program, params::decode_args(params::find(block, params::ARGS_TAG).unwrap()).unwrap())
+}
+
+#[test]
+fn request_layout() {
+    let request = spawn_request(&os(b"hello"), b"AppP\0\0\0\0").unwrap();
+    assert_eq!(request, b"\x05\x00hello\x08\x00\x00\x00AppP\0\0\0\0");
+}
+
+#[test]
+fn args_arrive_unchanged() {
+    let args = [
+        os(b""),
+        os(b"two words"),
+        os(b"\"double\" and 'single' quotes"),
+        os(b"line\nbreak"),
+        os(b"\xc3\x28 is not UTF-8"),
+        os(b"nul\0byte"),
+    ];
+    let mut command = Command::new(&os(b"echo \xff"));
+    for arg in &args {
+        command.arg(arg);
+    }
+    let (program, child) = child_args(&command);
+    assert_eq!(program, os(b"echo \xff"));
+    assert_eq!(child[0], program);
+    assert_eq!(child[1..], args);
+}
+
+/// A small, fixed pseudo-random sequence, so that a failure can be repeated.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[test]
+fn random_args_round_trip() {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    for _ in 0..200 {
+        let args: Vec<OsString> = (0..rng.below(20))
+            .map(|_| {
+                let len = if rng.below(10) == 0 { rng.below(1000) } else { rng.below(20) };
+                os(&(0..len).map(|_| rng.next() as u8).collect::<Vec<_>>())
+            })
+            .collect();
+        let mut command = Command::new(&os(b"prog"));
+        for arg in &args {
+            command.arg(arg);
+        }
+        let (_, child) = child_args(&command);
+        assert_eq!(child[1..], args);
+    }
+}
+
+#[test]
+fn too_many_args() {
+    let mut command = Command::new(&os(b"prog"));
+    let arg = os(&[b'x'; 1000]);
+    for _ in 0..params::ARGS_LIMIT / 1000 {
+        command.arg(&arg);
+    }
+    let e = command.params([params::STDIO_INHERIT; 3]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // A request that wouldn't fit in the pages lent with it.
+    let block = vec![0; SPAWN_PAGES * PAGE_SIZE];
+    let e = spawn_request(&os(b"prog"), &block).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn file_as_stdio_fails_to_spawn() {
+    let dir = crate::sys_common::io::test::tmpdir();
+    let file = crate::fs::File::create(dir.join("out")).unwrap();
+    let mut command = Command::new(&os(b"prog"));
+    command.stdout(Stdio::from(file.into_inner()));
+    match command.spawn(Stdio::Inherit, false) {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        Ok(_) => panic!("spawned with a file as stdout"),
+    }
+}
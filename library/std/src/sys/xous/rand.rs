@@ -0,0 +1,117 @@
+use super::page_pool::{self, PAGE_SIZE};
+use super::services;
+use crate::io;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sys_common::mutex::StaticMutex;
+
+/// The keys every `RandomState` in this process starts from, once they have
+/// been made.
+static KEYS_LOCK: StaticMutex = StaticMutex::new();
+static mut KEYS: Option<(u64, u64)> = None;
+
+/// Returns keys for `HashMap`'s `RandomState`, read from the TRNG the first
+/// time and the same for the rest of the process.
+///
+/// Some programs make their first `HashMap` very early, possibly before the
+/// TRNG has started, so it is waited for like any other server. If it still
+/// can't be reached, the keys are made from the time and the process ID
+/// instead. Those are far easier to guess, but at least differ between
+/// processes and between boots.
+pub fn hashmap_random_keys() -> (u64, u64) {
+    unsafe {
+        let _guard = KEYS_LOCK.lock();
+        *KEYS.get_or_insert_with(|| trng_keys().unwrap_or_else(fallback_keys))
+    }
+}
+
+fn trng_keys() -> Option<(u64, u64)> {
+    Some((next_u64().ok()?, next_u64().ok()?))
+}
+
+fn trng() -> io::Result<xous::CID> {
+    services::try_trng()
+        .ok_or(io::const_io_error!(io::ErrorKind::NotFound, &"the TRNG can't be reached"))
+}
+
+fn health_failure() -> io::Error {
+    io::const_io_error!(io::ErrorKind::Other, &"the TRNG failed its health tests")
+}
+
+/// Returns 64 random bits from the TRNG, which is one request to it.
+pub fn next_u64() -> io::Result<u64> {
+    // Asks for two 32-bit words. The TRNG answers with anything but the
+    // words if its health tests have failed, rather than hand out data that
+    // may not be random.
+    let message = xous::Message::new_blocking_scalar(0 /* GetTrng */, 2, 0, 0, 0);
+    match services::send(trng()?, message)? {
+        xous::Result::Scalar2(lower, upper) => Ok(lower as u64 | (upper as u64) << 32),
+        _ => Err(health_failure()),
+    }
+}
+
+/// Fills shorter than this are made eight bytes at a time with `next_u64`,
+/// which costs less than lending a page for a few requests' worth.
+const MIN_PAGE_FILL: usize = 64;
+
+/// Cleared once the TRNG turns out to predate `FillTrng`, after which every
+/// fill is made with `next_u64`.
+static PAGE_FILLS: AtomicBool = AtomicBool::new(true);
+
+/// Fills `buf`, which is at most a page, by lending the TRNG a page to fill
+/// with `valid` set to the length of `buf`. The TRNG returns the page with
+/// `valid` unchanged, or with an offset set if its health tests have failed.
+/// Returns `false` if the TRNG doesn't answer that way, which older ones
+/// don't.
+fn fill_page(buf: &mut [u8]) -> io::Result<bool> {
+    let mut page = page_pool::get();
+    let range = page.range();
+    let valid = xous::MemorySize::new(buf.len());
+    let message = xous::Message::new_lend_mut(1 /* FillTrng */, range, None, valid);
+    match services::send(trng()?, message)? {
+        xous::Result::MemoryReturned(Some(_), _) => Err(health_failure()),
+        xous::Result::MemoryReturned(None, returned) if returned == valid => {
+            buf.copy_from_slice(&page[..buf.len()]);
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Fills `buf` with random bytes from the TRNG, a page at a time, leaving
+/// anything shorter than `MIN_PAGE_FILL` at the end to `next_u64`. On
+/// error, `buf` may have been partly filled.
+pub fn fill_bytes(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while buf.len() - filled >= MIN_PAGE_FILL && PAGE_FILLS.load(Ordering::Relaxed) {
+        let len = (buf.len() - filled).min(PAGE_SIZE);
+        if !fill_page(&mut buf[filled..filled + len])? {
+            PAGE_FILLS.store(false, Ordering::Relaxed);
+            break;
+        }
+        filled += len;
+    }
+    for chunk in buf[filled..].chunks_mut(8) {
+        chunk.copy_from_slice(&next_u64()?.to_le_bytes()[..chunk.len()]);
+    }
+    Ok(())
+}
+
+fn fallback_keys() -> (u64, u64) {
+    let elapsed = |opcode| match services::ticktimer_scalar(opcode, 0, 0, 0, 0) {
+        Ok(xous::Result::Scalar2(lower, upper)) => Some(lower as u64 | (upper as u64) << 32),
+        _ => None,
+    };
+    let time = elapsed(16 /* ElapsedNs */).or_else(|| elapsed(0 /* ElapsedMs */)).unwrap_or(0);
+    let pid = xous::syscall::current_pid().map_or(0, |pid| pid.get() as u64);
+    (mix(time ^ pid << 48), mix(time.rotate_left(32) ^ pid))
+}
+
+/// Spreads every bit of `x` across the result, so that the few bits that
+/// vary in the time and process ID affect the whole key. This is the
+/// finalizer of SplitMix64.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
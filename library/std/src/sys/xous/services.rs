@@ -1,52 +1,500 @@
+use super::error::{decode_error_kind, error_string, xous_error};
+use crate::io;
+use crate::sys_common::mutex::StaticMutex;
 use core::sync::atomic::{AtomicU32, Ordering};
 use xous::services::nameserver as ns;
 
-pub(crate) fn network() -> xous::CID {
-    static NETWORK_CID: AtomicU32 = AtomicU32::new(0);
-    let cid = NETWORK_CID.load(Ordering::Relaxed);
-    if cid != 0 {
-        return cid;
+/// A connection the standard library makes for its own use, made by
+/// whichever thread needs it first and then shared by every thread.
+///
+/// Only atomics are involved, so this works before the runtime is set up
+/// and never allocates. While one thread connects, the others wait for it
+/// instead of making connections of their own. If connecting fails, the
+/// next thread to ask tries again.
+///
+/// Each one is a static here, with an accessor that hands out its
+/// connection through `connect_cached`.
+pub(crate) struct ServiceCid {
+    cid: AtomicU32,
+    /// What the server is called in messages, such as "the PDDB".
+    name: &'static str,
+    address: Address,
+    patience: Patience,
+}
+
+/// Where to find a server.
+#[derive(Clone, Copy)]
+enum Address {
+    /// Registered with the name server under this name.
+    Name(&'static str),
+    /// Listening on this well-known server ID.
+    Sid(&'static [u8; 16]),
+}
+
+impl Address {
+    /// Connects to the server. Unless `wait` is set, a server that hasn't
+    /// started yet fails with `ServerNotFound`.
+    fn connect(self, wait: bool) -> Result<xous::CID, xous::Error> {
+        match self {
+            Address::Name(name) => ns::connect(name),
+            Address::Sid(sid) if wait => xous::connect(xous::SID::from_bytes(sid).unwrap()),
+            Address::Sid(sid) => xous::try_connect(xous::SID::from_bytes(sid).unwrap()),
+        }
     }
+}
+
+/// Marks a connection as being made by another thread. Connection IDs are
+/// small, so this is never a real one.
+const CONNECTING: u32 = u32::MAX;
 
-    let cid = ns::connect("_Middleware Network Server_").unwrap();
-    NETWORK_CID.store(cid, Ordering::Relaxed);
-    cid
+/// The longest single wait between attempts to reach a server that hasn't
+/// started yet.
+const MAX_RETRY_DELAY_MS: usize = 256;
+
+/// How long to keep trying to reach a server that hasn't started yet, which
+/// happens to processes started early in boot.
+#[derive(Clone, Copy)]
+enum Patience {
+    /// A couple of seconds, sleeping on the ticktimer in between.
+    Normal,
+    /// Ten seconds, for the log server, which nearly everything uses.
+    Generous,
+    /// As long as it takes, for the ticktimer itself. Nothing can tell the
+    /// time until the ticktimer runs, so the kernel does the waiting: it
+    /// parks a thread connecting to a server ID that nobody has claimed yet
+    /// until somebody does, without giving it any time slices meanwhile.
+    Ticktimer,
 }
 
-pub(crate) fn dns() -> xous::CID {
-    static DNS_CID: AtomicU32 = AtomicU32::new(0);
-    let cid = DNS_CID.load(Ordering::Relaxed);
-    if cid != 0 {
-        return cid;
+impl Patience {
+    /// How long to wait, or `None` to leave it to the kernel.
+    fn budget_ms(self) -> Option<u64> {
+        match self {
+            Patience::Normal => Some(2_000),
+            Patience::Generous => Some(10_000),
+            Patience::Ticktimer => None,
+        }
+    }
+}
+
+/// Milliseconds since boot, as the ticktimer counts them.
+fn elapsed_ms() -> Option<u64> {
+    match ticktimer_scalar(0 /* ElapsedMs */, 0, 0, 0, 0) {
+        Ok(xous::Result::Scalar2(lower, upper)) => Some(lower as u64 | (upper as u64) << 32),
+        _ => None,
+    }
+}
+
+/// Connects to `address` once it has started, waiting longer between each
+/// attempt, and returns the last error once `patience` runs out. The time
+/// waited is measured on the ticktimer, so slow attempts count too. Other
+/// errors are returned right away.
+fn connect_patiently(patience: Patience, address: Address) -> Result<xous::CID, xous::Error> {
+    let budget = match patience.budget_ms() {
+        Some(budget) => budget,
+        None => return address.connect(true),
+    };
+    let start = elapsed_ms();
+    let mut delay = 1;
+    loop {
+        match address.connect(false) {
+            Err(xous::Error::ServerNotFound) => {
+                // A ticktimer that can't be reached can't be slept on either.
+                let waited = match (start, elapsed_ms()) {
+                    (Some(start), Some(now)) => now.saturating_sub(start),
+                    _ => return Err(xous::Error::ServerNotFound),
+                };
+                if waited >= budget {
+                    return Err(xous::Error::ServerNotFound);
+                }
+                ticktimer_scalar(1 /* SleepMs */, delay, 0, 0, 0).ok();
+                delay = (delay * 2).min(MAX_RETRY_DELAY_MS);
+            }
+            result => return result,
+        }
+    }
+}
+
+impl ServiceCid {
+    const fn new(name: &'static str, address: Address, patience: Patience) -> ServiceCid {
+        ServiceCid { cid: AtomicU32::new(0), name, address, patience }
+    }
+
+    /// Forgets the connection if it is still `stale`, so that the next call
+    /// to `get` connects again.
+    fn reset(&self, stale: xous::CID) {
+        self.cid.compare_exchange(stale, 0, Ordering::AcqRel, Ordering::Relaxed).ok();
+    }
+
+    /// Closes the connection, if there is one and no thread is still making
+    /// it. The next call to `get` connects again.
+    fn disconnect(&self) {
+        let cid = self.cid.load(Ordering::Acquire);
+        if cid == 0 || cid == CONNECTING {
+            return;
+        }
+        if self.cid.compare_exchange(cid, 0, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            // A server that has already gone away has nothing to close.
+            unsafe { xous::disconnect(cid).ok() };
+        }
+    }
+
+    fn is(&self, cid: xous::CID) -> bool {
+        self.cid.load(Ordering::Acquire) == cid
+    }
+}
+
+/// Returns the connection to `service`, connecting to it if no thread has
+/// yet. Panics if the server can't be reached within the service's
+/// patience.
+fn connect_cached(service: &ServiceCid) -> xous::CID {
+    match try_connect_cached(service) {
+        Ok(cid) => cid,
+        Err(e) => panic!("unable to connect to {}: {:?}", service.name, e),
     }
+}
+
+/// Like `connect_cached`, but returns the error if the server can't be
+/// reached. The next caller tries again.
+fn try_connect_cached(service: &ServiceCid) -> Result<xous::CID, xous::Error> {
+    loop {
+        match service.cid.compare_exchange(0, CONNECTING, Ordering::Acquire, Ordering::Acquire) {
+            Ok(_) => {
+                let result = connect_patiently(service.patience, service.address);
+                // If connecting failed, this lets the next caller try again.
+                service.cid.store(result.as_ref().map_or(0, |&cid| cid), Ordering::Release);
+                return result;
+            }
+            Err(CONNECTING) => xous::syscall::yield_slice(),
+            Err(cid) => return Ok(cid),
+        }
+    }
+}
+
+pub(crate) static NETWORK: ServiceCid = ServiceCid::new(
+    "the network server",
+    Address::Name("_Middleware Network Server_"),
+    Patience::Normal,
+);
+pub(crate) static DNS: ServiceCid = ServiceCid::new(
+    "the DNS resolver",
+    Address::Name("_DNS Resolver Middleware_"),
+    Patience::Normal,
+);
+pub(crate) static CONSOLE: ServiceCid =
+    ServiceCid::new("the console", Address::Name("_Console Input_"), Patience::Normal);
+pub(crate) static KEYBOARD: ServiceCid =
+    ServiceCid::new("the keyboard server", Address::Name("_Keyboard_"), Patience::Normal);
+pub(crate) static PDDB: ServiceCid =
+    ServiceCid::new("the PDDB", Address::Name("_Plausibly Deniable Database_"), Patience::Normal);
+pub(crate) static LOADER: ServiceCid =
+    ServiceCid::new("the process loader", Address::Name("_Process Loader_"), Patience::Normal);
+pub(crate) static TICKTIMER: ServiceCid =
+    ServiceCid::new("the ticktimer", Address::Sid(b"ticktimer-server"), Patience::Ticktimer);
+pub(crate) static RTC: ServiceCid =
+    ServiceCid::new("the time server", Address::Sid(b"timeserverpublic"), Patience::Normal);
+pub(crate) static TRNG: ServiceCid =
+    ServiceCid::new("the TRNG", Address::Name("_TRNG manager_"), Patience::Normal);
+pub(crate) static LOG: ServiceCid =
+    ServiceCid::new("the log server", Address::Sid(b"xous-log-server "), Patience::Generous);
+
+/// Every connection the standard library keeps for itself. These are never
+/// closed, even if a `Connection` to the same server is dropped, until the
+/// process exits. The log server comes last, since writing output needs it.
+static PINNED: [&ServiceCid; 10] =
+    [&NETWORK, &DNS, &CONSOLE, &KEYBOARD, &PDDB, &LOADER, &TICKTIMER, &RTC, &TRNG, &LOG];
+
+fn is_pinned(cid: xous::CID) -> bool {
+    PINNED.iter().any(|service| service.is(cid))
+}
+
+/// Closes the connections the standard library made for itself, in the
+/// order of `PINNED`. Each one takes up a slot in its server's table of
+/// connections until it is closed, so a process that exits without closing
+/// them leaves the server with less room for processes started later.
+///
+/// Called by the runtime cleanup, which `process::exit` runs too, but
+/// `abort` doesn't. Anything that needs a server after this connects again.
+pub(crate) fn disconnect_all() {
+    for service in PINNED {
+        service.disconnect();
+    }
+}
+
+/// The network server, `_Middleware Network Server_`.
+pub(crate) fn network() -> xous::CID {
+    connect_cached(&NETWORK)
+}
+
+/// The DNS resolver, `_DNS Resolver Middleware_`.
+pub(crate) fn dns() -> xous::CID {
+    connect_cached(&DNS)
+}
+
+/// The console, `_Console Input_`, which stdin reads from.
+pub(crate) fn console() -> xous::CID {
+    connect_cached(&CONSOLE)
+}
+
+/// The console, or `None` if it can't be reached, as in a process with no
+/// console to read from.
+pub(crate) fn try_console() -> Option<xous::CID> {
+    try_connect_cached(&CONSOLE).ok()
+}
+
+/// The keyboard server, `_Keyboard_`.
+pub(crate) fn keyboard() -> xous::CID {
+    connect_cached(&KEYBOARD)
+}
 
-    let cid = ns::connect("_DNS Resolver Middleware_").unwrap();
-    DNS_CID.store(cid, Ordering::Relaxed);
-    cid
+/// The PDDB, `_Plausibly Deniable Database_`, which holds the filesystem.
+pub(crate) fn pddb() -> xous::CID {
+    connect_cached(&PDDB)
 }
 
+/// The process loader, `_Process Loader_`.
+pub(crate) fn loader() -> xous::CID {
+    connect_cached(&LOADER)
+}
+
+/// The ticktimer, on the server ID `ticktimer-server`. Sleeping is done by
+/// sending it a blocking message, so nearly every thread ends up here.
 pub(crate) fn ticktimer() -> xous::CID {
-    // Sleep is done by connecting to the ticktimer server and sending
-    // a blocking message.
-    static TICKTIMER_CID: AtomicU32 = AtomicU32::new(0);
-    let cid = TICKTIMER_CID.load(Ordering::Relaxed);
-    if cid != 0 {
-        return cid;
+    connect_cached(&TICKTIMER)
+}
+
+/// The log server, on the server ID `xous-log-server `, which stdout and
+/// stderr write to.
+pub(crate) fn log_server() -> xous::CID {
+    connect_cached(&LOG)
+}
+
+/// The log server, or `None` if it can't be reached.
+pub(crate) fn try_log_server() -> Option<xous::CID> {
+    try_connect_cached(&LOG).ok()
+}
+
+/// The time server, on the server ID `timeserverpublic`, which fronts the
+/// RTC and keeps the wall-clock offset.
+pub(crate) fn rtc() -> xous::CID {
+    connect_cached(&RTC)
+}
+
+/// The TRNG, `_TRNG manager_`, or `None` if it can't be reached. Its only
+/// user has something else to fall back on.
+pub(crate) fn try_trng() -> Option<xous::CID> {
+    try_connect_cached(&TRNG).ok()
+}
+
+/// Drops the cached ticktimer connection if it is still `stale`, for example
+/// because the ticktimer was restarted, and returns a fresh one.
+pub(crate) fn ticktimer_reconnect(stale: xous::CID) -> xous::CID {
+    TICKTIMER.reset(stale);
+    ticktimer()
+}
+
+/// Sends a blocking scalar to the ticktimer, reconnecting once if the
+/// cached connection turns out to be dead.
+pub(crate) fn ticktimer_scalar(
+    opcode: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+) -> Result<xous::Result, xous::Error> {
+    let message = || xous::Message::new_blocking_scalar(opcode, arg1, arg2, arg3, arg4);
+    let cid = ticktimer();
+    match xous::send_message(cid, message()) {
+        Err(xous::Error::ServerNotFound) => xous::send_message(ticktimer_reconnect(cid), message()),
+        result => result,
+    }
+}
+
+/// Turns an error from sending to `cid` into an `io::Error`. A server that
+/// has gone away, for example because it crashed, gives `BrokenPipe` naming
+/// it, and its cached connection is dropped so that the next request
+/// reaches it again once it has restarted.
+fn send_error(cid: xous::CID, e: xous::Error) -> io::Error {
+    if !matches!(e, xous::Error::ServerNotFound) {
+        return xous_error(e);
+    }
+    match PINNED.iter().find(|service| service.is(cid)) {
+        Some(service) => {
+            service.reset(cid);
+            io::Error::new(io::ErrorKind::BrokenPipe, format!("{} has gone away", service.name))
+        }
+        None => io::const_io_error!(io::ErrorKind::BrokenPipe, &"the server has gone away"),
+    }
+}
+
+/// Sends `message` over `cid`, one of the connections from this module.
+/// Everything here talks to its servers through this, so that they all
+/// report a server that has gone away the same way.
+pub(crate) fn send(cid: xous::CID, message: xous::Message) -> io::Result<xous::Result> {
+    send_message(cid, message).map_err(|e| send_error(cid, e))
+}
+
+/// The name of the server on `cid`, if it is one of the connections from
+/// this module.
+pub(crate) fn name_of(cid: xous::CID) -> Option<&'static str> {
+    PINNED.iter().find(|service| service.is(cid)).map(|service| service.name)
+}
+
+/// Sends `message` over `cid`, tracing it if the standard library was built
+/// with the `xous-trace-ipc` feature. Without it, this is just the send.
+fn send_message(cid: xous::CID, message: xous::Message) -> Result<xous::Result, xous::Error> {
+    #[cfg(feature = "xous-trace-ipc")]
+    let trace = super::trace::start(cid, &message);
+    let result = xous::send_message(cid, message);
+    #[cfg(feature = "xous-trace-ipc")]
+    trace.finish(&result);
+    result
+}
+
+/// Runs `request`, a request to a server itself rather than about something
+/// open on it, and runs it once more if the server turned out to have gone
+/// away. `send` has dropped the stale connection by then, so the second try
+/// looks the server up again, waiting for it to come back if it is being
+/// restarted. Threads that notice at the same time share the one new
+/// connection, as with any other.
+///
+/// Requests about something open on the server, such as a socket, aren't
+/// retried, since whatever was open went away with the server.
+pub(crate) fn retry_if_gone<T>(mut request: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    match request() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => request(),
+        result => result,
+    }
+}
+
+/// A connection to a server, shared with everything else in the process.
+///
+/// The kernel gives a process a single connection to each server, so the
+/// connections handed out here are counted, and one is only closed when the
+/// last `Connection` to it is dropped. Connections that the standard library
+/// makes for its own use are never closed.
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct Connection {
+    cid: xous::CID,
+}
+
+struct Shared {
+    cid: xous::CID,
+    /// The name the connection was looked up by, if it was handed out here.
+    name: Option<String>,
+    users: usize,
+}
+
+static SHARED_LOCK: StaticMutex = StaticMutex::new();
+static mut SHARED: Vec<Shared> = Vec::new();
+
+fn with_shared<R>(f: impl FnOnce(&mut Vec<Shared>) -> R) -> R {
+    unsafe {
+        let _guard = SHARED_LOCK.lock();
+        f(&mut SHARED)
+    }
+}
+
+/// Connects to the server registered with the name server as `name`,
+/// reusing the connection if the process already has one.
+pub fn connect(name: &str) -> io::Result<Connection> {
+    let cached = with_shared(|shared| {
+        let s = shared.iter_mut().find(|s| s.name.as_deref() == Some(name))?;
+        s.users += 1;
+        Some(s.cid)
+    });
+    if let Some(cid) = cached {
+        return Ok(Connection { cid });
     }
 
-    let cid = xous::connect(xous::SID::from_bytes(b"ticktimer-server").unwrap()).unwrap();
-    TICKTIMER_CID.store(cid, Ordering::Relaxed);
-    cid
+    let cid = ns::connect(name).map_err(|e| {
+        let code = e.to_usize() as i32;
+        io::Error::new(
+            decode_error_kind(code),
+            format!("unable to connect to service `{}`: {}", name, error_string(code)),
+        )
+    })?;
+    with_shared(|shared| match shared.iter_mut().find(|s| s.cid == cid) {
+        Some(s) => {
+            s.name.get_or_insert_with(|| name.to_owned());
+            s.users += 1;
+        }
+        None => shared.push(Shared { cid, name: Some(name.to_owned()), users: 1 }),
+    });
+    Ok(Connection { cid })
 }
 
-pub(crate) fn systime() -> xous::CID {
-    static SYSTIME_CID: AtomicU32 = AtomicU32::new(0);
-    let cid = SYSTIME_CID.load(Ordering::Relaxed);
-    if cid != 0 {
-        return cid;
+#[unstable(feature = "xous_std", issue = "none")]
+impl Connection {
+    /// Returns the raw connection ID, for sending messages this type has no
+    /// wrapper for. The ID stays valid as long as `self` is alive.
+    pub fn cid(&self) -> u32 {
+        self.cid
     }
 
-    let cid = xous::connect(xous::SID::from_bytes(b"timeserverpublic").unwrap()).unwrap();
-    SYSTIME_CID.store(cid, Ordering::Relaxed);
-    cid
+    /// Sends a scalar message without waiting for the server to handle it.
+    pub fn send(&self, opcode: usize, args: [usize; 4]) -> io::Result<()> {
+        let [a1, a2, a3, a4] = args;
+        xous::send_message(self.cid, xous::Message::new_scalar(opcode, a1, a2, a3, a4))
+            .map(drop)
+            .map_err(xous_error)
+    }
+
+    /// Sends a scalar message and waits for the server's scalar reply. Reply
+    /// values the server didn't send are zero.
+    pub fn send_blocking(&self, opcode: usize, args: [usize; 4]) -> io::Result<[usize; 5]> {
+        let [a1, a2, a3, a4] = args;
+        match xous::send_message(
+            self.cid,
+            xous::Message::new_blocking_scalar(opcode, a1, a2, a3, a4),
+        )
+        .map_err(xous_error)?
+        {
+            xous::Result::Scalar1(r1) => Ok([r1, 0, 0, 0, 0]),
+            xous::Result::Scalar2(r1, r2) => Ok([r1, r2, 0, 0, 0]),
+            xous::Result::Scalar5(r1, r2, r3, r4, r5) => Ok([r1, r2, r3, r4, r5]),
+            _ => Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                &"the server did not reply with a scalar"
+            )),
+        }
+    }
+}
+
+impl Clone for Connection {
+    fn clone(&self) -> Connection {
+        with_shared(|shared| {
+            if let Some(s) = shared.iter_mut().find(|s| s.cid == self.cid) {
+                s.users += 1;
+            }
+        });
+        Connection { cid: self.cid }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let close = with_shared(|shared| {
+            let index = match shared.iter().position(|s| s.cid == self.cid) {
+                Some(index) => index,
+                None => return false,
+            };
+            let s = &mut shared[index];
+            s.users -= 1;
+            if s.users > 0 || is_pinned(self.cid) {
+                return false;
+            }
+            shared.swap_remove(index);
+            true
+        });
+        if close {
+            unsafe { xous::disconnect(self.cid).ok() };
+        }
+    }
+}
+
+impl crate::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut crate::fmt::Formatter<'_>) -> crate::fmt::Result {
+        f.debug_struct("Connection").field("cid", &self.cid).finish()
+    }
 }
@@ -1,20 +1,54 @@
+use super::error::xous_error;
+use super::{params, pipe};
+use crate::cell::UnsafeCell;
 use crate::io;
+use crate::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use crate::sys::services::{self, try_log_server};
+use crate::time::Duration;
 use xous::{
-    connect, map_memory, send_message, try_send_message, MemoryRange, MemorySize, Message,
-    ScalarMessage, CID, SID,
+    connect, send_message, try_send_message, MemoryRange, MemorySize, Message, ScalarMessage, CID,
+    SID,
 };
 
 /// Messages will get split into chunks that are, at most, this
-/// number of bytes.
+/// number of bytes. Longer output is framed so that the log server still
+/// prints it in one piece.
 const MESSAGE_CHUNK_SIZE: usize = 4096;
 
 pub struct Stdin;
-pub struct Stdout {
-    mem: Option<MemoryRange>,
-}
+pub struct Stdout;
 pub struct Stderr;
 
-static mut LOG_SERVER_CONNECTION: Option<CID> = None;
+/// Read timeout for stdin in milliseconds, where 0 waits forever. Shared by
+/// all handles, since they all read from the same console.
+static STDIN_READ_TIMEOUT: AtomicU32 = AtomicU32::new(0);
+
+/// Where one of this process's stdio streams goes, as decided by whoever
+/// started it.
+enum Redirect {
+    /// The console for stdin, and the log server for stdout and stderr.
+    Inherit,
+    /// Nothing: reads find the end, and writes are thrown away.
+    Null,
+    /// The pipe end with this handle.
+    Pipe(usize),
+}
+
+fn redirect(stream: usize) -> Redirect {
+    match params::block(params::STDIO_TAG).and_then(params::decode_stdio) {
+        None => Redirect::Inherit,
+        Some(handles) => match handles[stream] {
+            params::STDIO_INHERIT => Redirect::Inherit,
+            params::STDIO_NULL => Redirect::Null,
+            handle => Redirect::Pipe(handle),
+        },
+    }
+}
+
+#[repr(C, align(4096))]
+struct StdinBuffer {
+    raw: [u8; 4096],
+}
 
 impl Stdin {
     pub const fn new() -> Stdin {
@@ -23,52 +57,341 @@ pub const fn new() -> Stdin {
 }
 
 impl io::Read for Stdin {
-    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
-        Ok(0)
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match redirect(0) {
+            Redirect::Inherit => {}
+            Redirect::Null => return Ok(0),
+            Redirect::Pipe(handle) => return pipe::read(handle, buf),
+        }
+
+        let mut request = StdinBuffer { raw: [0u8; 4096] };
+        let data_to_read = buf.len().min(request.raw.len());
+        if data_to_read == 0 {
+            return Ok(0);
+        }
+
+        // A process with no console, such as one running headless, reads
+        // the end of the file, as it would from a detached console.
+        let console = match services::try_console() {
+            Some(console) => console,
+            None => return Ok(0),
+        };
+
+        let range =
+            unsafe { MemoryRange::new(&mut request as *mut StdinBuffer as usize, 4096).unwrap() };
+
+        // The console blocks until at least one byte is available, then returns
+        // however many bytes it has, up to `data_to_read`.
+        match xous::send_message(
+            console,
+            Message::new_lend_mut(
+                0, /* StdinRead */
+                range,
+                // Reuse the `offset` as the read timeout
+                xous::MemoryAddress::new(STDIN_READ_TIMEOUT.load(Ordering::Relaxed) as usize),
+                MemorySize::new(data_to_read),
+            ),
+        ) {
+            // A nonzero `offset` in the response means the timeout elapsed.
+            Ok(xous::Result::MemoryReturned(Some(_), _)) => {
+                Err(io::const_io_error!(io::ErrorKind::TimedOut, &"stdin read timed out"))
+            }
+            // No valid bytes means the console has been detached: end of file.
+            Ok(xous::Result::MemoryReturned(None, valid)) => {
+                let length = valid.map_or(0, |v| v.get()).min(data_to_read);
+                buf[..length].copy_from_slice(&request.raw[..length]);
+                Ok(length)
+            }
+            _ => Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                &"Library failure: wrong message type or messaging error"
+            )),
+        }
     }
 }
 
-impl Stdout {
-    pub const fn new() -> Stdout {
-        Stdout { mem: None }
-    }
-    fn ensure_connection(&mut self) {
-        unsafe {
-            // Accessing a global mutable is safe, because this call is idempotent.
-            // If there is a fight between threads, the result will be the same.
-            if LOG_SERVER_CONNECTION.is_none() {
-                LOG_SERVER_CONNECTION =
-                    Some(connect(SID::from_bytes(b"xous-log-server ").unwrap()).unwrap());
+pub fn set_stdin_read_timeout(timeout: Option<Duration>) -> io::Result<()> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::const_io_error!(io::ErrorKind::InvalidInput, &"Zero duration is invalid"));
+    }
+    STDIN_READ_TIMEOUT.store(
+        timeout.map(|t| t.as_millis().clamp(1, u32::MAX as u128) as u32).unwrap_or_default(),
+        Ordering::Relaxed,
+    );
+    Ok(())
+}
+
+pub fn stdin_read_timeout() -> Option<Duration> {
+    match STDIN_READ_TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        t => Some(Duration::from_millis(t as u64)),
+    }
+}
+
+/// A single key press or release, as delivered by the keyboard service.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[unstable(feature = "xous_std", issue = "none")]
+pub struct KeyEvent {
+    /// The keyboard service's code for the key. For keys that produce text,
+    /// this is the Unicode scalar value of the character.
+    pub keycode: u32,
+    /// The character produced by the key, if any. Keys such as the arrows
+    /// are reported through `keycode` only.
+    pub char: Option<char>,
+    /// `true` if the key went down, `false` if it was released.
+    pub pressed: bool,
+    /// Whether a Shift key was held.
+    pub shift: bool,
+    /// Whether a Control key was held.
+    pub ctrl: bool,
+    /// Whether an Alt key was held.
+    pub alt: bool,
+}
+
+// Bits of the flags word returned alongside the keycode by ReadKeyEvent.
+const KEY_PRESSED: usize = 1 << 0;
+const KEY_SHIFT: usize = 1 << 1;
+const KEY_CTRL: usize = 1 << 2;
+const KEY_ALT: usize = 1 << 3;
+const KEY_IS_CHAR: usize = 1 << 4;
+const KEY_TIMED_OUT: usize = 1 << 31;
+
+/// Waits for the next key event, honoring the stdin read timeout.
+pub fn read_key_event() -> io::Result<KeyEvent> {
+    let timeout = STDIN_READ_TIMEOUT.load(Ordering::Relaxed) as usize;
+    match send_message(
+        services::keyboard(),
+        Message::new_blocking_scalar(0 /* ReadKeyEvent */, timeout, 0, 0, 0),
+    ) {
+        Ok(xous::Result::Scalar2(_, flags)) if flags & KEY_TIMED_OUT != 0 => {
+            Err(io::const_io_error!(io::ErrorKind::TimedOut, &"key event read timed out"))
+        }
+        Ok(xous::Result::Scalar2(keycode, flags)) => {
+            let keycode = keycode as u32;
+            Ok(KeyEvent {
+                keycode,
+                char: if flags & KEY_IS_CHAR != 0 { char::from_u32(keycode) } else { None },
+                pressed: flags & KEY_PRESSED != 0,
+                shift: flags & KEY_SHIFT != 0,
+                ctrl: flags & KEY_CTRL != 0,
+                alt: flags & KEY_ALT != 0,
+            })
+        }
+        _ => Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            &"Library failure: wrong message type or messaging error"
+        )),
+    }
+}
+
+/// Whether this process has switched the console into raw mode, and so must
+/// switch it back before exiting.
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_raw_mode(raw: bool) -> io::Result<()> {
+    match send_message(
+        services::console(),
+        Message::new_blocking_scalar(2 /* SetRawMode */, raw as usize, 0, 0, 0),
+    ) {
+        Ok(xous::Result::Scalar1(0)) => {
+            RAW_MODE.store(raw, Ordering::Relaxed);
+            Ok(())
+        }
+        Ok(xous::Result::Scalar1(_)) => Err(io::const_io_error!(
+            io::ErrorKind::Unsupported,
+            &"console does not support raw mode"
+        )),
+        _ => Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            &"Library failure: wrong message type or messaging error"
+        )),
+    }
+}
+
+/// Puts the console back into line-cooked mode if this process left it raw.
+/// Called on exit, on panic and on abort, so this must not panic: it only
+/// sends a non-blocking message, and ignores failures, including the console
+/// having gone away.
+pub fn restore_cooked_mode() {
+    if RAW_MODE.swap(false, Ordering::Relaxed) {
+        if let Some(console) = services::try_console() {
+            try_send_message(console, Message::new_scalar(2 /* SetRawMode */, 0, 0, 0, 0)).ok();
+        }
+    }
+}
+
+#[repr(C, align(4096))]
+struct LogPage {
+    raw: [u8; MESSAGE_CHUNK_SIZE],
+}
+
+/// The page that text is lent to the log server from. The log server tags
+/// each message with the opcode it arrived on, which is how output sent to
+/// stdout and stderr can be told apart.
+///
+/// Nothing is held here between writes: `io::Stdout` already line-buffers
+/// its output, so every write is sent as soon as it arrives. The only text
+/// carried over from one page to the next is a UTF-8 character that a full
+/// page cut in half.
+struct LogBuffer {
+    opcode: usize,
+    /// Set while a thread is copying into or sending from the page.
+    busy: AtomicBool,
+    len: UnsafeCell<usize>,
+    /// Set while a framed message is partway sent. See `LogBufferGuard::send`.
+    framing: UnsafeCell<bool>,
+    page: UnsafeCell<LogPage>,
+}
+
+unsafe impl Sync for LogBuffer {}
+
+static STDOUT_BUFFER: LogBuffer = LogBuffer::new(1 /* StandardOutput */);
+static STDERR_BUFFER: LogBuffer = LogBuffer::new(2 /* StandardError */);
+
+struct LogBufferGuard<'a>(&'a LogBuffer);
+
+impl LogBuffer {
+    const fn new(opcode: usize) -> LogBuffer {
+        LogBuffer {
+            opcode,
+            busy: AtomicBool::new(false),
+            len: UnsafeCell::new(0),
+            framing: UnsafeCell::new(false),
+            page: UnsafeCell::new(LogPage { raw: [0u8; MESSAGE_CHUNK_SIZE] }),
+        }
+    }
+
+    /// Normally uncontended, since callers hold the stdio lock.
+    fn lock(&self) -> LogBufferGuard<'_> {
+        while self.busy.swap(true, Ordering::Acquire) {
+            xous::syscall::yield_slice();
+        }
+        LogBufferGuard(self)
+    }
+}
+
+/// Returns how much of `buf` can be sent without cutting a UTF-8 character
+/// in half. Text that isn't valid UTF-8 is sent as-is.
+fn utf8_boundary(buf: &[u8]) -> usize {
+    match core::str::from_utf8(buf) {
+        // `error_len()` is `None` only if the text is fine apart from a
+        // character that runs off the end, which goes into the next message.
+        Err(e) if e.error_len().is_none() && e.valid_up_to() > 0 => e.valid_up_to(),
+        _ => buf.len(),
+    }
+}
+
+impl LogBufferGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = unsafe { &mut *self.0.len.get() };
+        let page = unsafe { &mut *self.0.page.get() };
+        let mut remaining = buf;
+        while !remaining.is_empty() {
+            let space = page.raw.len() - *len;
+            let count = space.min(remaining.len());
+            page.raw[*len..*len + count].copy_from_slice(&remaining[..count]);
+            *len += count;
+            remaining = &remaining[count..];
+            if *len == page.raw.len() {
+                let boundary = utf8_boundary(&page.raw[..*len]);
+                self.send(boundary, true)?;
             }
         }
-        if self.mem.is_none() {
-            self.mem = Some(
-                map_memory(
-                    None,
-                    None,
-                    MESSAGE_CHUNK_SIZE,
-                    xous::MemoryFlags::R | xous::MemoryFlags::W,
-                )
-                .unwrap(),
+        self.flush()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let len = unsafe { *self.0.len.get() };
+        self.send(len, false)
+    }
+
+    /// Sends the first `count` buffered bytes and moves whatever follows them
+    /// to the front of the buffer. `more` is set if the text being sent
+    /// continues past `count`.
+    ///
+    /// Text that fits in one page goes out as a single message, so the log
+    /// server never mixes it with other output. Longer text is framed: the
+    /// `offset` of each message carries a begin, continue or end marker, and
+    /// the log server holds the pieces until the frame is complete. The log
+    /// server may accept less than a full message. Within a frame, that
+    /// leaves the frame open, and the rest is sent again with the marker it
+    /// still needs. A message that wasn't framed has its rest sent unframed
+    /// too, since a frame the log server never saw begin can't be ended.
+    fn send(&mut self, mut count: usize, more: bool) -> io::Result<()> {
+        let len = unsafe { &mut *self.0.len.get() };
+        let page = unsafe { &mut *self.0.page.get() };
+        let framing = unsafe { &mut *self.0.framing.get() };
+        let mem = unsafe {
+            MemoryRange::new(self.0.page.get() as usize, core::mem::size_of::<LogPage>()).unwrap()
+        };
+        // A process started without a log server has nowhere for this text
+        // to go. See `is_ebadf`.
+        let log_server = match try_log_server() {
+            Some(log_server) => log_server,
+            None => {
+                *len = 0;
+                *framing = false;
+                return Err(xous_error(xous::Error::ServerNotFound));
+            }
+        };
+        // A frame whose last byte was sent as part of a "continue" still
+        // needs an end marker, even if there is nothing left to send.
+        while count > 0 || (*framing && !more) {
+            let marker = match (*framing, more) {
+                (false, false) => 0,
+                (false, true) => 1, /* FrameBegin */
+                (true, true) => 2,  /* FrameContinue */
+                (true, false) => 3, /* FrameEnd */
+            };
+            let message = Message::new_lend(
+                self.0.opcode,
+                mem,
+                xous::MemoryAddress::new(marker),
+                MemorySize::new(count),
             );
+            let accepted = match send_message(log_server, message) {
+                Ok(xous::Result::MemoryReturned(_, Some(valid))) => valid.get().min(count),
+                // Older log servers don't report how much they took.
+                Ok(_) => count,
+                Err(_) => {
+                    // Drop the text rather than retrying it forever.
+                    *len = 0;
+                    *framing = false;
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::Other,
+                        &"Unable to send to log server"
+                    ));
+                }
+            };
+            *framing = more || (*framing && accepted < count);
+            page.raw.copy_within(accepted..*len, 0);
+            *len -= accepted;
+            count -= accepted;
         }
+        Ok(())
+    }
+}
+
+impl Drop for LogBufferGuard<'_> {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Release);
+    }
+}
+
+impl Stdout {
+    pub const fn new() -> Stdout {
+        Stdout
     }
 }
 
 impl io::Write for Stdout {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.ensure_connection();
-        let mem = &self.mem.unwrap();
-        let connection = unsafe { LOG_SERVER_CONNECTION.unwrap() };
-        let s = unsafe { core::slice::from_raw_parts_mut(mem.as_mut_ptr(), MESSAGE_CHUNK_SIZE) };
-        for chunk in buf.chunks(s.len()) {
-            for (dest, src) in s.iter_mut().zip(chunk) {
-                *dest = *src;
-            }
-            let message = Message::new_lend(1, *mem, None, MemorySize::new(chunk.len()));
-            send_message(connection, message).unwrap();
+        match redirect(1) {
+            Redirect::Inherit => STDOUT_BUFFER.lock().write(buf),
+            Redirect::Null => Ok(buf.len()),
+            Redirect::Pipe(handle) => pipe::write(handle, buf),
         }
-        Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -84,7 +407,11 @@ pub const fn new() -> Stderr {
 
 impl io::Write for Stderr {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        Ok(buf.len())
+        match redirect(2) {
+            Redirect::Inherit => STDERR_BUFFER.lock().write(buf),
+            Redirect::Null => Ok(buf.len()),
+            Redirect::Pipe(handle) => pipe::write(handle, buf),
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -92,10 +419,72 @@ fn flush(&mut self) -> io::Result<()> {
     }
 }
 
-pub const STDIN_BUF_SIZE: usize = 0;
+/// Cached answers to "is this stream attached to an interactive console?".
+/// The answer doesn't change for the life of the process.
+static STDIN_IS_TERMINAL: AtomicU8 = AtomicU8::new(TERMINAL_UNKNOWN);
+static STDOUT_IS_TERMINAL: AtomicU8 = AtomicU8::new(TERMINAL_UNKNOWN);
+static STDERR_IS_TERMINAL: AtomicU8 = AtomicU8::new(TERMINAL_UNKNOWN);
+const TERMINAL_UNKNOWN: u8 = 0;
+const TERMINAL_YES: u8 = 1;
+const TERMINAL_NO: u8 = 2;
+
+fn cached_is_terminal(cache: &AtomicU8, query: impl FnOnce() -> bool) -> bool {
+    match cache.load(Ordering::Relaxed) {
+        TERMINAL_YES => true,
+        TERMINAL_NO => false,
+        _ => {
+            let is_terminal = query();
+            cache.store(if is_terminal { TERMINAL_YES } else { TERMINAL_NO }, Ordering::Relaxed);
+            is_terminal
+        }
+    }
+}
+
+/// Asks a service whether a stream reaches an interactive console, as
+/// opposed to being captured, e.g. by a test harness. Any failure, including
+/// the service not being there to ask, is treated as "not a terminal", which
+/// is the safe choice for callers deciding whether to emit escape codes.
+fn query_is_terminal(connection: Option<CID>, opcode: usize, stream: usize) -> bool {
+    let connection = match connection {
+        Some(connection) => connection,
+        None => return false,
+    };
+    matches!(
+        send_message(connection, Message::new_blocking_scalar(opcode, stream, 0, 0, 0)),
+        Ok(xous::Result::Scalar1(1))
+    )
+}
 
-pub fn is_ebadf(_err: &io::Error) -> bool {
-    true
+// Streams that don't go to the console or log server never reach a terminal.
+pub fn stdin_is_terminal() -> bool {
+    cached_is_terminal(&STDIN_IS_TERMINAL, || {
+        matches!(redirect(0), Redirect::Inherit)
+            && query_is_terminal(services::try_console(), 1 /* IsInteractive */, 0)
+    })
+}
+
+pub fn stdout_is_terminal() -> bool {
+    cached_is_terminal(&STDOUT_IS_TERMINAL, || {
+        matches!(redirect(1), Redirect::Inherit)
+            && query_is_terminal(try_log_server(), 3 /* IsInteractive */, STDOUT_BUFFER.opcode)
+    })
+}
+
+pub fn stderr_is_terminal() -> bool {
+    cached_is_terminal(&STDERR_IS_TERMINAL, || {
+        matches!(redirect(2), Redirect::Inherit)
+            && query_is_terminal(try_log_server(), 3 /* IsInteractive */, STDERR_BUFFER.opcode)
+    })
+}
+
+pub const STDIN_BUF_SIZE: usize = crate::sys_common::io::DEFAULT_BUF_SIZE;
+
+/// The closest Xous has to a closed stdio handle is a process with no log
+/// server to write to, which is treated the way other platforms treat a
+/// closed file descriptor: the output is dropped. Every other error,
+/// including a stdin timeout or a failed send, is reported.
+pub fn is_ebadf(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(xous::Error::ServerNotFound.to_usize() as i32)
 }
 
 #[derive(Copy, Clone)]
@@ -177,26 +566,54 @@ fn flush(&mut self) -> io::Result<()> {
     }
 }
 
-use crate::cell::RefCell;
-thread_local! { static PANIC_WRITER: RefCell<Option<PanicWriter>> = RefCell::new(None) }
+/// Connections used only for panic output, kept apart from the normal stdio
+/// state so that a panic can be reported before stdio is set up, during TLS
+/// teardown, or while another thread is stuck in the middle of a send. Zero
+/// means "not connected yet".
+static PANIC_LOG_CONNECTION: AtomicU32 = AtomicU32::new(0);
+static PANIC_GFX_CONNECTION: AtomicU32 = AtomicU32::new(0);
+/// Stored in `PANIC_GFX_CONNECTION` once connecting to the graphics panic
+/// handler has failed, so that it isn't retried on every panic.
+const PANIC_GFX_UNAVAILABLE: u32 = u32::MAX;
+/// Set once the log server has been told that the process is panicking.
+static PANIC_ANNOUNCED: AtomicBool = AtomicBool::new(false);
 
 pub fn panic_output() -> Option<impl io::Write> {
-    PANIC_WRITER.with(|pwr| {
-        if pwr.borrow().is_none() {
-            // Generally this won't fail because every server has already allocated this connection.
-            let connection = xous::connect(SID::from_bytes(b"xous-log-server ").unwrap()).unwrap();
+    // Make sure the console is usable again after the panic message.
+    restore_cooked_mode();
 
+    // Nothing on this path may panic or block on a lock, since either would
+    // leave us with no output at all. Connecting twice to the same server
+    // hands back the same connection, so racing panics can't leak anything.
+    let conn = match PANIC_LOG_CONNECTION.load(Ordering::Acquire) {
+        0 => {
+            // Generally this won't fail because every server has already allocated this
+            // connection.
+            let conn = connect(SID::from_bytes(b"xous-log-server ")?).ok()?;
+            PANIC_LOG_CONNECTION.store(conn, Ordering::Release);
+            conn
+        }
+        conn => conn,
+    };
+
+    let gfx_conn = match PANIC_GFX_CONNECTION.load(Ordering::Acquire) {
+        PANIC_GFX_UNAVAILABLE => None,
+        0 => {
             // This is possibly fallible in the case that the connection table is full,
-            // and we can't make the connection to the graphics server. Most servers do not already
-            // have this connection.
-            let gfx_conn = xous::connect(SID::from_bytes(b"panic-to-screen!").unwrap()).ok();
+            // and we can't make the connection to the graphics server. Most servers do not
+            // already have this connection.
+            let gfx_conn = SID::from_bytes(b"panic-to-screen!").and_then(|sid| connect(sid).ok());
+            PANIC_GFX_CONNECTION
+                .store(gfx_conn.unwrap_or(PANIC_GFX_UNAVAILABLE), Ordering::Release);
+            gfx_conn
+        }
+        gfx_conn => Some(gfx_conn),
+    };
 
-            let pw = PanicWriter { conn: connection, gfx_conn };
+    if !PANIC_ANNOUNCED.swap(true, Ordering::AcqRel) {
+        // Send the "We're panicking" message (1000).
+        try_send_message(conn, Message::new_scalar(1000, 0, 0, 0, 0)).ok();
+    }
 
-            // Send the "We're panicking" message (1000).
-            try_send_message(connection, Message::new_scalar(1000, 0, 0, 0, 0)).ok();
-            *pwr.borrow_mut() = Some(pw);
-        }
-        *pwr.borrow()
-    })
+    Some(PanicWriter { conn, gfx_conn })
 }
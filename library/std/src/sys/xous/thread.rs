@@ -1,8 +1,11 @@
+use crate::cell::Cell;
 use crate::ffi::CStr;
 use crate::io;
 use crate::num::NonZeroUsize;
-use crate::sys::services::ticktimer;
-use crate::time::Duration;
+use crate::panic::AssertUnwindSafe;
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sys::services::ticktimer_scalar;
+use crate::time::{Duration, Instant};
 use core::arch::asm;
 
 pub struct Thread {
@@ -79,15 +82,21 @@ pub unsafe fn new(stack: usize, p: Box<dyn FnOnce()>) -> io::Result<Thread> {
             xous::rsyscall(call).map_err(|code| io::Error::from_raw_os_error(code as i32))?;
 
         extern "C" fn thread_start(main: *mut usize, guard_page_pre: usize, stack_size: usize) {
-            unsafe {
-                // // Next, set up our stack overflow handler which may get triggered if we run
-                // // out of stack.
-                // let _handler = stack_overflow::Handler::new();
-                // Finally, let's run some code.
-                Box::from_raw(main as *mut Box<dyn FnOnce()>)();
+            // Nothing can touch a thread-local before this.
+            crate::sys::thread_local_key::init_tls();
+
+            let stack_start = guard_page_pre + GUARD_PAGE_SIZE;
+            STACK.with(|stack| stack.set(Some((stack_start, stack_start + stack_size))));
+
+            // `main` catches any panic from the thread's own code, but a
+            // panic must never unwind past here, since there is no frame
+            // above this one to stop it.
+            let main = unsafe { Box::from_raw(main as *mut Box<dyn FnOnce()>) };
+            if crate::panic::catch_unwind(AssertUnwindSafe(main)).is_err() {
+                rtabort!("a thread's entry point panicked");
             }
 
-            // Destroy TLS, which will free the TLS page
+            // Destroy TLS, which will free the TLS block
             unsafe {
                 crate::sys::thread_local_key::destroy_tls();
             }
@@ -129,19 +138,24 @@ pub fn set_name(_name: &CStr) {
     }
 
     pub fn sleep(dur: Duration) {
-        // Because the sleep server works on units of `usized milliseconds`, split
-        // the messages up into these chunks. This means we may run into issues
-        // if you try to sleep a thread for more than 49 days on a 32-bit system.
-        let mut millis = dur.as_millis();
-        while millis > 0 {
-            let sleep_duration =
-                if millis > (usize::MAX as _) { usize::MAX } else { millis as usize };
-            xous::send_message(
-                ticktimer(),
-                xous::Message::new_blocking_scalar(1 /* SleepMs */, sleep_duration, 0, 0, 0),
-            )
-            .expect("Ticktimer: failure to send message to Ticktimer");
-            millis -= sleep_duration as u128;
+        // The sleep server works on units of `usize` milliseconds, which both loses
+        // precision and limits a single request to about 49 days. Sleep against a
+        // deadline instead, rounding each request up to a whole millisecond, so that
+        // we never return before `dur` has elapsed and long sleeps get split up.
+        let deadline = Instant::now().checked_add(dur);
+        loop {
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                // A deadline that doesn't fit into an `Instant` is effectively forever.
+                None => Duration::MAX,
+            };
+            if remaining.is_zero() {
+                break;
+            }
+            let millis = remaining.as_nanos().saturating_add(999_999) / 1_000_000;
+            let sleep_duration = millis.min(usize::MAX as u128) as usize;
+            ticktimer_scalar(1 /* SleepMs */, sleep_duration, 0, 0, 0)
+                .expect("Ticktimer: failure to send message to Ticktimer");
         }
     }
 
@@ -155,13 +169,50 @@ pub fn available_parallelism() -> io::Result<NonZeroUsize> {
     Ok(unsafe { NonZeroUsize::new_unchecked(1) })
 }
 
+crate::thread_local! {
+    /// The stack of a thread started by `Thread::new`, as a range of
+    /// addresses between its guard pages.
+    static STACK: Cell<Option<(usize, usize)>> = const { Cell::new(None) }
+}
+
+/// The stack pointer on entry to the process, as recorded by `_start`. The
+/// main thread's stack ends here.
+static MAIN_STACK_TOP: AtomicUsize = AtomicUsize::new(0);
+
+/// Records the stack pointer on entry to the process.
+pub(crate) fn set_main_stack_top(sp: usize) {
+    MAIN_STACK_TOP.store(sp, Ordering::Relaxed);
+}
+
+/// Returns the current stack pointer.
+#[inline(always)]
+pub(crate) fn stack_pointer() -> usize {
+    let sp: usize;
+    unsafe { asm!("mv {}, sp", out(reg) sp, options(nomem, nostack)) };
+    sp
+}
+
 pub mod guard {
-    pub type Guard = !;
+    use crate::ops::Range;
+
+    /// The range of addresses a thread's stack takes up. The kernel catches
+    /// overflows with guard pages, so this is only used to tell which
+    /// addresses can be read while walking the stack.
+    pub type Guard = Range<usize>;
+
     pub unsafe fn current() -> Option<Guard> {
-        None
+        super::STACK.try_with(|stack| stack.get()).ok().flatten().map(|(start, end)| start..end)
     }
+
+    /// The loader doesn't say how far down the main thread's stack goes, so
+    /// its range starts at the stack pointer as the runtime starts. Frames
+    /// further down are still within the stack, and code walking it should
+    /// go by the stack pointer at the time.
     pub unsafe fn init() -> Option<Guard> {
-        None
+        match super::MAIN_STACK_TOP.load(super::Ordering::Relaxed) {
+            0 => None,
+            top => Some(super::stack_pointer()..top),
+        }
     }
 }
 
@@ -0,0 +1,7 @@
+#![cfg(target_thread_local)]
+#![unstable(feature = "thread_local_internals", issue = "none")]
+
+// Destructors are kept on a list behind one of the TLS keys, whose own
+// destructor runs them along with those of the other keys when the thread
+// exits (see `thread_local_key::run_dtors`).
+pub use crate::sys_common::thread_local_dtor::register_dtor_fallback as register_dtor;
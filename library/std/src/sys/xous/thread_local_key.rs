@@ -5,21 +5,58 @@
 use crate::sync::atomic::Ordering::SeqCst;
 use core::arch::asm;
 
+#[cfg(test)]
+mod tests;
+
 /// Thread Local Storage
-/// Currently, we are limited to 1023 TLS entries. The entries
-/// live in a page of memory that's unique per-process, and is
+/// Each thread has a block of memory that's unique to it, and is
 /// stored in the `$tp` register. If this register is 0, then
 /// TLS has not been initialized and thread cleanup can be skipped.
 ///
-/// The index into this register is the `key`. This key is identical
-/// between all threads, but indexes a different offset within this
-/// pointer.
+/// The block starts with a page of TLS keys, and `$tp` points just past
+/// it, at the thread's copy of the program's `#[thread_local]` statics,
+/// which is where the code for them expects it. `thread_local!` uses those
+/// statics, and keys are left for the destructors it registers.
+///
+/// Currently, we are limited to 1023 TLS entries. The index into the key
+/// page is the `key`. This key is identical between all threads, but
+/// indexes a different offset within each thread's page.
+///
+/// Threads started by the standard library, and the main thread, have the
+/// block set up before anything else runs. A thread started some other way,
+/// such as with `xous::create_thread`, starts with a `$tp` of 0, and gets
+/// the block the first time it uses a key or a `thread_local!`, which checks
+/// for it first (see `ensure_tls`).
 
 pub type Key = usize;
 pub type Dtor = unsafe extern "C" fn(*mut u8);
 
 const TLS_MEMORY_SIZE: usize = 4096;
 
+// The target's link script puts these around the program's `.tdata` and
+// `.tbss`, which are the template every thread's copy is made from: the
+// initial values, followed by the statics that start out zeroed.
+extern "C" {
+    static __tdata_start: u8;
+    static __tdata_end: u8;
+    static __tbss_end: u8;
+}
+
+/// Returns the initial values of the `#[thread_local]` statics, and the
+/// length of the whole copy of them each thread needs.
+fn tls_template() -> (&'static [u8], usize) {
+    let start = unsafe { ptr::addr_of!(__tdata_start) };
+    let data_len = unsafe { ptr::addr_of!(__tdata_end) } as usize - start as usize;
+    let len = unsafe { ptr::addr_of!(__tbss_end) } as usize - start as usize;
+    (unsafe { crate::slice::from_raw_parts(start, data_len) }, len)
+}
+
+/// The length of a thread's block: the key page, then its statics, rounded
+/// up to whole pages.
+fn tls_memory_len(statics_len: usize) -> usize {
+    TLS_MEMORY_SIZE + (statics_len + TLS_MEMORY_SIZE - 1) / TLS_MEMORY_SIZE * TLS_MEMORY_SIZE
+}
+
 /// TLS keys start at `1` to mimic POSIX.
 static TLS_KEY_INDEX: AtomicUsize = AtomicUsize::new(1);
 
@@ -34,25 +71,36 @@ fn tls_ptr_addr() -> usize {
     tp
 }
 
-/// Create an area of memory that's unique per thread. This area will
-/// contain all thread local pointers.
-fn tls_ptr() -> *mut usize {
+/// Create an area of memory that's unique per thread, if the current thread
+/// doesn't have one yet, and return its `$tp`. This area will contain all
+/// thread local pointers, followed by the thread's own copy of the
+/// `#[thread_local]` statics.
+///
+/// This has to run before a thread uses any of those statics, which is
+/// nearly anything in the standard library, so it is called on its own at
+/// the very start of every thread the standard library starts.
+pub fn init_tls() -> usize {
     let mut tp = tls_ptr_addr();
 
     // If the TP register is `0`, then this thread hasn't initialized
-    // its TLS yet. Allocate a new page to store this memory.
+    // its TLS yet. Allocate new pages to store this memory.
     if tp == 0 {
+        let (data, statics_len) = tls_template();
         let syscall = xous::SysCall::MapMemory(
             None,
             None,
-            xous::MemorySize::new(TLS_MEMORY_SIZE).unwrap(),
+            xous::MemorySize::new(tls_memory_len(statics_len)).unwrap(),
             xous::MemoryFlags::R | xous::MemoryFlags::W,
         );
         if let Ok(xous::Result::MemoryRange(mem)) = xous::rsyscall(syscall) {
-            tp = mem.as_ptr() as usize;
+            tp = mem.as_ptr() as usize + TLS_MEMORY_SIZE;
             unsafe {
                 // Key #0 is currently unused.
-                (tp as *mut usize).write_volatile(0);
+                (mem.as_ptr() as *mut usize).write_volatile(0);
+
+                // Freshly mapped memory is zeroed, so only the statics
+                // with initial values need copying.
+                ptr::copy_nonoverlapping(data.as_ptr(), tp as *mut u8, data.len());
 
                 // Set the thread's `$tp` register
                 asm!(
@@ -64,7 +112,25 @@ fn tls_ptr() -> *mut usize {
             panic!("Unable to allocate memory for thread local storage");
         }
     }
-    tp as *mut usize
+    tp
+}
+
+/// Sets up TLS for the current thread if it doesn't have it yet, because
+/// something other than the standard library started it. `thread_local!`
+/// calls this before touching its `#[thread_local]` statics, which are found
+/// through `$tp` and would otherwise be read from near address 0.
+#[unstable(feature = "thread_local_internals", issue = "none")]
+#[inline]
+pub fn ensure_tls() {
+    if tls_ptr_addr() == 0 {
+        init_tls();
+    }
+}
+
+/// Returns the current thread's key page, setting up its TLS first if
+/// need be.
+fn tls_ptr() -> *mut usize {
+    (init_tls() - TLS_MEMORY_SIZE) as *mut usize
 }
 
 /// Allocate a new TLS key. These keys are shared among all threads.
@@ -159,13 +225,18 @@ pub unsafe fn destroy_tls() {
     }
     unsafe { run_dtors() };
 
-    // Finally, free the TLS array
-    let tls_memory = unsafe { xous::MemoryRange::new(tp, TLS_MEMORY_SIZE).unwrap() };
+    // Finally, free the TLS array, along with the thread's statics
+    let (_, statics_len) = tls_template();
+    let tls_memory = unsafe {
+        xous::MemoryRange::new(tp - TLS_MEMORY_SIZE, tls_memory_len(statics_len)).unwrap()
+    };
     let syscall = xous::SysCall::UnmapMemory(tls_memory);
     xous::rsyscall(syscall).unwrap();
 }
 
-unsafe fn run_dtors() {
+/// Runs the destructors of the current thread's thread-locals, without
+/// releasing its TLS block.
+pub unsafe fn run_dtors() {
     let mut any_run = true;
     for _ in 0..5 {
         if !any_run {
@@ -0,0 +1,28 @@
+use super::tls_ptr_addr;
+use crate::cell::{Cell, RefCell};
+
+thread_local! {
+    static COUNT: Cell<u32> = const { Cell::new(7) };
+    static NAMES: RefCell<Vec<&'static str>> = RefCell::new(vec!["first"]);
+}
+
+/// Runs on a thread started by the kernel rather than the standard library,
+/// which has no TLS until it first uses a thread-local.
+fn foreign_thread() -> usize {
+    assert_eq!(tls_ptr_addr(), 0);
+    COUNT.with(|count| count.set(count.get() + 1));
+    assert_ne!(tls_ptr_addr(), 0);
+    NAMES.with(|names| names.borrow_mut().push("second"));
+    let names = NAMES.with(|names| names.borrow().clone());
+    assert_eq!(names, ["first", "second"]);
+    COUNT.with(|count| count.get()) as usize
+}
+
+#[test]
+fn thread_locals_on_foreign_threads() {
+    let thread = xous::create_thread_0(foreign_thread).unwrap();
+    assert_eq!(xous::wait_thread(thread).unwrap(), 8);
+    // The thread had its own copies.
+    assert_eq!(COUNT.with(|count| count.get()), 7);
+    assert_eq!(NAMES.with(|names| names.borrow().len()), 1);
+}
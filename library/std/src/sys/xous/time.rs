@@ -1,5 +1,7 @@
+use crate::io;
+use crate::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use crate::sys::services::{self, rtc, ticktimer_scalar};
 use crate::time::Duration;
-use crate::sys::services::{ticktimer, systime};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub struct Instant(Duration);
@@ -9,26 +11,159 @@
 
 pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::from_secs(0));
 
+/// Whether `SystemTime::now` has reported failing to read the wall clock.
+static CLOCK_FAILURE_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Which ticktimer call backs `Instant`. Older ticktimers only count
+/// milliseconds and don't understand `ElapsedNs`, in which case we fall back
+/// to `ElapsedMs`. The choice is made once, on the first answer from the
+/// ticktimer, so that the resolution of `Instant` never changes under a
+/// running program. A call that fails to reach the ticktimer at all doesn't
+/// tell us which it is, so that call falls back, and the next one asks again.
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(SOURCE_UNKNOWN);
+const SOURCE_UNKNOWN: u8 = 0;
+const SOURCE_NS: u8 = 1;
+const SOURCE_MS: u8 = 2;
+
+fn elapsed_ns() -> Option<u64> {
+    let source = CLOCK_SOURCE.load(Ordering::Relaxed);
+    if source == SOURCE_MS {
+        return None;
+    }
+    match ticktimer_scalar(16 /* ElapsedNs */, 0, 0, 0, 0) {
+        Ok(xous::Result::Scalar2(lower, upper)) => {
+            CLOCK_SOURCE.store(SOURCE_NS, Ordering::Relaxed);
+            Some(lower as u64 | (upper as u64) << 32)
+        }
+        Ok(_) if source == SOURCE_NS => {
+            panic!("Ticktimer: incorrect response when requesting elapsed_ns")
+        }
+        Ok(_) => {
+            CLOCK_SOURCE.store(SOURCE_MS, Ordering::Relaxed);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+fn elapsed_ms() -> u64 {
+    match ticktimer_scalar(0 /* ElapsedMs */, 0, 0, 0, 0)
+        .expect("Ticktimer: failure to request elapsed_ms")
+    {
+        xous::Result::Scalar2(lower, upper) => lower as u64 | (upper as u64) << 32,
+        _ => panic!("Ticktimer: incorrect response when requesting elapsed_ms")
+    }
+}
+
+/// Whether the ticktimer keeps a count of the time the device has been awake,
+/// which stops while it is suspended. Ticktimers that predate `ElapsedAwakeNs`
+/// only keep the count that includes suspends, in which case `Instant` falls
+/// back to that, less whatever `note_resume` has been told. Like
+/// `CLOCK_SOURCE`, this is decided once, on the first answer from the
+/// ticktimer.
+static AWAKE_CLOCK: AtomicU8 = AtomicU8::new(AWAKE_UNKNOWN);
+const AWAKE_UNKNOWN: u8 = 0;
+const AWAKE_SUPPORTED: u8 = 1;
+const AWAKE_UNSUPPORTED: u8 = 2;
+
+/// The time the device has been awake in nanoseconds, if the ticktimer
+/// counts it.
+fn elapsed_awake_ns() -> Option<u64> {
+    let support = AWAKE_CLOCK.load(Ordering::Relaxed);
+    if support == AWAKE_UNSUPPORTED {
+        return None;
+    }
+    match ticktimer_scalar(17 /* ElapsedAwakeNs */, 0, 0, 0, 0) {
+        Ok(xous::Result::Scalar2(lower, upper)) => {
+            AWAKE_CLOCK.store(AWAKE_SUPPORTED, Ordering::Relaxed);
+            Some(lower as u64 | (upper as u64) << 32)
+        }
+        // The other count doesn't leave out suspends, so switching to it now
+        // would make `Instant` jump.
+        _ if support == AWAKE_SUPPORTED => panic!("Ticktimer: failure to request elapsed_awake_ns"),
+        Ok(_) => {
+            AWAKE_CLOCK.store(AWAKE_UNSUPPORTED, Ordering::Relaxed);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Time the device has spent suspended that `Instant` still has to leave
+/// out, in nanoseconds, as reported through `note_resume`. Like
+/// `CLOCK_MONOTONIC`, `Instant` doesn't count suspends, so that timeouts
+/// measured across one neither fire early nor stall.
+static SUSPENDED_NS: AtomicU64 = AtomicU64::new(0);
+
+/// The latest time handed out by `Instant::now`, in nanoseconds. Used to keep
+/// `Instant` from ever going backwards.
+static LAST_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Records that the device has just resumed after being suspended for
+/// `suspended`. Time after this call is measured as though the suspend never
+/// happened; `Instant::now` still never decreases, even if the reported
+/// duration overshoots.
+///
+/// The awake count already leaves suspends out, so this is only needed with
+/// ticktimers that don't keep one, or to simulate a suspend.
+pub fn note_resume(suspended: Duration) {
+    let ns = suspended.as_nanos().min(u64::MAX as u128) as u64;
+    SUSPENDED_NS.fetch_add(ns, Ordering::SeqCst);
+}
+
+/// The ticktimer's count in nanoseconds, including any time spent suspended.
+/// A `u64` worth of nanoseconds covers over 500 years of uptime.
+fn raw_elapsed_ns() -> u64 {
+    match elapsed_ns() {
+        Some(ns) => ns,
+        None => elapsed_ms().saturating_mul(1_000_000),
+    }
+}
+
+/// The earliest wall-clock offset believed, in seconds since the UNIX epoch,
+/// which is the start of 2020. An RTC that has lost power, for example
+/// because the backup battery was removed, starts counting from zero again,
+/// which puts the offset decades before this.
+const EARLIEST_OFFSET_SECS: u64 = 1_577_836_800;
+
+/// Asks the time server for the wall-clock offset: the number of milliseconds
+/// between the UNIX epoch and the point where the ticktimer started counting.
+/// Fails if the time server can't be asked, or if the offset it gives is
+/// before `EARLIEST_OFFSET_SECS`, which is how an RTC that has never been set
+/// shows up.
+///
+/// Setting the clock, or NTP adjusting it, only ever changes this offset. The
+/// ticktimer itself is never touched.
+pub fn utc_offset_ms() -> io::Result<u64> {
+    let offset = match services::send(
+        rtc(),
+        xous::Message::new_blocking_scalar(6 /* GetUtcOffsetMs */, 0, 0, 0, 0),
+    )? {
+        xous::Result::Scalar2(upper, lower) => (upper as u64) << 32 | lower as u64,
+        _ => {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                &"unexpected reply from the time server",
+            ));
+        }
+    };
+    if offset / 1000 < EARLIEST_OFFSET_SECS {
+        return Err(io::const_io_error!(
+            io::ErrorKind::NotFound,
+            &"the real-time clock has not been set",
+        ));
+    }
+    Ok(offset)
+}
+
 impl Instant {
     pub fn now() -> Instant {
-        match xous::send_message(
-            ticktimer(),
-            xous::Message::new_blocking_scalar(
-                0, /* ElapsedMs */
-                0,
-                0,
-                0,
-                0,
-            ),
-        )
-        .expect("Ticktimer: failure to request elapsed_ms") {
-            xous::Result::Scalar2(lower, upper) => {
-                Instant {
-                    0: Duration::from_millis(lower as u64 | (upper as u64) << 32)
-                }
-            }
-            _ => panic!("Ticktimer: incorrect response when requesting elapsed_ms")
-        }
+        // `Duration` arithmetic on the result cannot overflow before the
+        // ticktimer itself does.
+        let awake = elapsed_awake_ns().unwrap_or_else(raw_elapsed_ns);
+        let adjusted = awake.saturating_sub(SUSPENDED_NS.load(Ordering::SeqCst));
+        let last = LAST_NS.fetch_max(adjusted, Ordering::SeqCst);
+        Instant(Duration::from_nanos(adjusted.max(last)))
     }
 
     pub fn checked_sub_instant(&self, other: &Instant) -> Option<Duration> {
@@ -45,24 +180,31 @@ pub fn checked_sub_duration(&self, other: &Duration) -> Option<Instant> {
 }
 
 impl SystemTime {
+    /// Reads the wall clock as the ticktimer's count (the base) plus the
+    /// wall-clock offset kept by the time server, which fronts the RTC.
+    ///
+    /// Because the base is monotonic and clock adjustments only move the
+    /// offset, each call sees one consistent wall clock. Two calls that
+    /// straddle an adjustment may still go backwards; `sub_time` then reports
+    /// an `Err` rather than misbehaving.
+    ///
+    /// If the offset can't be read, or the RTC has never been set (see
+    /// `utc_offset_ms`), the clock reads `UNIX_EPOCH`, so that
+    /// `duration_since(UNIX_EPOCH)` returns zero rather than a made-up time.
+    /// The first such failure is reported on the panic output, since there
+    /// is no other way to tell that the time is wrong.
     pub fn now() -> SystemTime {
-        match xous::send_message(
-            systime(),
-            xous::Message::new_blocking_scalar(
-                3, /* GetUtcTimeMs */
-                0,
-                0,
-                0,
-                0,
-            ),
-        )
-        .expect("Systime: failure to request UTC time in ms") {
-            xous::Result::Scalar2(upper, lower) => {
-                SystemTime {
-                    0: Duration::from_millis((upper as u64) << 32 | lower as u64)
+        match utc_offset_ms() {
+            Ok(offset) => {
+                let base = Duration::from_nanos(raw_elapsed_ns());
+                SystemTime(Duration::from_millis(offset).saturating_add(base))
+            }
+            Err(e) => {
+                if !CLOCK_FAILURE_REPORTED.swap(true, Ordering::Relaxed) {
+                    rtprintpanic!("failed to read the wall clock, using UNIX_EPOCH: {}\n", e);
                 }
+                UNIX_EPOCH
             }
-            _ => panic!("Ticktimer: incorrect response when requesting elapsed_ms")
         }
     }
 
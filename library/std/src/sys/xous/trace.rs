@@ -0,0 +1,102 @@
+//! Tracing of the messages the standard library sends to servers, for
+//! debugging the conversation between the two. Only built with the
+//! `xous-trace-ipc` feature; without it, nothing calls into here.
+//!
+//! Every message sent through `services` gives one line naming the server,
+//! the kind of message, its opcode, how many bytes it carried, how long the
+//! server took and what it answered:
+//!
+//! ```text
+//! ipc: _xous_std_network lend_mut 30 4096B 1.2ms -> MemoryReturned
+//! ```
+//!
+//! Only the size of what a message carries is traced, never its contents,
+//! which may be private, such as keys and data on their way to the PDDB.
+//!
+//! Lines are lent straight to the log server rather than written to stderr,
+//! so tracing never goes through the stdio it may be tracing, and never waits
+//! for a thread that is in the middle of printing.
+
+use super::page_pool::{self, PageBuf};
+use super::services::{log_server, name_of};
+use crate::fmt::{self, Write};
+use crate::time::Instant;
+
+/// A message on its way to a server.
+pub struct Trace {
+    service: &'static str,
+    kind: &'static str,
+    opcode: usize,
+    len: usize,
+    start: Instant,
+}
+
+/// Starts tracing `message`, which is about to be sent over `cid`.
+pub fn start(cid: xous::CID, message: &xous::Message) -> Trace {
+    let memory = |kind, m: &xous::MemoryMessage| {
+        (kind, m.id, m.valid.map_or(m.buf.len(), |valid| valid.get()))
+    };
+    let (kind, opcode, len) = match message {
+        xous::Message::MutableBorrow(m) => memory("lend_mut", m),
+        xous::Message::Borrow(m) => memory("lend", m),
+        xous::Message::Move(m) => memory("move", m),
+        xous::Message::Scalar(m) => ("scalar", m.id, 0),
+        xous::Message::BlockingScalar(m) => ("blocking_scalar", m.id, 0),
+    };
+    Trace { service: name_of(cid).unwrap_or("?"), kind, opcode, len, start: Instant::now() }
+}
+
+impl Trace {
+    /// Finishes tracing the message with the server's answer, and writes
+    /// out its line.
+    pub fn finish(self, result: &Result<xous::Result, xous::Error>) {
+        let elapsed = self.start.elapsed();
+        let mut line = Line { page: page_pool::get(), len: 0 };
+        let _ = write!(
+            line,
+            "ipc: {} {} {} {}B {:?} -> ",
+            self.service, self.kind, self.opcode, self.len, elapsed
+        );
+        let _ = match result {
+            Ok(xous::Result::Ok) => line.write_str("Ok"),
+            Ok(xous::Result::Scalar1(..)) => line.write_str("Scalar1"),
+            Ok(xous::Result::Scalar2(..)) => line.write_str("Scalar2"),
+            Ok(xous::Result::Scalar5(..)) => line.write_str("Scalar5"),
+            Ok(xous::Result::MemoryReturned(..)) => line.write_str("MemoryReturned"),
+            Ok(xous::Result::Error(e)) => write!(line, "Error({:?})", e),
+            Ok(_) => line.write_str("another result"),
+            Err(e) => write!(line, "send failed: {:?}", e),
+        };
+        line.send();
+    }
+}
+
+/// A line of trace output, in a page ready to lend to the log server.
+/// Anything that doesn't fit is cut off.
+struct Line {
+    page: PageBuf,
+    len: usize,
+}
+
+impl Line {
+    fn send(mut self) {
+        let message = xous::Message::new_lend(
+            2, /* StandardError */
+            self.page.range(),
+            xous::MemoryAddress::new(0),
+            xous::MemorySize::new(self.len),
+        );
+        // Nothing is done about a line that can't be sent: the log server is
+        // also where the failure would be reported.
+        xous::send_message(log_server(), message).ok();
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(self.page.len() - self.len);
+        self.page[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
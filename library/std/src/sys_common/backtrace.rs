@@ -32,10 +32,16 @@ pub fn print(w: &mut dyn Write, format: PrintFmt) -> io::Result<()> {
     // Some platforms also requires it, like `SymFromAddr` on Windows.
     unsafe {
         let _lock = lock();
-        _print(w, format)
+        // Xous walks frame pointers, and has nothing to symbolize with.
+        #[cfg(target_os = "xous")]
+        let result = crate::sys::backtrace::print(w, format);
+        #[cfg(not(target_os = "xous"))]
+        let result = _print(w, format);
+        result
     }
 }
 
+#[cfg_attr(target_os = "xous", allow(dead_code))]
 unsafe fn _print(w: &mut dyn Write, format: PrintFmt) -> io::Result<()> {
     struct DisplayBacktrace {
         format: PrintFmt,
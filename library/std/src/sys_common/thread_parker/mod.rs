@@ -2,6 +2,7 @@
     if #[cfg(any(
         target_os = "linux",
         target_os = "android",
+        target_os = "xous",
         all(target_arch = "wasm32", target_feature = "atomics"),
     ))] {
         mod futex;
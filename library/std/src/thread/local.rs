@@ -202,6 +202,11 @@ unsafe fn __getit(
                 not(all(target_family = "wasm", not(target_feature = "atomics"))),
             ))]
             {
+                // Threads that Xous started without the standard library
+                // have nowhere for `#[thread_local]` statics until this.
+                #[cfg(target_os = "xous")]
+                $crate::thread::__xous_ensure_tls();
+
                 #[thread_local]
                 static mut VAL: $t = INIT_EXPR;
 
@@ -334,6 +339,11 @@ unsafe fn __getit(
                 static __KEY: $crate::thread::__OsLocalKeyInner<$t> =
                     $crate::thread::__OsLocalKeyInner::new();
 
+                // Threads that Xous started without the standard library
+                // have nowhere for `#[thread_local]` statics until this.
+                #[cfg(all(target_os = "xous", target_thread_local))]
+                $crate::thread::__xous_ensure_tls();
+
                 // FIXME: remove the #[allow(...)] marker when macros don't
                 // raise warning for missing/extraneous unsafe blocks anymore.
                 // See https://github.com/rust-lang/rust/issues/74838.
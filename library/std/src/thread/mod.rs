@@ -209,6 +209,10 @@
 #[cfg(all(target_family = "wasm", not(target_feature = "atomics")))]
 #[doc(hidden)]
 pub use self::local::statik::Key as __StaticLocalKeyInner;
+#[unstable(feature = "libstd_thread_internals", issue = "none")]
+#[cfg(target_os = "xous")]
+#[doc(hidden)]
+pub use crate::sys::thread_local_key::ensure_tls as __xous_ensure_tls;
 
 ////////////////////////////////////////////////////////////////////////////////
 // Builder
@@ -0,0 +1,47 @@
+//! Checks that arguments reach a child process exactly as they were given.
+//! This runs without the test harness, since the harness would take the
+//! child's arguments as its own options.
+//!
+//! The test starts itself again as a child, which writes each argument it
+//! got back over a pipe, with its length in front of it.
+
+#[cfg(target_os = "xous")]
+fn main() {
+    use std::env;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const CHILD: &str = "XOUS_ARGS_CHILD";
+
+    if env::var_os(CHILD).is_some() {
+        let mut out = Vec::new();
+        for arg in env::args_os().skip(1) {
+            let arg = arg.into_string().unwrap();
+            out.extend_from_slice(&(arg.len() as u32).to_le_bytes());
+            out.extend_from_slice(arg.as_bytes());
+        }
+        std::io::stdout().write_all(&out).unwrap();
+        return;
+    }
+
+    let args = ["", "two words", "\"double\" and 'single' quotes", "line\nbreak", "nul\0byte", "-"];
+    let output = Command::new(env::current_exe().unwrap())
+        .args(args)
+        .env(CHILD, "1")
+        .stdout(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut echoed = Vec::new();
+    let mut rest = &output.stdout[..];
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+        echoed.push(String::from_utf8(rest[4..4 + len].to_vec()).unwrap());
+        rest = &rest[4 + len..];
+    }
+    assert_eq!(echoed, args);
+}
+
+#[cfg(not(target_os = "xous"))]
+fn main() {}
@@ -0,0 +1,46 @@
+//! Checks the order a Xous program winds down in once `main` returns (see
+//! `_start` in `sys/xous/common.rs`). This runs without the test harness,
+//! since the harness runs tests on threads of their own, and it is the main
+//! thread's own thread-locals that are dropped last.
+//!
+//! The test starts itself again as a child, which leaves output in stdout's
+//! buffer, prints again from a thread-local's destructor, and returns an
+//! exit code. The buffered output has to come first, since the runtime
+//! cleanup flushes it before thread-locals are dropped, and the exit code
+//! has to survive both.
+
+#[cfg(target_os = "xous")]
+fn main() -> std::process::ExitCode {
+    use std::env;
+    use std::process::{Command, ExitCode, Stdio};
+
+    const CHILD: &str = "XOUS_EXIT_CHILD";
+
+    struct PrintOnDrop;
+
+    impl Drop for PrintOnDrop {
+        fn drop(&mut self) {
+            print!("thread-local dropped");
+        }
+    }
+
+    thread_local! { static LOCAL: PrintOnDrop = PrintOnDrop }
+
+    if env::var_os(CHILD).is_some() {
+        LOCAL.with(|_| {});
+        print!("main returned, ");
+        return ExitCode::from(3);
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env(CHILD, "1")
+        .stdout(Stdio::piped())
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "main returned, thread-local dropped");
+    assert_eq!(output.status.code(), Some(3));
+    ExitCode::SUCCESS
+}
+
+#[cfg(not(target_os = "xous"))]
+fn main() {}
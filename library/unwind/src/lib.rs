@@ -23,6 +23,7 @@
         windows,
         target_os = "psp",
         target_os = "solid_asp3",
+        target_os = "xous",
         all(target_vendor = "fortanix", target_env = "sgx"),
     ))] {
         mod libunwind;
@@ -86,3 +87,7 @@
 #[cfg(all(target_vendor = "fortanix", target_env = "sgx"))]
 #[link(name = "unwind", kind = "static", modifiers = "-bundle")]
 extern "C" {}
+
+#[cfg(target_os = "xous")]
+#[link(name = "unwind", kind = "static", modifiers = "-bundle")]
+extern "C" {}
@@ -1,7 +1,16 @@
 #![allow(nonstandard_style)]
 
+#[cfg(not(target_os = "xous"))]
 use libc::{c_int, c_void, uintptr_t};
 
+// The libc crate has nothing for Xous, which has no C library.
+#[cfg(target_os = "xous")]
+use core::ffi::c_void;
+#[cfg(target_os = "xous")]
+type c_int = i32;
+#[cfg(target_os = "xous")]
+type uintptr_t = usize;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum _Unwind_Reason_Code {
@@ -0,0 +1,36 @@
+/*
+ * The part of pthreads that libunwind uses, for Xous, which has no C library.
+ * libunwind takes a read-write lock around its cache of frame descriptions,
+ * so that several threads can unwind at once.
+ *
+ * The locks are the standard library's `RWLock`, whose functions for C are in
+ * library/std/src/sys/xous/locks/rwlock.rs. A lock is a single `u32`, zero
+ * when unlocked, which a test there checks.
+ */
+
+#ifndef XOUS_PTHREAD_H
+#define XOUS_PTHREAD_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct {
+    unsigned int mode;
+} pthread_rwlock_t;
+
+#define PTHREAD_RWLOCK_INITIALIZER {0}
+
+int __rust_rwlock_rdlock(pthread_rwlock_t *rwlock);
+int __rust_rwlock_wrlock(pthread_rwlock_t *rwlock);
+int __rust_rwlock_unlock(pthread_rwlock_t *rwlock);
+
+#define pthread_rwlock_rdlock __rust_rwlock_rdlock
+#define pthread_rwlock_wrlock __rust_rwlock_wrlock
+#define pthread_rwlock_unlock __rust_rwlock_unlock
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* XOUS_PTHREAD_H */
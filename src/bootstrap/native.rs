@@ -1088,6 +1088,19 @@ fn run(self, builder: &Builder<'_>) -> Self::Output {
                 cfg.define("__LIBUNWIND_IS_NATIVE_ONLY", None);
                 cfg.define("NDEBUG", None);
             }
+
+            if self.target.contains("xous") {
+                // Xous has no C library or pthreads. Threads may unwind at
+                // the same time, so the unwinder's locks are the standard
+                // library's, through a `pthread.h` of its own.
+                cfg.static_flag(true);
+                cfg.flag("-fno-stack-protector");
+                cfg.flag("-ffreestanding");
+                cfg.flag("-fexceptions");
+                cfg.include(builder.src.join("library/unwind/xous"));
+                cfg.define("_LIBUNWIND_IS_BAREMETAL", None);
+                cfg.define("NDEBUG", None);
+            }
         }
 
         cc_cfg.compiler(builder.cc(self.target));